@@ -1,11 +1,18 @@
-#![feature(test)]
-extern crate test;
-use test::Bencher;
+use std::hint::black_box;
+use std::time::Instant;
 // extern crate mancala_ai;
 
 const SIZE: usize = 1024;
+const ITERS: u32 = 10_000;
 
-#[bench]
-fn alloc(bench: &mut Bencher) {
-    bench.iter(|| (0..SIZE).map(|_| 0u8).collect::<Vec<_>>())
+fn alloc() {
+    let start = Instant::now();
+    for _ in 0..ITERS {
+        black_box((0..SIZE).map(|_| 0u8).collect::<Vec<_>>());
+    }
+    println!("alloc: {:?}/iter", start.elapsed() / ITERS);
+}
+
+fn main() {
+    alloc();
 }
@@ -0,0 +1,183 @@
+use crate::engine::{self, Evaluation};
+use crate::mancala::{GameState, ValueTable};
+use crate::packed_actions::Action;
+use crate::search;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Deepest search after each move, in turns.
+pub const MAX_ANALYSIS_DEPTH: u32 = 8;
+/// How long the moves of a position are searched before the analysis settles for what it has.
+const ANALYSIS_TIME: Duration = Duration::from_secs(10);
+
+/// What searching on from a candidate move found.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LineAnalysis {
+    /// Turns searched after the move.
+    pub depth: u32,
+    /// The move's score for the player making it, as `engine::score_afterstate` scores.
+    pub score: f64,
+    /// The best play after the move, starting with the opponent's reply.
+    pub line: Vec<Action>,
+}
+
+/// The moves of a position being searched in the background, a turn deeper each round, with
+/// results coming in as each search finishes. Dropping it stops the search thread.
+pub struct Analysis {
+    pub evaluation: Evaluation,
+    results: Receiver<(Action, LineAnalysis)>,
+    /// Set to call off the search.
+    stop: Arc<AtomicBool>,
+    lines: HashMap<Action, LineAnalysis>,
+    moves: usize,
+    finished: bool,
+}
+
+impl Analysis {
+    /// Start searching the moves of `position`, scoring what the searches reach by `evaluation`.
+    pub fn start(
+        position: GameState,
+        table: Arc<dyn ValueTable>,
+        evaluation: Evaluation,
+    ) -> Analysis {
+        let actions: Vec<Action> = position.gen_actions().collect();
+        let moves = actions.len();
+        let (sender, results) = mpsc::channel();
+        let stop = Arc::new(AtomicBool::new(false));
+        let stopped = Arc::clone(&stop);
+        thread::spawn(move || {
+            let deadline = Instant::now() + ANALYSIS_TIME;
+            let evaluate =
+                |state: &GameState| engine::score_afterstate(table.as_ref(), evaluation, state);
+            for depth in 1..=MAX_ANALYSIS_DEPTH {
+                for &action in &actions {
+                    let mut after = position;
+                    after.evaluate_action(action);
+                    let line = if after.is_ended() {
+                        LineAnalysis {
+                            depth,
+                            score: evaluate(&after),
+                            line: Vec::new(),
+                        }
+                    } else {
+                        after.swap_board();
+                        let Some(result) = search::alpha_beta_until(
+                            &after,
+                            depth,
+                            &evaluate,
+                            Some(deadline),
+                            Some(&stopped),
+                        ) else {
+                            return;
+                        };
+                        LineAnalysis {
+                            depth,
+                            score: -result.score,
+                            line: result.pv,
+                        }
+                    };
+                    // Nobody is looking any more
+                    if sender.send((action, line)).is_err() {
+                        return;
+                    }
+                }
+            }
+        });
+        Analysis {
+            evaluation,
+            results,
+            stop,
+            lines: HashMap::new(),
+            moves,
+            finished: false,
+        }
+    }
+
+    /// Take in the searches finished since last time, returning whether there were any.
+    pub fn update(&mut self) -> bool {
+        let mut changed = false;
+        loop {
+            match self.results.try_recv() {
+                Ok((action, line)) => {
+                    self.lines.insert(action, line);
+                    changed = true;
+                }
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    self.finished = true;
+                    break;
+                }
+            }
+        }
+        changed
+    }
+
+    /// The deepest search finished for `action`.
+    pub fn line(&self, action: Action) -> Option<&LineAnalysis> {
+        self.lines.get(&action)
+    }
+
+    /// How many turns deep every move has been searched.
+    pub fn depth(&self) -> u32 {
+        if self.lines.len() < self.moves {
+            return 0;
+        }
+        self.lines
+            .values()
+            .map(|line| line.depth)
+            .min()
+            .unwrap_or(0)
+    }
+
+    /// Whether the search has gone as deep as it will.
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+}
+
+impl Drop for Analysis {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mancala::ValueFunction;
+    use crate::search::store_margin;
+
+    #[test]
+    fn test_analysis() {
+        let position = GameState::new(4);
+        let mut analysis =
+            Analysis::start(position, Arc::new(ValueFunction::new()), Evaluation::Margin);
+        let start = Instant::now();
+        while analysis.depth() < 2 && start.elapsed() < Duration::from_secs(5) {
+            analysis.update();
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert!(analysis.depth() >= 2);
+
+        // Each move's line starts with the reply the search expects, as searching for the
+        // opponent would find it
+        for action in position.gen_actions() {
+            let line = analysis.line(action).unwrap();
+            let mut after = position;
+            after.evaluate_action(action);
+            after.swap_board();
+            let reply = search::alpha_beta(&after, line.depth, &store_margin);
+            assert_eq!(line.score, -reply.score);
+            assert_eq!(line.line, reply.pv);
+        }
+
+        // Letting go of the analysis calls off the search under way
+        let stop = Arc::clone(&analysis.stop);
+        drop(analysis);
+        assert!(stop.load(Ordering::Relaxed));
+    }
+}
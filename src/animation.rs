@@ -0,0 +1,234 @@
+use crate::mancala::GameState;
+use crate::packed_actions::{Action, ActionQueue};
+use std::time::{Duration, Instant};
+
+/// How fast moves are shown being sown on the board, if at all.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AnimationSpeed {
+    Off,
+    Fast,
+    Normal,
+    Slow,
+}
+
+pub const ANIMATION_SPEEDS: [AnimationSpeed; 4] = [
+    AnimationSpeed::Off,
+    AnimationSpeed::Fast,
+    AnimationSpeed::Normal,
+    AnimationSpeed::Slow,
+];
+
+impl AnimationSpeed {
+    /// How long each step stays on the board; `None` when moves are not animated.
+    pub fn step(&self) -> Option<Duration> {
+        match self {
+            AnimationSpeed::Off => None,
+            AnimationSpeed::Fast => Some(Duration::from_millis(80)),
+            AnimationSpeed::Normal => Some(Duration::from_millis(200)),
+            AnimationSpeed::Slow => Some(Duration::from_millis(500)),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            AnimationSpeed::Off => "Off",
+            AnimationSpeed::Fast => "Fast",
+            AnimationSpeed::Normal => "Normal",
+            AnimationSpeed::Slow => "Slow",
+        }
+    }
+}
+
+/// What happened to a house in a step of a sowing.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Mark {
+    /// The seeds were picked up from here.
+    Lifted,
+    /// A seed was just dropped here.
+    Dropped,
+    /// Its seeds were taken in a capture.
+    Captured,
+}
+
+/// One step of a move being made: the board at that point, the houses to pick out, and a line
+/// saying what is going on.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AnimationFrame {
+    pub board: GameState,
+    pub marks: Vec<(usize, Mark)>,
+    pub caption: String,
+}
+
+/// The board label of a house, as `draw_board` numbers them.
+fn house_name(house: usize) -> String {
+    match house {
+        6 | 13 => String::from("the store"),
+        0..=5 => format!("cell {}", house + 1),
+        _ => format!("cell {}", house),
+    }
+}
+
+/// The steps of `mover` playing `action` from `before`, which is seen from their side. With
+/// `flip` the frames show the board from the other side instead.
+pub fn frames(before: GameState, action: Action, mover: &str, flip: bool) -> Vec<AnimationFrame> {
+    let seen = |mut board: GameState| {
+        if flip {
+            board.swap_board();
+        }
+        board
+    };
+    let at = |house: usize| if flip { (house + 7) % 14 } else { house };
+    let mut frames = Vec::new();
+    let mut board = before;
+    let mut queue = action;
+    while !queue.is_empty() {
+        let subaction = queue.pop_front();
+        // Replay the drops one at a time on a copy of the board before the sowing
+        let mut sowing_board = board;
+        let sowing = board.sow(subaction);
+        let lifted = sowing_board.houses[sowing.house];
+        sowing_board.houses[sowing.house] = 0;
+        let from = at(sowing.house);
+        frames.push(AnimationFrame {
+            board: seen(sowing_board),
+            marks: vec![(from, Mark::Lifted)],
+            caption: format!(
+                "{}: {} seeds picked up from {}",
+                mover,
+                lifted,
+                house_name(from)
+            ),
+        });
+        for (sown, &house) in sowing.drops.iter().enumerate() {
+            sowing_board.houses[house] += 1;
+            let left = sowing.drops.len() - sown - 1;
+            frames.push(AnimationFrame {
+                board: seen(sowing_board),
+                marks: vec![(from, Mark::Lifted), (at(house), Mark::Dropped)],
+                caption: format!(
+                    "{}: sown into {} ({} left)",
+                    mover,
+                    house_name(at(house)),
+                    left
+                ),
+            });
+        }
+        if let Some((end, opposite, captured)) = sowing.capture {
+            frames.push(AnimationFrame {
+                board: seen(sowing_board),
+                marks: vec![(at(end), Mark::Captured), (at(opposite), Mark::Captured)],
+                caption: format!(
+                    "{}: {} seeds captured from {} and {}",
+                    mover,
+                    captured,
+                    house_name(at(end)),
+                    house_name(at(opposite))
+                ),
+            });
+            frames.push(AnimationFrame {
+                board: seen(board),
+                marks: vec![(at(6), Mark::Captured)],
+                caption: format!("{}: {} seeds banked", mover, captured),
+            });
+        }
+        if sowing.renewing {
+            frames.push(AnimationFrame {
+                board: seen(board),
+                marks: vec![(at(6), Mark::Dropped)],
+                caption: format!("{}: last seed in the store, sow again", mover),
+            });
+        }
+    }
+    frames
+}
+
+/// A move being played out on the board, a step at a time.
+pub struct Animation {
+    frames: Vec<AnimationFrame>,
+    shown: usize,
+    step: Duration,
+    last_step: Instant,
+    /// Who sits at the bottom and top of the board while it plays.
+    pub names: (&'static str, &'static str),
+}
+
+impl Animation {
+    pub fn new(
+        frames: Vec<AnimationFrame>,
+        names: (&'static str, &'static str),
+        step: Duration,
+    ) -> Animation {
+        Animation {
+            frames,
+            shown: 0,
+            step,
+            last_step: Instant::now(),
+            names,
+        }
+    }
+
+    pub fn frame(&self) -> &AnimationFrame {
+        &self.frames[self.shown.min(self.frames.len() - 1)]
+    }
+
+    /// Move on a step if one is due. False once the last step has had its time on the board.
+    pub fn tick(&mut self) -> bool {
+        if self.last_step.elapsed() >= self.step {
+            self.last_step = Instant::now();
+            self.shown += 1;
+        }
+        self.shown < self.frames.len()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::engine::parse_move;
+
+    #[test]
+    fn test_frames() {
+        // Cell 3 ends in the store, then cell 1 sows on
+        let start = GameState::new(4);
+        let action = parse_move(&start, "3-1").unwrap();
+        let shown = frames(start, action, "You", false);
+        assert_eq!(shown[0].marks, vec![(2, Mark::Lifted)]);
+        assert_eq!(shown[0].board.houses[2], 0);
+        assert_eq!(shown[4].marks, vec![(2, Mark::Lifted), (6, Mark::Dropped)]);
+        assert!(shown[5].caption.contains("sow again"));
+        let mut after = start;
+        after.evaluate_action(action);
+        assert_eq!(shown.last().unwrap().board, after);
+
+        // The same move made by the player across the board
+        let flipped = frames(start, action, "AI", true);
+        assert_eq!(flipped[0].marks, vec![(9, Mark::Lifted)]);
+        after.swap_board();
+        assert_eq!(flipped.last().unwrap().board, after);
+        assert_eq!(flipped.len(), shown.len());
+
+        // A capture picks out both houses, then the store
+        let mut start = GameState::new(4);
+        start.houses[4] = 0;
+        let shown = frames(start, Action::singleton(0), "You", false);
+        let capture = &shown[shown.len() - 2];
+        assert_eq!(
+            capture.marks,
+            vec![(4, Mark::Captured), (8, Mark::Captured)]
+        );
+        assert!(capture.caption.contains("5 seeds captured"));
+        assert_eq!(shown.last().unwrap().board.houses[6], 5);
+    }
+
+    #[test]
+    fn test_tick() {
+        let shown = frames(GameState::new(4), Action::singleton(0), "You", false);
+        let mut animation = Animation::new(shown, ("You", "AI"), Duration::ZERO);
+        // Picked up, four drops, then done
+        for _ in 0..4 {
+            assert!(animation.tick());
+        }
+        assert!(!animation.tick());
+        assert_eq!(animation.frame().board.houses[4], 5);
+    }
+}
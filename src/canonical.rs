@@ -0,0 +1,186 @@
+use crate::mancala::{GameState, ValueFunction, ValueTable, VisitCounts};
+use crate::player::DEFAULT_STATE_VAL;
+use std::collections::HashMap;
+use std::fmt::{self, Display, Formatter};
+
+/// Key an afterstate (a board seen by the player who has just moved, who owns `houses[0..7]`) is
+/// stored under. A finished game is worth the same whatever seeds were left on the board, so it
+/// is stored as the finalized board, with only the final scores left in the stores.
+pub fn key(state: &GameState) -> GameState {
+    if state.is_ended() {
+        let mut finalized = *state;
+        finalized.finalize_game();
+        finalized
+    } else {
+        *state
+    }
+}
+
+/// Key a position with its owner to move is stored under, and whether its stored value has to be
+/// flipped. Such a position is the opponent's afterstate with the board swapped, so it shares
+/// that entry with the value seen from the other side (`1 - v`). Finished games need no flip:
+/// their value does not depend on whose turn it is.
+pub fn to_move_key(state: &GameState) -> (GameState, bool) {
+    if state.is_ended() {
+        (key(state), false)
+    } else {
+        let mut swapped = *state;
+        swapped.swap_board();
+        (swapped, true)
+    }
+}
+
+/// Value of an afterstate for the player who just moved.
+pub fn value(values: &dyn ValueTable, state: &GameState) -> f64 {
+    values.get_value(&key(state)).unwrap_or(DEFAULT_STATE_VAL)
+}
+
+/// Value of a position for its owner, who is to move.
+pub fn value_to_move(values: &dyn ValueTable, state: &GameState) -> f64 {
+    let (key, flip) = to_move_key(state);
+    let value = values.get_value(&key).unwrap_or(DEFAULT_STATE_VAL);
+    if flip { 1.0 - value } else { value }
+}
+
+/// TD target for an afterstate followed by a position worth `q_next`. Values are discounted
+/// towards 0.5, an even game, rather than towards 0, so both players are discounted alike: seen
+/// from the other side, the target is still `1 - target`. With no discount this is plain
+/// `reward + q_next`.
+pub fn td_target(reward: f64, discount_factor: f64, q_next: f64) -> f64 {
+    reward + 0.5 + discount_factor * (q_next - 0.5)
+}
+
+/// How much canonicalizing a table shrank it.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ShrinkStats {
+    /// Entries before canonicalizing.
+    pub entries: usize,
+    /// How many of those were finished games.
+    pub terminal_entries: usize,
+    /// Entries after canonicalizing.
+    pub canonical_entries: usize,
+    /// How many of those are finished games.
+    pub terminal_keys: usize,
+}
+
+impl ShrinkStats {
+    pub fn is_unchanged(&self) -> bool {
+        self.entries == self.canonical_entries
+    }
+}
+
+impl Display for ShrinkStats {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let saved = self.entries - self.canonical_entries;
+        write!(
+            f,
+            "{} entries -> {} ({:.1}% smaller); {} finished games merged into {} final scores",
+            self.entries,
+            self.canonical_entries,
+            100.0 * saved as f64 / self.entries.max(1) as f64,
+            self.terminal_entries,
+            self.terminal_keys
+        )
+    }
+}
+
+/// Re-key a table stored without canonical keys, averaging the values of entries that merge.
+/// Every non-terminal entry is taken to be an afterstate, as lookups have always treated them.
+pub fn canonicalize_values(values: ValueFunction) -> (ValueFunction, ShrinkStats) {
+    let mut stats = ShrinkStats {
+        entries: values.len(),
+        ..ShrinkStats::default()
+    };
+    let mut merged: HashMap<GameState, (f64, usize)> = HashMap::with_capacity(values.len());
+    for (state, value) in values {
+        if state.is_ended() {
+            stats.terminal_entries += 1;
+        }
+        let entry = merged.entry(key(&state)).or_insert((0.0, 0));
+        entry.0 += value;
+        entry.1 += 1;
+    }
+    stats.canonical_entries = merged.len();
+    stats.terminal_keys = merged.keys().filter(|state| state.is_ended()).count();
+    let values = merged
+        .into_iter()
+        .map(|(state, (sum, count))| (state, sum / count as f64))
+        .collect();
+    (values, stats)
+}
+
+/// Re-key visit counts stored without canonical keys, adding up the counts that merge.
+pub fn canonicalize_visits(visits: VisitCounts) -> VisitCounts {
+    let mut merged = HashMap::with_capacity(visits.len());
+    for (state, count) in visits {
+        *merged.entry(key(&state)).or_insert(0) += count;
+    }
+    merged
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::packed_actions::{Action, ActionQueue};
+
+    /// A finished game: `mine` seeds left on our side, scores in the stores.
+    fn finished(mine: [u8; 6], store: u8, opp_store: u8) -> GameState {
+        let mut houses = [0; 14];
+        houses[..6].copy_from_slice(&mine);
+        houses[6] = store;
+        houses[13] = opp_store;
+        GameState { houses }
+    }
+
+    #[test]
+    fn test_terminal_keys() {
+        // Same final score, different seeds left over
+        let a = finished([1, 2, 0, 0, 0, 0], 20, 25);
+        let b = finished([0, 0, 0, 0, 0, 3], 20, 25);
+        assert_ne!(a, b);
+        assert_eq!(key(&a), key(&b));
+        assert_eq!(key(&a).houses[6], 23);
+        assert_eq!(to_move_key(&a), (key(&a), false));
+    }
+
+    #[test]
+    fn test_to_move_flips() {
+        let mut values = HashMap::new();
+        let mut state = GameState::new(4);
+        state.evaluate_action(Action::singleton(3));
+        values.insert(state, 0.8);
+        assert_eq!(value(&values, &state), 0.8);
+        // The opponent sees the same board swapped, with them to move
+        let mut opponent_view = state;
+        opponent_view.swap_board();
+        assert!((value_to_move(&values, &opponent_view) - 0.2).abs() < 1e-12);
+        assert_eq!(value(&values, &GameState::new(3)), DEFAULT_STATE_VAL);
+    }
+
+    #[test]
+    fn test_canonicalize() {
+        let mut values = HashMap::new();
+        values.insert(finished([1, 2, 0, 0, 0, 0], 20, 25), 1.0);
+        values.insert(finished([0, 0, 0, 0, 0, 3], 20, 25), 0.0);
+        values.insert(finished([0, 0, 0, 0, 0, 0], 30, 18), 1.0);
+        values.insert(GameState::new(4), 0.25);
+        let (canonical, stats) = canonicalize_values(values);
+        assert_eq!(
+            stats,
+            ShrinkStats {
+                entries: 4,
+                terminal_entries: 3,
+                canonical_entries: 3,
+                terminal_keys: 2,
+            }
+        );
+        assert_eq!(canonical[&key(&finished([1, 2, 0, 0, 0, 0], 20, 25))], 0.5);
+        assert_eq!(canonical[&GameState::new(4)], 0.25);
+
+        let mut visits = HashMap::new();
+        visits.insert(finished([1, 2, 0, 0, 0, 0], 20, 25), 2);
+        visits.insert(finished([0, 0, 0, 0, 0, 3], 20, 25), 3);
+        let visits = canonicalize_visits(visits);
+        assert_eq!(visits.values().copied().collect::<Vec<_>>(), vec![5]);
+    }
+}
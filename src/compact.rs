@@ -0,0 +1,622 @@
+use crate::index::StateIndex;
+use crate::mancala::{GameState, Rules, ValueFunction, ValueTable};
+use crate::persist;
+use std::fmt::{self, Display, Formatter};
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+use std::str::FromStr;
+
+/// Bytes every compact table starts with.
+pub const MAGIC: &[u8; 4] = b"MNCX";
+
+/// Version of the compact table format. Version 1 only checksummed the data, not the header.
+const VERSION: u16 = 2;
+
+/// The header is padded to this size so the data after it is aligned for any value type.
+const HEADER_LEN: usize = 64;
+
+/// Where the checksum sits in the header. It covers every header field before it, then the data.
+const CHECKSUM_OFFSET: usize = 40;
+
+/// Ranks in a sparse table are stored as u64.
+const RANK_WIDTH: usize = 8;
+
+/// Most positions a dense table is built for: enough for one seed per house.
+const MAX_DENSE_POSITIONS: u64 = 1 << 32;
+
+/// How each value is stored.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Encoding {
+    F32,
+    /// Quantized evenly over the range of the table's values, to 65535 levels.
+    U16,
+}
+
+impl Encoding {
+    fn width(&self) -> usize {
+        match self {
+            Encoding::F32 => 4,
+            Encoding::U16 => 2,
+        }
+    }
+}
+
+impl Display for Encoding {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Encoding::F32 => write!(f, "f32"),
+            Encoding::U16 => write!(f, "u16"),
+        }
+    }
+}
+
+impl FromStr for Encoding {
+    type Err = String;
+
+    fn from_str(spec: &str) -> Result<Encoding, String> {
+        match spec {
+            "f32" => Ok(Encoding::F32),
+            "u16" => Ok(Encoding::U16),
+            _ => Err(format!("unknown encoding '{}' (expected f32 or u16)", spec)),
+        }
+    }
+}
+
+/// How values are laid out.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Layout {
+    /// Whichever of `Dense` and `Sparse` is smaller.
+    Auto,
+    /// A value for every position, indexed by rank, with a marker for positions never seen.
+    Dense,
+    /// The ranks of the stored positions in order, then their values, found by binary search.
+    Sparse,
+}
+
+impl Display for Layout {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Layout::Auto => write!(f, "auto"),
+            Layout::Dense => write!(f, "dense"),
+            Layout::Sparse => write!(f, "sparse"),
+        }
+    }
+}
+
+impl FromStr for Layout {
+    type Err = String;
+
+    fn from_str(spec: &str) -> Result<Layout, String> {
+        match spec {
+            "auto" => Ok(Layout::Auto),
+            "dense" => Ok(Layout::Dense),
+            "sparse" => Ok(Layout::Sparse),
+            _ => Err(format!(
+                "unknown layout '{}' (expected auto, dense or sparse)",
+                spec
+            )),
+        }
+    }
+}
+
+/// A read-only memory mapping of a whole file.
+#[cfg(unix)]
+struct Mapping {
+    ptr: *mut libc::c_void,
+    len: usize,
+}
+
+#[cfg(unix)]
+impl Mapping {
+    fn new(file: &File, len: usize) -> io::Result<Mapping> {
+        use std::os::unix::io::AsRawFd;
+        // SAFETY: a private read-only mapping, unmapped on drop. Value files are replaced by
+        // renaming rather than rewritten in place, so the mapped pages never change under us.
+        let ptr = unsafe {
+            libc::mmap(
+                std::ptr::null_mut(),
+                len,
+                libc::PROT_READ,
+                libc::MAP_PRIVATE,
+                file.as_raw_fd(),
+                0,
+            )
+        };
+        if ptr == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Mapping { ptr, len })
+    }
+
+    fn bytes(&self) -> &[u8] {
+        // SAFETY: the mapping is `len` bytes long and lives as long as `self`
+        unsafe { std::slice::from_raw_parts(self.ptr as *const u8, self.len) }
+    }
+}
+
+#[cfg(unix)]
+impl Drop for Mapping {
+    fn drop(&mut self) {
+        // SAFETY: `ptr` and `len` describe a mapping made by `Mapping::new`
+        unsafe {
+            libc::munmap(self.ptr, self.len);
+        }
+    }
+}
+
+// SAFETY: the mapping is read-only
+#[cfg(unix)]
+unsafe impl Send for Mapping {}
+#[cfg(unix)]
+unsafe impl Sync for Mapping {}
+
+/// Where a table's data lives: built in memory, or mapped from its file.
+enum Data {
+    Owned(Vec<u8>),
+    #[cfg(unix)]
+    Mapped(Mapping),
+}
+
+impl Data {
+    fn bytes(&self) -> &[u8] {
+        match self {
+            Data::Owned(bytes) => bytes,
+            #[cfg(unix)]
+            Data::Mapped(mapping) => &mapping.bytes()[HEADER_LEN..],
+        }
+    }
+}
+
+/// A read-only value table packed into a flat array by `StateIndex` rank: a few bytes per entry
+/// instead of a hash map's forty or so, and loaded by mapping the file into memory.
+pub struct CompactTable {
+    rules: Rules,
+    index: StateIndex,
+    encoding: Encoding,
+    /// `Dense` or `Sparse`, never `Auto`.
+    layout: Layout,
+    entries: usize,
+    /// Range that `U16` values are quantized over.
+    lo: f64,
+    hi: f64,
+    data: Data,
+}
+
+fn invalid(message: impl Display) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+impl CompactTable {
+    /// Pack `values`, a table for games under `rules`.
+    pub fn build(
+        values: &ValueFunction,
+        rules: Rules,
+        encoding: Encoding,
+        layout: Layout,
+    ) -> Result<CompactTable, String> {
+        let index = StateIndex::for_game(&rules.starting_state())
+            .ok_or_else(|| format!("too many positions to index under {}", rules))?;
+        let mut ranked = values
+            .iter()
+            .map(|(state, &value)| {
+                // A stored NaN would read back as a missing value
+                if !value.is_finite() {
+                    return Err(format!("position has value {}:\n{}", value, state));
+                }
+                index
+                    .rank(state)
+                    .map(|rank| (rank, value))
+                    .ok_or_else(|| format!("position is not from a game of {}:\n{}", rules, state))
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+        ranked.sort_unstable_by_key(|(rank, _)| *rank);
+
+        let lo = ranked
+            .iter()
+            .map(|(_, value)| *value)
+            .fold(f64::INFINITY, f64::min);
+        let hi = ranked
+            .iter()
+            .map(|(_, value)| *value)
+            .fold(f64::NEG_INFINITY, f64::max);
+        let (lo, hi) = match (lo, hi) {
+            (lo, hi) if lo < hi => (lo, hi),
+            // A single value, or none at all
+            (lo, _) if lo.is_finite() => (lo, lo + 1.0),
+            _ => (0.0, 1.0),
+        };
+        let width = encoding.width() as u64;
+        let dense_len = index.count() * width;
+        let sparse_len = ranked.len() as u64 * (RANK_WIDTH as u64 + width);
+        let layout = match layout {
+            Layout::Auto if index.count() <= MAX_DENSE_POSITIONS && dense_len <= sparse_len => {
+                Layout::Dense
+            }
+            Layout::Auto => Layout::Sparse,
+            Layout::Dense if index.count() > MAX_DENSE_POSITIONS => {
+                return Err(format!(
+                    "{} has {} positions, too many for a dense table",
+                    rules,
+                    index.count()
+                ));
+            }
+            layout => layout,
+        };
+        let mut table = CompactTable {
+            rules,
+            index,
+            encoding,
+            layout,
+            entries: ranked.len(),
+            lo,
+            hi,
+            data: Data::Owned(Vec::new()),
+        };
+        let len = match layout {
+            Layout::Dense => dense_len,
+            _ => sparse_len,
+        };
+        let len = usize::try_from(len)
+            .map_err(|_| format!("a {} {} table is too large to address", layout, encoding))?;
+        let mut bytes = Vec::new();
+        bytes
+            .try_reserve_exact(len)
+            .map_err(|_| format!("a {} {} table needs {} bytes", layout, encoding, len))?;
+        match layout {
+            Layout::Dense => {
+                let missing = table.encode(None);
+                for _ in 0..index_len(&table) {
+                    bytes.extend_from_slice(&missing);
+                }
+                for (rank, value) in &ranked {
+                    let offset = *rank as usize * width as usize;
+                    bytes[offset..offset + width as usize]
+                        .copy_from_slice(&table.encode(Some(*value)));
+                }
+            }
+            _ => {
+                for (rank, _) in &ranked {
+                    bytes.extend_from_slice(&rank.to_le_bytes());
+                }
+                for (_, value) in &ranked {
+                    bytes.extend_from_slice(&table.encode(Some(*value)));
+                }
+            }
+        }
+        table.data = Data::Owned(bytes);
+        Ok(table)
+    }
+
+    pub fn rules(&self) -> Rules {
+        self.rules
+    }
+
+    /// Size of the table's data in bytes.
+    pub fn data_len(&self) -> usize {
+        self.data.bytes().len()
+    }
+
+    fn encode(&self, value: Option<f64>) -> Vec<u8> {
+        match (self.encoding, value) {
+            (Encoding::F32, value) => (value.unwrap_or(f64::NAN) as f32).to_le_bytes().to_vec(),
+            (Encoding::U16, None) => u16::MAX.to_le_bytes().to_vec(),
+            (Encoding::U16, Some(value)) => {
+                let level = (value - self.lo) / (self.hi - self.lo) * (u16::MAX - 1) as f64;
+                (level.round().clamp(0.0, (u16::MAX - 1) as f64) as u16)
+                    .to_le_bytes()
+                    .to_vec()
+            }
+        }
+    }
+
+    /// The value in slot `slot` of the value array, if one was stored.
+    fn decode(&self, slot: usize) -> Option<f64> {
+        let offset = match self.layout {
+            Layout::Sparse => self.entries * RANK_WIDTH,
+            _ => 0,
+        } + slot * self.encoding.width();
+        let bytes = &self.data.bytes()[offset..offset + self.encoding.width()];
+        match self.encoding {
+            Encoding::F32 => {
+                let value = f32::from_le_bytes(bytes.try_into().unwrap());
+                (!value.is_nan()).then_some(value as f64)
+            }
+            Encoding::U16 => {
+                let level = u16::from_le_bytes(bytes.try_into().unwrap());
+                (level != u16::MAX)
+                    .then(|| self.lo + level as f64 / (u16::MAX - 1) as f64 * (self.hi - self.lo))
+            }
+        }
+    }
+
+    /// Slot of `rank` in a sparse table.
+    fn find(&self, rank: u64) -> Option<usize> {
+        let bytes = self.data.bytes();
+        let rank_at = |slot: usize| {
+            let offset = slot * RANK_WIDTH;
+            u64::from_le_bytes(bytes[offset..offset + RANK_WIDTH].try_into().unwrap())
+        };
+        let (mut lo, mut hi) = (0, self.entries);
+        while lo < hi {
+            let mid = (lo + hi) / 2;
+            match rank_at(mid).cmp(&rank) {
+                std::cmp::Ordering::Less => lo = mid + 1,
+                std::cmp::Ordering::Greater => hi = mid,
+                std::cmp::Ordering::Equal => return Some(mid),
+            }
+        }
+        None
+    }
+
+    /// Every stored position with its value, in rank order.
+    pub fn iter(&self) -> impl Iterator<Item = (GameState, f64)> + '_ {
+        let slots = match self.layout {
+            Layout::Sparse => self.entries,
+            _ => index_len(self),
+        };
+        let bytes = self.data.bytes();
+        (0..slots).filter_map(move |slot| {
+            let value = self.decode(slot)?;
+            let rank = match self.layout {
+                Layout::Sparse => {
+                    let offset = slot * RANK_WIDTH;
+                    u64::from_le_bytes(bytes[offset..offset + RANK_WIDTH].try_into().unwrap())
+                }
+                _ => slot as u64,
+            };
+            Some((self.index.unrank(rank), value))
+        })
+    }
+
+    fn header(&self) -> [u8; HEADER_LEN] {
+        let mut header = [0; HEADER_LEN];
+        header[..4].copy_from_slice(MAGIC);
+        header[4..6].copy_from_slice(&VERSION.to_le_bytes());
+        match self.rules {
+            Rules::Kalah { seeds } => header[6..8].copy_from_slice(&[0, seeds]),
+        }
+        header[8] = match self.encoding {
+            Encoding::F32 => 0,
+            Encoding::U16 => 1,
+        };
+        header[9] = match self.layout {
+            Layout::Sparse => 1,
+            _ => 0,
+        };
+        header[16..24].copy_from_slice(&(self.entries as u64).to_le_bytes());
+        header[24..32].copy_from_slice(&self.lo.to_le_bytes());
+        header[32..40].copy_from_slice(&self.hi.to_le_bytes());
+        let checksum = checksum(&header, self.data.bytes());
+        header[CHECKSUM_OFFSET..CHECKSUM_OFFSET + 8].copy_from_slice(&checksum.to_le_bytes());
+        header
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        persist::write_atomic(path, &[&self.header(), self.data.bytes()])
+    }
+
+    /// Load a compact table, mapping its data into memory where the platform allows.
+    pub fn load(path: &Path) -> io::Result<CompactTable> {
+        let mut file = File::open(path)?;
+        let mut header = [0; HEADER_LEN];
+        file.read_exact(&mut header)
+            .map_err(|_| invalid("truncated header"))?;
+        if &header[..4] != MAGIC {
+            return Err(invalid("not a compact table"));
+        }
+        let version = u16::from_le_bytes([header[4], header[5]]);
+        if version != VERSION {
+            return Err(invalid(format!(
+                "compact format version {} is not supported (this build reads version {})",
+                version, VERSION
+            )));
+        }
+        let rules = match header[6] {
+            0 => Rules::Kalah { seeds: header[7] },
+            variant => return Err(invalid(format!("unknown rule variant {}", variant))),
+        };
+        let encoding = match header[8] {
+            0 => Encoding::F32,
+            1 => Encoding::U16,
+            encoding => return Err(invalid(format!("unknown encoding {}", encoding))),
+        };
+        let layout = match header[9] {
+            0 => Layout::Dense,
+            1 => Layout::Sparse,
+            layout => return Err(invalid(format!("unknown layout {}", layout))),
+        };
+        let read_u64 =
+            |offset: usize| u64::from_le_bytes(header[offset..offset + 8].try_into().unwrap());
+        let entries =
+            usize::try_from(read_u64(16)).map_err(|_| invalid("too many entries to address"))?;
+        let index = StateIndex::for_game(&rules.starting_state())
+            .ok_or_else(|| invalid(format!("too many positions to index under {}", rules)))?;
+        let mut table = CompactTable {
+            rules,
+            index,
+            encoding,
+            layout,
+            entries,
+            lo: f64::from_le_bytes(header[24..32].try_into().unwrap()),
+            hi: f64::from_le_bytes(header[32..40].try_into().unwrap()),
+            data: Data::Owned(Vec::new()),
+        };
+        // Checked, as a damaged header could otherwise wrap around to the file's real size
+        let expected_len = match layout {
+            Layout::Dense => usize::try_from(table.index.count())
+                .ok()
+                .and_then(|slots| slots.checked_mul(encoding.width())),
+            _ => entries.checked_mul(RANK_WIDTH + encoding.width()),
+        }
+        .ok_or_else(|| invalid(format!("{} entries are too many to address", entries)))?;
+        let file_len = file.metadata()?.len();
+        if file_len.checked_sub(HEADER_LEN as u64) != Some(expected_len as u64) {
+            return Err(invalid(format!(
+                "expected {} bytes of data, found {}",
+                expected_len,
+                file_len.saturating_sub(HEADER_LEN as u64)
+            )));
+        }
+        let file_len = file_len as usize;
+        table.data = map_data(file, file_len)?;
+        if checksum(&header, table.data.bytes()) != read_u64(CHECKSUM_OFFSET) {
+            return Err(invalid(
+                "checksum mismatch, the file is damaged or truncated",
+            ));
+        }
+        Ok(table)
+    }
+}
+
+/// Checksum of a table's header fields and data, so a damaged range or entry count is caught as
+/// well as damaged values.
+fn checksum(header: &[u8; HEADER_LEN], data: &[u8]) -> u64 {
+    persist::checksum_parts(&[&header[..CHECKSUM_OFFSET], data])
+}
+
+/// Number of slots in a dense table.
+fn index_len(table: &CompactTable) -> usize {
+    table.index.count() as usize
+}
+
+#[cfg(unix)]
+fn map_data(file: File, file_len: usize) -> io::Result<Data> {
+    if file_len == HEADER_LEN {
+        // Nothing to map, and mapping nothing is an error
+        return Ok(Data::Owned(Vec::new()));
+    }
+    Ok(Data::Mapped(Mapping::new(&file, file_len)?))
+}
+
+#[cfg(not(unix))]
+fn map_data(mut file: File, file_len: usize) -> io::Result<Data> {
+    let mut bytes = Vec::with_capacity(file_len - HEADER_LEN);
+    file.read_to_end(&mut bytes)?;
+    Ok(Data::Owned(bytes))
+}
+
+impl ValueTable for CompactTable {
+    fn get_value(&self, state: &GameState) -> Option<f64> {
+        let rank = self.index.rank(state)?;
+        let slot = match self.layout {
+            Layout::Sparse => self.find(rank)?,
+            _ => rank as usize,
+        };
+        self.decode(slot)
+    }
+
+    fn entries(&self) -> usize {
+        self.entries
+    }
+}
+
+impl Display for CompactTable {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} entries for {}, {} {}: {} bytes ({:.1} per entry)",
+            self.entries,
+            self.rules,
+            self.layout,
+            self.encoding,
+            self.data_len(),
+            self.data_len() as f64 / self.entries.max(1) as f64
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::collections::HashMap;
+    use std::fs;
+
+    fn table(rules: Rules) -> ValueFunction {
+        let mut values = HashMap::new();
+        let start = rules.starting_state();
+        values.insert(start, 0.25);
+        for (i, action) in start.gen_actions().enumerate() {
+            let mut state = start;
+            state.evaluate_action(action);
+            values.insert(state, 0.1 * i as f64);
+        }
+        values
+    }
+
+    #[test]
+    fn test_sparse_round_trip() {
+        let rules = Rules::Kalah { seeds: 4 };
+        let values = table(rules);
+        let path = std::env::temp_dir().join(format!("mancala-compact-{}.dat", std::process::id()));
+        for encoding in [Encoding::F32, Encoding::U16] {
+            let compact = CompactTable::build(&values, rules, encoding, Layout::Auto).unwrap();
+            assert_eq!(compact.layout, Layout::Sparse);
+            compact.save(&path).unwrap();
+            let loaded = CompactTable::load(&path).unwrap();
+            assert_eq!(loaded.entries(), values.len());
+            assert_eq!(loaded.rules(), rules);
+            for (state, value) in &values {
+                assert!((loaded.get_value(state).unwrap() - value).abs() < 1e-4);
+            }
+            assert_eq!(loaded.get_value(&GameState::new(3)), None);
+            let unpacked: ValueFunction = loaded.iter().collect();
+            assert_eq!(unpacked.len(), values.len());
+        }
+
+        // An entry count too large to address is caught before it can be multiplied out
+        let mut bytes = fs::read(&path).unwrap();
+        let good = bytes.clone();
+        bytes[16..24].copy_from_slice(&u64::MAX.to_le_bytes());
+        fs::write(&path, &bytes).unwrap();
+        assert!(CompactTable::load(&path).is_err());
+
+        // So is a damaged quantization range, which would otherwise scale every value wrongly
+        let mut bytes = good.clone();
+        bytes[32..40].copy_from_slice(&2.0f64.to_le_bytes());
+        fs::write(&path, &bytes).unwrap();
+        let err = CompactTable::load(&path).err().unwrap();
+        assert!(err.to_string().contains("checksum mismatch"), "{}", err);
+
+        let mut bytes = good;
+        *bytes.last_mut().unwrap() ^= 1;
+        fs::write(&path, &bytes).unwrap();
+        assert!(CompactTable::load(&path).is_err());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_dense() {
+        let rules = Rules::Kalah { seeds: 1 };
+        let values = table(rules);
+        let compact = CompactTable::build(&values, rules, Encoding::U16, Layout::Dense).unwrap();
+        // C(12 + 14, 14) positions, two bytes each
+        assert_eq!(compact.data_len(), 2 * 9_657_700);
+        for (state, value) in &values {
+            assert!((compact.get_value(state).unwrap() - value).abs() < 1e-4);
+        }
+        assert_eq!(compact.iter().count(), values.len());
+        let mut unseen = rules.starting_state();
+        unseen.swap_board();
+        unseen.houses[0] = 0;
+        unseen.houses[6] = 1;
+        assert_eq!(compact.get_value(&unseen), None);
+
+        let mut wrong_game = HashMap::new();
+        wrong_game.insert(GameState::new(4), 0.5);
+        assert!(CompactTable::build(&wrong_game, rules, Encoding::F32, Layout::Auto).is_err());
+        // NaN marks an unseen position, so it cannot be stored as a value
+        let mut not_a_number = values.clone();
+        not_a_number.insert(rules.starting_state(), f64::NAN);
+        assert!(CompactTable::build(&not_a_number, rules, Encoding::F32, Layout::Auto).is_err());
+        // The standard game has far too many positions for a dense table
+        let values = table(Rules::Kalah { seeds: 4 });
+        let dense = CompactTable::build(
+            &values,
+            Rules::Kalah { seeds: 4 },
+            Encoding::F32,
+            Layout::Dense,
+        );
+        assert!(dense.is_err());
+    }
+}
@@ -0,0 +1,440 @@
+use crate::canonical;
+use crate::mancala::{GameState, Outcome, Rules, ValueTable};
+use crate::packed_actions::{Action, ActionQueue};
+use crate::search::{self, SearchResult};
+use std::fmt::{self, Display, Formatter};
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+/// Deepest search `go` will run, in turns.
+pub const MAX_DEPTH: u32 = 32;
+
+/// How the engine scores the positions at the end of its search.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Evaluation {
+    /// Values from the table, with finished games scored by who won. Reported as `score value
+    /// V`, the table's value of the position for the player to move.
+    Table,
+    /// Store margin, ignoring the table. Reported as `score seeds N`.
+    Margin,
+}
+
+impl Display for Evaluation {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Evaluation::Table => write!(f, "table"),
+            Evaluation::Margin => write!(f, "margin"),
+        }
+    }
+}
+
+impl FromStr for Evaluation {
+    type Err = String;
+
+    fn from_str(spec: &str) -> Result<Evaluation, String> {
+        match spec {
+            "table" => Ok(Evaluation::Table),
+            "margin" => Ok(Evaluation::Margin),
+            _ => Err(format!(
+                "unknown evaluation '{}' (expected table or margin)",
+                spec
+            )),
+        }
+    }
+}
+
+/// Score of `state`, reached right after a turn, for the player who made it; zero-sum as
+/// `search::alpha_beta` needs.
+pub fn score_afterstate(table: &dyn ValueTable, evaluation: Evaluation, state: &GameState) -> f64 {
+    match evaluation {
+        Evaluation::Margin => search::store_margin(state),
+        Evaluation::Table => match state.is_won() {
+            Some(Outcome::P1win(_)) => 1.0,
+            Some(Outcome::P2win(_)) => -1.0,
+            Some(Outcome::Tie(_)) => 0.0,
+            None => 2.0 * canonical::value(table, state) - 1.0,
+        },
+    }
+}
+
+/// A score from `score_afterstate` as `info` lines report it: on the table's scale, or in seeds.
+pub fn format_score(evaluation: Evaluation, score: f64) -> String {
+    match evaluation {
+        Evaluation::Table => format!("value {:.4}", (score + 1.0) / 2.0),
+        Evaluation::Margin => format!("seeds {}", score as i64),
+    }
+}
+
+/// A turn as the protocol writes it: the houses sown, numbered 1 to 6 from the mover's left,
+/// joined by `-` when sowing into the store earns another, e.g. `3-1`.
+pub fn format_move(action: Action) -> String {
+    let mut action = action;
+    let mut houses = Vec::new();
+    while !action.is_empty() {
+        houses.push((action.pop_front() + 1).to_string());
+    }
+    houses.join("-")
+}
+
+/// Read a turn written by `format_move`, which has to be legal for the player to move at
+/// `state`.
+pub fn parse_move(state: &GameState, text: &str) -> Result<Action, String> {
+    let mut action = Action::new();
+    for house in text.split('-') {
+        match house.parse::<u8>() {
+            Ok(house) if (1..=6).contains(&house) => action.push_front(house - 1),
+            _ => return Err(format!("bad move '{}': houses are numbered 1 to 6", text)),
+        }
+    }
+    if state.gen_actions().any(|legal| legal == action) {
+        Ok(action)
+    } else {
+        Err(format!("illegal move '{}'", text))
+    }
+}
+
+/// Loads the table named by `setoption name Table`.
+pub type TableLoader<'a> = dyn Fn(&Path) -> io::Result<Box<dyn ValueTable>> + 'a;
+
+/// Plays one side of a game for a frontend, answering a line-based protocol modelled on UCI.
+/// Commands are handled in turn, so `go` answers before the next command is read. Unlike UCI
+/// there is no `stop`: a search blocks the engine until it finishes, so frontends that need an
+/// answer in time bound it with `movetime`.
+///
+/// - `mancala`: reply with `id` and `option` lines, then `mancalaok`
+/// - `isready`: reply `readyok`
+/// - `setoption name NAME value VALUE`: set `Table` (a value file or compact table), `Depth`
+///   (turns searched by a plain `go`) or `Evaluation` (`table` or `margin`)
+/// - `newgame`: go back to the starting position
+/// - `position (startpos | board SLOTS) [moves MOVE...]`: set the position, with `SLOTS` as for
+///   `model query` (the player to move first) and holding as many seeds as the rules deal out,
+///   then play the moves in turn
+/// - `go [depth N] [movetime MS]`: search, deepening a turn at a time up to `N` turns or until
+///   `MS` milliseconds have passed, with an `info` line for each depth finished; then reply
+///   `bestmove MOVE`, or `bestmove none` if the game is over
+/// - `board`: reply `board SLOTS` for the position, with the player to move first
+/// - `quit`
+///
+/// Anything the engine cannot make sense of gets an `info string` line saying why.
+pub struct Engine<'a> {
+    rules: Rules,
+    table: Box<dyn ValueTable>,
+    table_path: PathBuf,
+    load_table: &'a TableLoader<'a>,
+    /// The position, seen by the player to move.
+    position: GameState,
+    depth: u32,
+    evaluation: Evaluation,
+}
+
+impl<'a> Engine<'a> {
+    pub fn new(
+        rules: Rules,
+        table_path: PathBuf,
+        load_table: &'a TableLoader<'a>,
+        depth: u32,
+    ) -> io::Result<Engine<'a>> {
+        Ok(Engine {
+            rules,
+            table: load_table(&table_path)?,
+            table_path,
+            load_table,
+            position: rules.starting_state(),
+            depth: depth.clamp(1, MAX_DEPTH),
+            evaluation: Evaluation::Table,
+        })
+    }
+
+    /// Answer commands from `input` until it ends or says `quit`.
+    pub fn run(&mut self, input: &mut dyn BufRead, out: &mut dyn Write) -> io::Result<()> {
+        let mut line = String::new();
+        loop {
+            line.clear();
+            if input.read_line(&mut line)? == 0 {
+                return Ok(());
+            }
+            let line = line.trim();
+            if line == "quit" {
+                return Ok(());
+            }
+            if let Err(err) = self.command(line, out) {
+                writeln!(out, "info string error: {}", err)?;
+            }
+            // Frontends wait on each reply, so never leave one buffered
+            out.flush()?;
+        }
+    }
+
+    fn command(&mut self, line: &str, out: &mut dyn Write) -> Result<(), String> {
+        let (command, args) = line.split_once(' ').unwrap_or((line, ""));
+        let args = args.trim();
+        match command {
+            "" => {}
+            "mancala" => {
+                let mut reply = format!("id name mancala {}\n", env!("CARGO_PKG_VERSION"));
+                reply += &format!(
+                    "option name Table type string default {}\n",
+                    self.table_path.display()
+                );
+                reply += &format!(
+                    "option name Depth type spin default {} min 1 max {}\n",
+                    self.depth, MAX_DEPTH
+                );
+                reply += &format!(
+                    "option name Evaluation type combo default {} var table var margin\n",
+                    self.evaluation
+                );
+                reply += "mancalaok";
+                writeln!(out, "{}", reply).map_err(|err| err.to_string())?;
+            }
+            "isready" => writeln!(out, "readyok").map_err(|err| err.to_string())?,
+            "setoption" => self.set_option(args)?,
+            "newgame" => self.position = self.rules.starting_state(),
+            "position" => self.set_position(args)?,
+            "go" => self.go(args, out)?,
+            "board" => {
+                let slots: Vec<String> = self.position.houses.iter().map(u8::to_string).collect();
+                writeln!(out, "board {}", slots.join(",")).map_err(|err| err.to_string())?;
+            }
+            _ => return Err(format!("unknown command '{}'", command)),
+        }
+        Ok(())
+    }
+
+    fn set_option(&mut self, args: &str) -> Result<(), String> {
+        let usage = || "expected setoption name NAME value VALUE".to_string();
+        let (name, value) = args
+            .strip_prefix("name ")
+            .and_then(|rest| rest.split_once(" value "))
+            .ok_or_else(usage)?;
+        let value = value.trim();
+        match name.trim() {
+            "Table" => {
+                let path = PathBuf::from(value);
+                self.table = (self.load_table)(&path)
+                    .map_err(|err| format!("loading {}: {}", path.display(), err))?;
+                self.table_path = path;
+            }
+            "Depth" => match value.parse::<u32>() {
+                Ok(depth) if (1..=MAX_DEPTH).contains(&depth) => self.depth = depth,
+                _ => return Err(format!("Depth has to be from 1 to {}", MAX_DEPTH)),
+            },
+            "Evaluation" => self.evaluation = value.parse()?,
+            name => return Err(format!("unknown option '{}'", name)),
+        }
+        Ok(())
+    }
+
+    fn set_position(&mut self, args: &str) -> Result<(), String> {
+        let mut tokens = args.split_whitespace();
+        let mut position = match tokens.next() {
+            Some("startpos") => self.rules.starting_state(),
+            Some("board") => {
+                let board = tokens
+                    .next()
+                    .ok_or("expected a board after 'position board'")?
+                    .parse()?;
+                self.rules.check_board(&board)?;
+                board
+            }
+            _ => return Err("expected position startpos or position board SLOTS".to_string()),
+        };
+        match tokens.next() {
+            None => {}
+            Some("moves") => {
+                for text in tokens {
+                    if position.is_ended() {
+                        return Err(format!("move '{}' after the end of the game", text));
+                    }
+                    position.evaluate_action(parse_move(&position, text)?);
+                    position.swap_board();
+                }
+            }
+            Some(token) => return Err(format!("expected 'moves', not '{}'", token)),
+        }
+        // Only replace the position once the whole command has made sense
+        self.position = position;
+        Ok(())
+    }
+
+    fn evaluate(&self, state: &GameState) -> f64 {
+        score_afterstate(self.table.as_ref(), self.evaluation, state)
+    }
+
+    fn go(&self, args: &str, out: &mut dyn Write) -> Result<(), String> {
+        let mut depth = None;
+        let mut movetime = None;
+        let mut tokens = args.split_whitespace();
+        while let Some(token) = tokens.next() {
+            let value = tokens.next().and_then(|value| value.parse::<u64>().ok());
+            match (token, value) {
+                ("depth", Some(turns)) if (1..=MAX_DEPTH as u64).contains(&turns) => {
+                    depth = Some(turns as u32)
+                }
+                ("movetime", Some(ms)) => movetime = Some(Duration::from_millis(ms)),
+                _ => return Err(format!("expected go [depth 1-{}] [movetime MS]", MAX_DEPTH)),
+            }
+        }
+        let io_error = |err: io::Error| err.to_string();
+        if self.position.is_ended() {
+            writeln!(out, "bestmove none").map_err(io_error)?;
+            return Ok(());
+        }
+        let start = Instant::now();
+        let deadline = movetime.map(|movetime| start + movetime);
+        let max_depth = depth.unwrap_or(if movetime.is_some() {
+            MAX_DEPTH
+        } else {
+            self.depth
+        });
+        let evaluate = |state: &GameState| self.evaluate(state);
+        let mut best: Option<SearchResult> = None;
+        let mut nodes = 0;
+        for depth in 1..=max_depth {
+            // Always finish the first turn, so there is a move to play
+            let deadline = deadline.filter(|_| best.is_some());
+            let Some(result) =
+                search::alpha_beta_until(&self.position, depth, &evaluate, deadline, None)
+            else {
+                break;
+            };
+            nodes += result.nodes;
+            // A search that grew no bigger than the last reached the end of every line
+            let exhausted = best.as_ref().is_some_and(|last| last.nodes == result.nodes);
+            let pv: Vec<String> = result
+                .pv
+                .iter()
+                .map(|&action| format_move(action))
+                .collect();
+            writeln!(
+                out,
+                "info depth {} score {} nodes {} time {} pv {}",
+                depth,
+                format_score(self.evaluation, result.score),
+                nodes,
+                start.elapsed().as_millis(),
+                pv.join(" ")
+            )
+            .map_err(io_error)?;
+            best = Some(result);
+            if exhausted {
+                break;
+            }
+        }
+        writeln!(out, "bestmove {}", format_move(best.unwrap().pv[0])).map_err(io_error)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mancala::ValueFunction;
+
+    fn session(script: &str) -> Vec<String> {
+        let load =
+            |_: &Path| -> io::Result<Box<dyn ValueTable>> { Ok(Box::new(ValueFunction::new())) };
+        let mut engine = Engine::new(
+            Rules::Kalah { seeds: 4 },
+            PathBuf::from("train.dat"),
+            &load,
+            1,
+        )
+        .unwrap();
+        let mut out = Vec::new();
+        engine.run(&mut script.as_bytes(), &mut out).unwrap();
+        String::from_utf8(out)
+            .unwrap()
+            .lines()
+            .map(String::from)
+            .collect()
+    }
+
+    #[test]
+    fn test_moves() {
+        let start = GameState::new(4);
+        // Sowing house 3 ends in the store, so the turn goes on
+        assert!(parse_move(&start, "3").unwrap_err().contains("illegal"));
+        let action = parse_move(&start, "3-1").unwrap();
+        assert_eq!(format_move(action), "3-1");
+        assert_eq!(action.to_string(), "Cells 3 → 1");
+        assert!(parse_move(&start, "7").is_err());
+        assert!(parse_move(&start, "3-").is_err());
+    }
+
+    #[test]
+    fn test_handshake() {
+        let reply = session("mancala\nisready\nquit\nisready\n");
+        assert_eq!(
+            reply[0],
+            format!("id name mancala {}", env!("CARGO_PKG_VERSION"))
+        );
+        assert!(reply[1].starts_with("option name Table type string"));
+        assert_eq!(&reply[reply.len() - 2..], ["mancalaok", "readyok"]);
+    }
+
+    #[test]
+    fn test_position_and_go() {
+        let reply = session(
+            "position startpos moves 3-1 2\n\
+             board\n\
+             setoption name Evaluation value margin\n\
+             go depth 2\n\
+             position startpos moves 3\n\
+             board\n\
+             frobnicate\n\
+             position board 9,9,9,9,9,9,0,4,4,4,4,4,4,0\n\
+             board\n",
+        );
+        // Seen by the first player, to move again
+        assert_eq!(reply[0], "board 0,5,1,6,6,5,1,4,0,5,5,5,5,0");
+        assert!(reply[1].starts_with("info depth 1 score seeds "));
+        assert!(reply[2].starts_with("info depth 2 "));
+        let pv: Vec<&str> = reply[2].split(" pv ").nth(1).unwrap().split(' ').collect();
+        assert_eq!(pv.len(), 2);
+        assert_eq!(reply[3], format!("bestmove {}", pv[0]));
+        // A bad command leaves the position alone
+        assert_eq!(reply[4], "info string error: illegal move '3'");
+        assert_eq!(reply[5], reply[0]);
+        assert_eq!(reply[6], "info string error: unknown command 'frobnicate'");
+        // So does a board with seeds the rules never dealt
+        assert_eq!(
+            reply[7],
+            "info string error: board holds 78 seeds, but kalah with 4 seeds per house starts \
+             with only 48"
+        );
+        assert_eq!(reply[8], reply[0]);
+    }
+
+    #[test]
+    fn test_dropped_seeds() {
+        // Sowing only deals out what is left over after whole laps of the board, so 16 seeds sow
+        // two and the game goes on with fewer seeds than it started with
+        let reply = session("position board 16,2,2,2,2,2,0,4,4,4,4,3,3,0 moves 1\nboard\n");
+        let board = reply[0].strip_prefix("board ").unwrap();
+        let seeds: u32 = board
+            .split(',')
+            .map(|slot| slot.parse::<u32>().unwrap())
+            .sum();
+        assert!(seeds < 48);
+        // The engine takes back the board it handed out
+        let reply = session(&format!("position board {}\nboard\n", board));
+        assert_eq!(reply, vec![format!("board {}", board)]);
+    }
+
+    #[test]
+    fn test_game_over() {
+        let reply = session(
+            "position board 0,0,0,0,0,1,20,4,4,4,4,4,4,3\n\
+             go movetime 50\n\
+             position board 0,0,0,0,0,0,22,4,4,4,4,4,4,2\n\
+             go\n",
+        );
+        // Emptying our side ends the game, lost 21 to 27, so there is nothing deeper to search
+        assert!(reply[0].starts_with("info depth 1 score value 0.0000 nodes 1 "));
+        assert!(reply[1].starts_with("info depth 2 ") && reply[1].ends_with(" pv 6"));
+        assert_eq!(reply[2], "bestmove 6");
+        assert_eq!(reply[3], "bestmove none");
+    }
+}
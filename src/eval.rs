@@ -0,0 +1,273 @@
+use crate::mancala::{GameState, ValueTable};
+use crate::packed_actions::Action;
+use crate::player::{AIPlayer, BaselinePlayer, Player, Strategy};
+use rand::RngCore;
+use rand::seq::SliceRandom;
+use std::cmp::Ordering;
+use std::fmt::{self, Display, Formatter};
+use std::path::PathBuf;
+use std::str::FromStr;
+
+/// An agent named on the command line: `table[:FILE]`, `random`, `greedy` or `alphabeta:DEPTH`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum AgentSpec {
+    /// Play greedily from a value table; `None` means the `--train` file.
+    Table(Option<PathBuf>),
+    Baseline(Strategy),
+}
+
+impl AgentSpec {
+    /// Create a player for this agent, seated at `starting_state` (from its own perspective).
+    pub fn player(&self, starting_state: GameState) -> Box<dyn Player> {
+        match self {
+            AgentSpec::Table(_) => Box::new(AIPlayer::new(starting_state)),
+            AgentSpec::Baseline(strategy) => {
+                Box::new(BaselinePlayer::new(starting_state, *strategy))
+            }
+        }
+    }
+}
+
+impl Display for AgentSpec {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            AgentSpec::Table(None) => write!(f, "table"),
+            AgentSpec::Table(Some(path)) => write!(f, "table:{}", path.display()),
+            AgentSpec::Baseline(Strategy::Random) => write!(f, "random"),
+            AgentSpec::Baseline(Strategy::Greedy) => write!(f, "greedy"),
+            AgentSpec::Baseline(Strategy::AlphaBeta(depth)) => write!(f, "alphabeta:{}", depth),
+        }
+    }
+}
+
+impl FromStr for AgentSpec {
+    type Err = String;
+
+    fn from_str(spec: &str) -> Result<AgentSpec, String> {
+        match spec.split_once(':') {
+            None if spec == "table" => Ok(AgentSpec::Table(None)),
+            None if spec == "random" => Ok(AgentSpec::Baseline(Strategy::Random)),
+            None if spec == "greedy" => Ok(AgentSpec::Baseline(Strategy::Greedy)),
+            Some(("table", path)) => Ok(AgentSpec::Table(Some(PathBuf::from(path)))),
+            Some(("alphabeta", depth)) => match depth.parse::<u32>() {
+                Ok(depth) if depth > 0 => Ok(AgentSpec::Baseline(Strategy::AlphaBeta(depth))),
+                _ => Err(format!("bad search depth '{}'", depth)),
+            },
+            _ => Err(format!(
+                "unknown agent '{}' (expected table[:FILE], random, greedy or alphabeta:DEPTH)",
+                spec
+            )),
+        }
+    }
+}
+
+/// An agent ready to play: its spec and the value table it reads from (empty for baselines).
+pub struct Agent<'a> {
+    pub spec: AgentSpec,
+    pub values: &'a dyn ValueTable,
+}
+
+/// Play one game from `state`, with `to_move` taking the first turn. Returns the finished board
+/// from `to_move`'s perspective.
+pub fn play_game(
+    to_move: &Agent,
+    other: &Agent,
+    state: GameState,
+    rng: &mut dyn RngCore,
+) -> GameState {
+    let mut other_state = state;
+    other_state.swap_board();
+    let mut players = [
+        (to_move.spec.player(state), to_move.values),
+        (other.spec.player(other_state), other.values),
+    ];
+    let mut turn = 0;
+    loop {
+        let action = {
+            let (player, values) = &mut players[turn % 2];
+            player.take_action(*values, 0.0, rng)
+        };
+        players[(turn + 1) % 2].0.opponent_plays(action);
+        if players[0].0.current_state().is_ended() {
+            break;
+        }
+        turn += 1;
+    }
+    let mut final_state = players[0].0.current_state();
+    final_state.finalize_game();
+    final_state
+}
+
+/// Play `plies` uniformly random turns from `state`, returning the position reached (from the
+/// perspective of the player to move) and how many turns were played. Stops early rather than
+/// finishing the game.
+pub fn random_opening(state: GameState, plies: usize, rng: &mut dyn RngCore) -> (GameState, usize) {
+    let mut state = state;
+    for ply in 0..plies {
+        let actions: Vec<Action> = state.gen_actions().collect();
+        let mut next = state;
+        next.evaluate_action(*actions.choose(rng).unwrap());
+        if next.is_ended() {
+            return (state, ply);
+        }
+        next.swap_board();
+        state = next;
+    }
+    (state, plies)
+}
+
+/// Outcomes of a match between agents A and B, from A's point of view.
+#[derive(Debug, Default)]
+pub struct MatchStats {
+    /// (A's store minus B's at the end of the game, whether A moved first)
+    pub games: Vec<(i32, bool)>,
+}
+
+/// Match score for a final margin: 1 for a win, 0.5 for a draw and 0 for a loss.
+pub fn margin_score(margin: i32) -> f64 {
+    match margin.cmp(&0) {
+        Ordering::Greater => 1.0,
+        Ordering::Equal => 0.5,
+        Ordering::Less => 0.0,
+    }
+}
+
+impl MatchStats {
+    pub fn record(&mut self, a_final_state: &GameState, a_moved_first: bool) {
+        let margin = a_final_state.houses[6] as i32 - a_final_state.houses[13] as i32;
+        self.games.push((margin, a_moved_first));
+    }
+
+    /// (wins, draws, losses) for A, optionally only counting games where A did or did not move
+    /// first.
+    pub fn results(&self, moved_first: Option<bool>) -> (usize, usize, usize) {
+        let mut counts = (0, 0, 0);
+        for &(margin, first) in &self.games {
+            if moved_first.is_some_and(|moved_first| moved_first != first) {
+                continue;
+            }
+            match margin.cmp(&0) {
+                Ordering::Greater => counts.0 += 1,
+                Ordering::Equal => counts.1 += 1,
+                Ordering::Less => counts.2 += 1,
+            }
+        }
+        counts
+    }
+
+    /// Fraction of the games A won outright.
+    pub fn win_rate(&self) -> f64 {
+        let (wins, _, _) = self.results(None);
+        wins as f64 / self.games.len().max(1) as f64
+    }
+
+    /// A's mean score (win = 1, draw = 0.5) and the half-width of its 95% confidence interval.
+    pub fn score(&self) -> (f64, f64) {
+        mean_and_interval(self.games.iter().map(|game| margin_score(game.0)))
+    }
+
+    /// A's mean store margin and the half-width of its 95% confidence interval.
+    pub fn margin(&self) -> (f64, f64) {
+        mean_and_interval(self.games.iter().map(|game| game.0 as f64))
+    }
+}
+
+/// Sample mean and the half-width of its normal-approximation 95% confidence interval.
+fn mean_and_interval(samples: impl Iterator<Item = f64>) -> (f64, f64) {
+    let samples: Vec<f64> = samples.collect();
+    let n = samples.len() as f64;
+    if samples.is_empty() {
+        return (0.0, 0.0);
+    }
+    let mean = samples.iter().sum::<f64>() / n;
+    if samples.len() < 2 {
+        return (mean, f64::INFINITY);
+    }
+    let variance = samples.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / (n - 1.0);
+    (mean, 1.96 * (variance / n).sqrt())
+}
+
+impl Display for MatchStats {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let (wins, draws, losses) = self.results(None);
+        writeln!(f, "Games: {}", self.games.len())?;
+        writeln!(f, "Wins/draws/losses: {}/{}/{}", wins, draws, losses)?;
+        for (label, first) in [("first", true), ("second", false)] {
+            let (wins, draws, losses) = self.results(Some(first));
+            writeln!(f, "  moving {}: {}/{}/{}", label, wins, draws, losses)?;
+        }
+        let (score, score_ci) = self.score();
+        writeln!(f, "Score: {:.3} ± {:.3} (95% CI)", score, score_ci)?;
+        let (margin, margin_ci) = self.margin();
+        write!(
+            f,
+            "Average margin: {:+.2} ± {:.2} (95% CI)",
+            margin, margin_ci
+        )
+    }
+}
+
+/// Play `pairs` pairs of games between `a` and `b`. Each pair starts from the same position
+/// (after `opening_plies` random turns) with each agent moving first once.
+pub fn evaluate(
+    a: &Agent,
+    b: &Agent,
+    starting_state: GameState,
+    pairs: usize,
+    opening_plies: usize,
+    rng: &mut dyn RngCore,
+) -> MatchStats {
+    let mut stats = MatchStats::default();
+    for _ in 0..pairs {
+        let (state, _) = random_opening(starting_state, opening_plies, rng);
+        let final_state = play_game(a, b, state, rng);
+        stats.record(&final_state, true);
+        let mut final_state = play_game(b, a, state, rng);
+        final_state.swap_board();
+        stats.record(&final_state, false);
+    }
+    stats
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_parse_agent_spec() {
+        for spec in ["table", "table:foo.dat", "random", "greedy", "alphabeta:3"] {
+            assert_eq!(spec.parse::<AgentSpec>().unwrap().to_string(), spec);
+        }
+        assert!("alphabeta:0".parse::<AgentSpec>().is_err());
+        assert!("minimax:2".parse::<AgentSpec>().is_err());
+    }
+
+    #[test]
+    fn test_evaluate() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let empty = HashMap::new();
+        let search = Agent {
+            spec: "alphabeta:2".parse().unwrap(),
+            values: &empty,
+        };
+        let random = Agent {
+            spec: "random".parse().unwrap(),
+            values: &empty,
+        };
+        let stats = evaluate(&search, &random, GameState::new(4), 20, 2, &mut rng);
+        assert_eq!(stats.games.len(), 40);
+        let (wins, draws, losses) = stats.results(Some(true));
+        assert_eq!(wins + draws + losses, 20);
+        let (score, ci) = stats.score();
+        assert!(score - ci > 0.5, "search should beat random: {}", stats);
+        let (all_wins, all_draws, _) = stats.results(None);
+        assert_eq!(stats.win_rate(), all_wins as f64 / 40.0);
+        assert!(stats.win_rate() <= score && score <= stats.win_rate() + all_draws as f64 / 80.0);
+        for &(margin, _) in &stats.games {
+            assert!(margin.abs() <= 48);
+        }
+    }
+}
@@ -0,0 +1,133 @@
+use crate::mancala::GameState;
+
+/// Number of slots on the board: twelve houses and two stores.
+const SLOTS: usize = 14;
+/// Slots ranked over: the board and the seeds missing from it.
+const RANKED: usize = SLOTS + 1;
+
+/// A perfect ranking of the boards holding at most a given number of seeds, to the dense range
+/// `0..count()`. No position in a game holds more seeds than it started with, so a table for one
+/// game can be stored as a flat array indexed by rank. (Sowing a full lap of the board drops
+/// seeds, so totals can fall during a game.)
+///
+/// Boards are ranked as if a fifteenth slot held the seeds missing from the total, ordered
+/// lexicographically by their slots; the rank of a board is how many boards come before it.
+#[derive(Debug, Clone)]
+pub struct StateIndex {
+    seeds: usize,
+    /// `compositions[m][r]`: the ways to spread `r` seeds over `m` slots.
+    compositions: Vec<Vec<u64>>,
+}
+
+impl StateIndex {
+    /// Index the boards holding up to `seeds` seeds, or `None` if there are too many of them
+    /// to count in a u64.
+    pub fn new(seeds: usize) -> Option<StateIndex> {
+        // One way to spread any number of seeds over one slot; more slots by Pascal's rule
+        let mut compositions = vec![vec![0; seeds + 1], vec![1; seeds + 1]];
+        for slots in 2..=RANKED {
+            let mut row = vec![1u64; seeds + 1];
+            for r in 1..=seeds {
+                row[r] = row[r - 1].checked_add(compositions[slots - 1][r])?;
+            }
+            compositions.push(row);
+        }
+        Some(StateIndex {
+            seeds,
+            compositions,
+        })
+    }
+
+    /// Index the positions of a game starting from `starting_state`.
+    pub fn for_game(starting_state: &GameState) -> Option<StateIndex> {
+        StateIndex::new(
+            starting_state
+                .houses
+                .iter()
+                .map(|&seeds| seeds as usize)
+                .sum(),
+        )
+    }
+
+    /// Number of boards indexed.
+    pub fn count(&self) -> u64 {
+        self.compositions[RANKED][self.seeds]
+    }
+
+    /// Rank of `state`, or `None` if it holds more than the indexed number of seeds.
+    pub fn rank(&self, state: &GameState) -> Option<u64> {
+        let total: usize = state.houses.iter().map(|&seeds| seeds as usize).sum();
+        if total > self.seeds {
+            return None;
+        }
+        let mut rank = 0;
+        let mut remaining = self.seeds;
+        // The missing seeds make up whatever is left, so they add nothing
+        for (slot, &seeds) in state.houses.iter().enumerate() {
+            let slots = RANKED - slot;
+            let seeds = seeds as usize;
+            // Boards that put fewer seeds here: all spreads of `remaining` over these slots, less
+            // those with at least `seeds` here
+            rank +=
+                self.compositions[slots][remaining] - self.compositions[slots][remaining - seeds];
+            remaining -= seeds;
+        }
+        Some(rank)
+    }
+
+    /// The board with the given rank, which has to be less than `count()`.
+    pub fn unrank(&self, mut rank: u64) -> GameState {
+        assert!(rank < self.count(), "rank {} out of range", rank);
+        let mut state = GameState { houses: [0; SLOTS] };
+        let mut remaining = self.seeds;
+        for slot in 0..SLOTS {
+            let rest = RANKED - slot - 1;
+            let mut seeds = 0;
+            while rank >= self.compositions[rest][remaining - seeds] {
+                rank -= self.compositions[rest][remaining - seeds];
+                seeds += 1;
+            }
+            state.houses[slot] = seeds as u8;
+            remaining -= seeds;
+        }
+        state
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::packed_actions::{Action, ActionQueue};
+
+    #[test]
+    fn test_rank_round_trip() {
+        let index = StateIndex::new(3).unwrap();
+        // C(3 + 14, 14): boards with up to 3 seeds
+        assert_eq!(index.count(), 680);
+        for rank in 0..index.count() {
+            let state = index.unrank(rank);
+            assert_eq!(index.rank(&state), Some(rank));
+        }
+        assert_eq!(index.unrank(0).houses, [0; SLOTS]);
+        assert_eq!(index.unrank(index.count() - 1).houses[0], 3);
+        assert_eq!(index.rank(&GameState::new(4)), None);
+    }
+
+    #[test]
+    fn test_kalah_index() {
+        let start = GameState::new(4);
+        let index = StateIndex::for_game(&start).unwrap();
+        // C(48 + 14, 14)
+        assert_eq!(index.count(), 29_078_984_349_975);
+        let mut state = start;
+        state.evaluate_action(Action::singleton(2));
+        // Sowing a full lap leaves fewer seeds on the board
+        let mut lapped = GameState { houses: [0; SLOTS] };
+        lapped.houses[5] = 16;
+        lapped.evaluate_action(Action::singleton(5));
+        for state in [start, state, lapped] {
+            assert_eq!(index.unrank(index.rank(&state).unwrap()), state);
+        }
+        assert!(StateIndex::new(10_000).is_none());
+    }
+}
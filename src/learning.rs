@@ -1,49 +1,188 @@
-use super::player::{AIPlayer, Player};
+use super::canonical;
+use super::player::{AIPlayer, BaselinePlayer, DEFAULT_STATE_VAL, Player};
+use super::pool::{Opponent, OpponentPool, PoolConfig, SNAPSHOT_EPSILON};
+use super::replay::{ReplayBuffer, ReplayConfig, Transition};
+use super::reward::RewardScheme;
+use super::schedule::Schedule;
+use rand::{Rng, RngCore};
 use std::collections::HashMap;
+use std::time::{Duration, Instant};
 
-fn dump_counter_stats(lens: &Vec<usize>, header_only: bool) {
-    let buckets = vec![0, 5, 10, 15, 20, 25, 30, 45, 50, 60, 70, 80, 90, 100];
+extern crate serde;
+use self::serde::{Deserialize, Serialize};
+
+/// Hyperparameters for a training run.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TrainingConfig {
+    pub epsilon: Schedule,
+    pub learning_rate: Schedule,
+    pub discount_factor: f64,
+    pub episodes: usize,
+    /// What the learner is rewarded for.
+    pub reward: RewardScheme,
+    /// Experience replay on top of the online updates, if any.
+    pub replay: Option<ReplayConfig>,
+    /// Opponents to mix in instead of pure self-play, if any.
+    pub pool: Option<PoolConfig>,
+}
+
+/// Bucket boundaries (in turns) for game-length histograms.
+pub const GAME_LENGTH_BUCKETS: [usize; 14] = [0, 5, 10, 15, 20, 25, 30, 45, 50, 60, 70, 80, 90, 100];
+
+/// Count how many of `lens` fall between each consecutive pair of `GAME_LENGTH_BUCKETS`.
+pub fn game_length_histogram(lens: &[usize]) -> Vec<usize> {
+    GAME_LENGTH_BUCKETS
+        .windows(2)
+        .map(|bucket| {
+            lens.iter()
+                .filter(|val| **val < bucket[1] && **val >= bucket[0])
+                .count()
+        })
+        .collect()
+}
+
+/// Print a row of `game_length_histogram(lens)`, or the bucket boundaries if `header_only`.
+pub fn dump_counter_stats(lens: &[usize], header_only: bool) {
     if header_only {
-        for buc in buckets.iter() {
+        for buc in GAME_LENGTH_BUCKETS.iter() {
             print!("[{:5}] ", buc);
         }
-        println!("");
+        println!();
         return;
     }
-    let mut counts = Vec::new();
-
-    for i in 1..buckets.len() {
-        let count = lens
-            .iter()
-            .filter(|val| **val < buckets[i] && **val >= buckets[i - 1])
-            .count();
-        counts.push(count);
-    }
-    for count in counts.iter() {
+    for count in game_length_histogram(lens).iter() {
         print!("{:7} ", count);
     }
-    println!("");
+    println!();
 }
 
-use crate::mancala::GameState;
+use crate::mancala::{GameState, ValueFunction, VisitCounts};
 
-pub fn sarsa_loop(
-    values: &mut HashMap<GameState, f64>,
-    starting_state: GameState,
-    epsilon: f64,
+/// Statistics gathered over one reporting interval of training.
+#[derive(Debug, Clone)]
+pub struct IntervalStats {
+    /// Episodes completed in this run so far.
+    pub episodes: usize,
+    /// Epsilon used for the last episode of the interval.
+    pub epsilon: f64,
+    /// Mean absolute TD error of the updates made during the interval.
+    pub mean_td_error: f64,
+    /// Number of states in the value table.
+    pub table_size: usize,
+    /// Length in turns of each game played during the interval.
+    pub game_lengths: Vec<usize>,
+    /// Wall time since the start of the run.
+    pub elapsed: Duration,
+}
+
+/// Hooks called by `sarsa_loop` as training progresses.
+pub trait TrainingObserver {
+    /// Episodes the table was trained for before this run, which the epsilon and learning rate
+    /// schedules carry on from.
+    fn previous_episodes(&self) -> usize {
+        0
+    }
+    /// Episodes between calls to `report`.
+    fn report_every(&self) -> usize {
+        1000
+    }
+    /// Called at the end of every reporting interval, and for any partial interval at the end.
+    fn report(&mut self, _stats: &IntervalStats, _values: &ValueFunction) {}
+    /// Episodes between calls to `checkpoint`, if checkpointing is wanted.
+    fn checkpoint_every(&self) -> Option<usize> {
+        None
+    }
+    /// Called with the values, the visit counts and the episodes completed so far.
+    fn checkpoint(&mut self, _values: &ValueFunction, _visits: &VisitCounts, _episodes: usize) {}
+    /// Called after every episode with the episodes completed so far. Training stops early if
+    /// this returns false.
+    fn after_episode(
+        &mut self,
+        _values: &ValueFunction,
+        _visits: &VisitCounts,
+        _episodes: usize,
+    ) -> bool {
+        true
+    }
+}
+
+impl TrainingObserver for () {}
+
+/// Apply a replayed transition to the table, returning its TD error.
+fn replay_update(
+    values: &mut ValueFunction,
+    transition: &Transition,
     learning_rate: f64,
     discount_factor: f64,
-    episodes: usize,
-) {
-    let print_rate = 1000;
-    let mut game_lengths = Vec::with_capacity(print_rate);
-    println!("Game length histogram:");
-    dump_counter_stats(&game_lengths, true);
+) -> f64 {
+    let q_next = if transition.next_to_move {
+        canonical::value_to_move(values, &transition.next_state)
+    } else {
+        canonical::value(values, &transition.next_state)
+    };
+    let target = canonical::td_target(transition.reward, discount_factor, q_next);
+    let q_last = values
+        .entry(canonical::key(&transition.afterstate))
+        .or_insert(DEFAULT_STATE_VAL);
+    let td_error = target - *q_last;
+    *q_last += learning_rate * td_error;
+    td_error
+}
 
-    for episode in 0..episodes {
+/// The table being trained during one episode, and what is tracked about its updates.
+struct Learner<'a> {
+    values: &'a mut ValueFunction,
+    visits: &'a mut VisitCounts,
+    replay: Option<&'a mut ReplayBuffer>,
+    config: &'a TrainingConfig,
+    /// Episode the schedules are at, counting those of earlier runs.
+    episode: usize,
+    td_error_sum: f64,
+    td_updates: usize,
+}
+
+impl Learner<'_> {
+    fn epsilon(&self) -> f64 {
+        self.config.epsilon.value(self.episode, 0).clamp(0.0, 1.0)
+    }
+
+    /// TD update for `player`, recording the transition for replay. A finished game's final
+    /// position has to be valued with `finish` first.
+    fn update(&mut self, player: &AIPlayer) {
+        let (config, episode) = (self.config, self.episode);
+        let learning_rate = |visits| config.learning_rate.value(episode, visits);
+        let reward = config
+            .reward
+            .step_reward(&player.curr_state, config.discount_factor);
+        self.td_error_sum += player
+            .td_update(
+                self.values,
+                self.visits,
+                &learning_rate,
+                config.discount_factor,
+                reward,
+            )
+            .abs();
+        self.td_updates += 1;
+        if let Some(buffer) = &mut self.replay {
+            buffer.push(player.transition(reward));
+        }
+    }
+
+    /// Set the value of a finished game's final position for `player` under the reward scheme.
+    fn finish(&mut self, player: &AIPlayer) {
+        info!("Game ended at state:\n{}", player.curr_state);
+        let outcome = player.curr_state.is_won().unwrap();
+        let value = self.config.reward.terminal_value(&outcome);
+        self.values.insert(canonical::key(&player.curr_state), value);
+    }
+
+    /// Play a game against itself, updating both sides. Returns the game length in turns.
+    fn self_play(&mut self, starting_state: GameState, rng: &mut dyn RngCore) -> usize {
+        let epsilon = self.epsilon();
         let mut current_player = AIPlayer::new(starting_state);
         let mut opposing_player = {
-            let mut opp_starting_state = starting_state.clone();
+            let mut opp_starting_state = starting_state;
             opp_starting_state.swap_board();
             AIPlayer::new(opp_starting_state)
         };
@@ -53,49 +192,163 @@ pub fn sarsa_loop(
             let players_turn = if counter % 2 == 0 { 1 } else { 2 };
             info!("Turn {}, player {}'s turn", counter, players_turn);
 
-            let action = current_player.take_action(values, epsilon);
+            let action = current_player.take_action(self.values, epsilon, rng);
             opposing_player.opponent_plays(action);
+            counter += 1;
 
             if current_player.curr_state.is_ended() {
-                info!("Game ended at state:\n{}", current_player.curr_state);
-                let (tie, curr_player_win) = {
-                    let mut copy = current_player.curr_state.clone();
-                    copy.finalize_game();
-                    let diff = copy.houses[6] as i32 - copy.houses[13] as i32;
-                    (diff == 0, diff > 0)
-                };
-                if curr_player_win {
-                    values.insert(current_player.curr_state, 1.0);
-                    values.insert(opposing_player.curr_state, 0.0);
-                } else if !tie {
-                    values.insert(current_player.curr_state, 0.0);
-                    values.insert(opposing_player.curr_state, 1.0);
-                }
-                // The only reason this duplication has to happen here is because
-                // we need to first set the terminal states to {1.0, 0.0}
-                // otherwise we could move these four lines before the is_ended check
-                // and remove the duplication
+                // The terminal states have to be valued before the updates
+                self.finish(&current_player);
+                self.finish(&opposing_player);
                 debug!("TD Update for current player");
-                current_player.td_update(values, learning_rate, discount_factor);
+                self.update(&current_player);
                 debug!("TD Update for opposing player");
-                opposing_player.td_update(values, learning_rate, discount_factor);
-
-                counter += 1;
-                game_lengths.push(counter);
-                break;
+                self.update(&opposing_player);
+                return counter;
             }
             debug!("TD Update for current player");
-            current_player.td_update(values, learning_rate, discount_factor);
+            self.update(&current_player);
             debug!("TD Update for opposing player");
-            opposing_player.td_update(values, learning_rate, discount_factor);
-            counter += 1;
+            self.update(&opposing_player);
             std::mem::swap(&mut current_player, &mut opposing_player);
             info!(">>>>>>>>>>>>>>>>>");
         }
-        if (episode + 1) % print_rate == 0 {
-            dump_counter_stats(&game_lengths, false);
-            game_lengths.clear();
+    }
+
+    /// Play a game against a fixed opponent, updating only the learner's side as it would be in
+    /// self-play. Returns the game length in turns.
+    fn play_opponent(
+        &mut self,
+        opponent: &Opponent,
+        starting_state: GameState,
+        learner_first: bool,
+        rng: &mut dyn RngCore,
+    ) -> usize {
+        let epsilon = self.epsilon();
+        let mut swapped_state = starting_state;
+        swapped_state.swap_board();
+        let (learner_state, opponent_state) = if learner_first {
+            (starting_state, swapped_state)
+        } else {
+            (swapped_state, starting_state)
+        };
+        let mut learner = AIPlayer::new(learner_state);
+        let empty = HashMap::new();
+        let (mut opponent_player, opponent_values): (Box<dyn Player>, &ValueFunction) =
+            match opponent {
+                Opponent::Snapshot(values) => (Box::new(AIPlayer::new(opponent_state)), values),
+                Opponent::Baseline(strategy) => (
+                    Box::new(BaselinePlayer::new(opponent_state, *strategy)),
+                    &empty,
+                ),
+                Opponent::SelfPlay => unreachable!("self-play has no fixed opponent"),
+            };
+        let mut learner_to_move = learner_first;
+        let mut counter = 0;
+        loop {
+            if learner_to_move {
+                let action = learner.take_action(self.values, epsilon, rng);
+                opponent_player.opponent_plays(action);
+            } else {
+                let action = opponent_player.take_action(opponent_values, SNAPSHOT_EPSILON, rng);
+                learner.opponent_plays(action);
+            }
+            counter += 1;
+
+            if learner.curr_state.is_ended() {
+                self.finish(&learner);
+                self.update(&learner);
+                return counter;
+            }
+            self.update(&learner);
+            learner_to_move = !learner_to_move;
+        }
+    }
+}
+
+/// Train `values` by self-play (or against an opponent pool, if configured), counting updates
+/// per state in `visits` and keeping `observer` informed of progress. Returns the number of
+/// episodes played.
+pub fn sarsa_loop(
+    values: &mut HashMap<GameState, f64>,
+    visits: &mut VisitCounts,
+    starting_state: GameState,
+    config: &TrainingConfig,
+    rng: &mut dyn RngCore,
+    observer: &mut dyn TrainingObserver,
+) -> usize {
+    let discount_factor = config.discount_factor;
+    let episodes = config.episodes;
+    let print_rate = observer.report_every().max(1);
+    let start = Instant::now();
+    let mut game_lengths = Vec::with_capacity(print_rate);
+    let (mut td_error_sum, mut td_updates) = (0.0, 0);
+    let mut replay = config.replay.as_ref().map(ReplayBuffer::new);
+    let mut pool = config.pool.as_ref().map(OpponentPool::new);
+    let previous_episodes = observer.previous_episodes();
+
+    for episode in 0..episodes {
+        let mut learner = Learner {
+            values,
+            visits,
+            replay: replay.as_mut(),
+            config,
+            episode: previous_episodes + episode,
+            td_error_sum: 0.0,
+            td_updates: 0,
+        };
+        let epsilon = learner.epsilon();
+        let opponent = match &pool {
+            Some(pool) => pool.choose(rng),
+            None => Opponent::SelfPlay,
+        };
+        let game_length = match opponent {
+            Opponent::SelfPlay => learner.self_play(starting_state, rng),
+            opponent => {
+                let learner_first = rng.gen_bool(0.5);
+                learner.play_opponent(&opponent, starting_state, learner_first, rng)
+            }
+        };
+        game_lengths.push(game_length);
+        td_error_sum += learner.td_error_sum;
+        td_updates += learner.td_updates;
+
+        if let Some(pool) = &mut pool {
+            pool.after_episode(values, episode + 1);
+        }
+        if let Some(buffer) = &mut replay {
+            buffer.replay(rng, &mut |transition, weight| {
+                let visits = visits
+                    .get(&canonical::key(&transition.afterstate))
+                    .copied()
+                    .unwrap_or(1);
+                let learning_rate =
+                    weight * config.learning_rate.value(previous_episodes + episode, visits);
+                replay_update(values, transition, learning_rate, discount_factor)
+            });
+        }
+        let stop = !observer.after_episode(values, visits, episode + 1);
+        if (episode + 1) % print_rate == 0 || episode + 1 == episodes || stop {
+            let stats = IntervalStats {
+                episodes: episode + 1,
+                epsilon,
+                mean_td_error: td_error_sum / td_updates.max(1) as f64,
+                table_size: values.len(),
+                game_lengths: std::mem::take(&mut game_lengths),
+                elapsed: start.elapsed(),
+            };
+            observer.report(&stats, values);
+            (td_error_sum, td_updates) = (0.0, 0);
+        }
+        if let Some(every) = observer.checkpoint_every()
+            && every > 0
+            && (episode + 1) % every == 0
+        {
+            observer.checkpoint(values, visits, episode + 1);
+        }
+        if stop {
+            return episode + 1;
         }
     }
-    dump_counter_stats(&game_lengths, false);
+    episodes
 }
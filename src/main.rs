@@ -1,9 +1,8 @@
-use std::fs::File;
-use std::io::{Read, Write};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
 
 extern crate clap;
 extern crate postcard;
-use crate::postcard::{from_bytes, to_allocvec};
 use clap::{Parser, Subcommand};
 
 #[derive(Parser)]
@@ -14,6 +13,15 @@ struct Args {
     #[arg(short, long, value_name = "FILE")]
     train: Option<String>,
 
+    /// Seed for the random number generator (chosen at random and printed if omitted).
+    #[arg(short, long, value_name = "SEED")]
+    seed: Option<u64>,
+
+    /// Seeds in each house at the start of a game, for the tables trained and the games played
+    /// [default: 4].
+    #[arg(long, value_name = "N", default_value_t = 4, value_parser = clap::value_parser!(u8).range(1..=6))]
+    seeds_per_house: u8,
+
     #[command(subcommand)]
     command: Option<Commands>,
 }
@@ -24,19 +32,335 @@ enum Commands {
         /// Number of complete games [default: 10].
         #[arg(short, long, value_name = "GAMES", default_value_t = 10)]
         num_runs: usize,
-        /// Epsilon for non-greedy actions [default: 0.02].
-        #[arg(short, long, value_name = "EPS", default_value_t = 0.02)]
-        epsilon: f64,
+        /// Epsilon for non-greedy actions, as a number or schedule such as
+        /// `linear:0.2,0.01,10000`, `exp:0.2,0.999,0.01` or `step:0.2,0.5,1000` [default: 0.02].
+        #[arg(short, long, value_name = "EPS", default_value = "0.02", value_parser = schedule::parse_epsilon)]
+        epsilon: Schedule,
         /// Discount rate [default: 1.0].
         #[arg(short, long, value_name = "DISC", default_value_t = 1.0)]
         discount_rate: f64,
-        /// Learning rate [default: 0.05].
-        #[arg(short, long, value_name = "DISC", default_value_t = 0.05)]
-        learning_rate: f64,
+        /// Learning rate, as a number or schedule (as for epsilon, plus `visits[:MIN]` for a 1/n
+        /// rate from per-state visit counts) [default: 0.05].
+        #[arg(short, long, value_name = "RATE", default_value = "0.05")]
+        learning_rate: Schedule,
+        /// What to reward: `winloss` (1/0/0.5 for a win/loss/draw), `margin` (final store
+        /// margin) or `shaped` (win/loss plus store margin, credited as it is banked)
+        /// [default: winloss].
+        #[arg(long, value_name = "SCHEME", default_value = "winloss")]
+        reward: reward::RewardScheme,
+        /// Continue training from the existing training datafile instead of starting fresh.
+        #[arg(short, long)]
+        resume: bool,
+        /// Save a checkpoint of the training datafile every N games.
+        #[arg(short, long, value_name = "N")]
+        checkpoint_every: Option<usize>,
+        /// Log training metrics to FILE, as CSV if it ends in `.csv` and JSON lines otherwise.
+        #[arg(long, value_name = "FILE")]
+        metrics: Option<PathBuf>,
+        /// Games between metrics reports [default: 1000].
+        #[arg(long, value_name = "N", default_value_t = 1000)]
+        report_every: usize,
+        /// Agent to evaluate against at each metrics report [default: random].
+        #[arg(long, value_name = "AGENT", default_value = "random")]
+        baseline: AgentSpec,
+        /// Games against the baseline per report, 0 to skip evaluation [default: 20].
+        #[arg(long, value_name = "GAMES", default_value_t = 20)]
+        baseline_games: usize,
+        /// Keep the last N transitions for experience replay (off unless given).
+        #[arg(long, value_name = "N")]
+        replay_buffer: Option<usize>,
+        /// Transitions replayed after each game [default: 32].
+        #[arg(long, value_name = "N", default_value_t = 32)]
+        replay_batch: usize,
+        /// Replay sampling: `uniform` or `prioritized[:ALPHA,BETA]` [default: uniform].
+        #[arg(long, value_name = "SAMPLING", default_value = "uniform")]
+        replay_sampling: replay::Sampling,
+        /// Train against a mix of opponents instead of only itself, e.g.
+        /// `self=0.5,snapshot=0.3,random=0.1,alphabeta:2=0.1`. `snapshot` plays a frozen copy of
+        /// the table from earlier in the run.
+        #[arg(long, value_name = "MIX")]
+        opponents: Option<pool::OpponentMix>,
+        /// Games between snapshots added to the opponent pool [default: 1000].
+        #[arg(long, value_name = "N", default_value_t = 1000)]
+        snapshot_every: usize,
+        /// Snapshots kept in the opponent pool [default: 5].
+        #[arg(long, value_name = "N", default_value_t = 5)]
+        max_snapshots: usize,
+        /// Show a live dashboard of training progress, with pause and checkpoint keys.
+        #[arg(long)]
+        tui: bool,
     },
     Play {},
     /// Play with TUI interface showing move analysis
-    PlayTUI {},
+    PlayTUI {
+        /// Start with two people taking turns at this terminal chosen on the setup screen.
+        #[arg(long)]
+        hot_seat: bool,
+    },
+    /// Pit two agents against each other and report win rates
+    Eval {
+        /// Agent to evaluate: `table[:FILE]`, `random`, `greedy` or `alphabeta:DEPTH`.
+        #[arg(default_value = "table")]
+        agent: AgentSpec,
+        /// Opponent, in the same format as the agent.
+        #[arg(default_value = "random")]
+        opponent: AgentSpec,
+        /// Number of games, split evenly between both seatings [default: 100].
+        #[arg(short = 'n', long, value_name = "GAMES", default_value_t = 100)]
+        num_games: usize,
+        /// Random turns played at the start of each pair of games, for variety [default: 2].
+        #[arg(short, long, value_name = "PLIES", default_value_t = 2)]
+        opening_plies: usize,
+    },
+    /// Watch two agents play in the TUI, with playback controls
+    Watch {
+        /// Agent moving first: `table[:FILE]`, `random`, `greedy` or `alphabeta:DEPTH`.
+        #[arg(default_value = "table")]
+        agent: AgentSpec,
+        /// Its opponent, in the same format.
+        #[arg(default_value = "alphabeta:4")]
+        opponent: AgentSpec,
+        /// Milliseconds between moves when playing [default: 800].
+        #[arg(short, long, value_name = "MS", default_value_t = 800)]
+        delay: u64,
+    },
+    /// Rate several agents by playing them against each other
+    Tournament {
+        /// Agents to enter, each `table[:FILE]`, `random`, `greedy` or `alphabeta:DEPTH`.
+        #[arg(required = true, num_args = 2..)]
+        agents: Vec<AgentSpec>,
+        /// Pairing format: `roundrobin[:CYCLES]` or `swiss:ROUNDS` [default: roundrobin].
+        #[arg(short, long, default_value = "roundrobin")]
+        format: tournament::Format,
+        /// Rating system: `elo[:K]` or `glicko` [default: elo].
+        #[arg(short, long, default_value = "elo")]
+        rating: tournament::RatingSystem,
+        /// Games per pairing each round, split evenly between both seatings [default: 20].
+        #[arg(short = 'n', long, value_name = "GAMES", default_value_t = 20)]
+        num_games: usize,
+        /// Random turns played at the start of each pair of games, for variety [default: 2].
+        #[arg(short, long, value_name = "PLIES", default_value_t = 2)]
+        opening_plies: usize,
+        /// File to write the standings and crosstable to [default: tournament.txt].
+        #[arg(long, value_name = "FILE", default_value = "tournament.txt")]
+        output: PathBuf,
+    },
+    /// Pack the training datafile into a compact, memory-mapped table for play and evaluation
+    Compact {
+        /// File to write the compact table to.
+        output: PathBuf,
+        /// How values are stored: `f32`, or `u16` quantized over the table's range
+        /// [default: f32].
+        #[arg(long, default_value = "f32")]
+        encoding: compact::Encoding,
+        /// `dense` (a slot for every position), `sparse` (stored positions only) or `auto` for
+        /// the smaller [default: auto].
+        #[arg(long, default_value = "auto")]
+        layout: compact::Layout,
+    },
+    /// Speak a line-based engine protocol on standard input and output, for other frontends
+    Engine {
+        /// Turns searched ahead by a plain `go` [default: 1].
+        #[arg(short, long, value_name = "TURNS", default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..=engine::MAX_DEPTH as i64))]
+        depth: u32,
+    },
+    /// Serve a JSON API over HTTP for analysing positions and playing against the training
+    /// datafile
+    Serve {
+        /// Address to listen on; the default only accepts connections from this machine.
+        #[arg(long, default_value = "127.0.0.1")]
+        host: String,
+        /// Port to listen on [default: 8080].
+        #[arg(short, long, default_value_t = 8080)]
+        port: u16,
+    },
+    /// Host a game over TCP and wait for an opponent to join
+    Host {
+        /// Address to listen on; the default only accepts opponents on this machine, so pass
+        /// `--host 0.0.0.0` to play across the network.
+        #[arg(long, default_value = "127.0.0.1")]
+        host: String,
+        /// Port to listen on [default: 7777].
+        #[arg(short, long, default_value_t = net::DEFAULT_PORT)]
+        port: u16,
+        /// Let the guest move first.
+        #[arg(long)]
+        guest_first: bool,
+        /// Have the training datafile play this side instead of a person.
+        #[arg(long, conflicts_with = "tui")]
+        ai: bool,
+        /// Play in the TUI, with move analysis from the training datafile.
+        #[arg(long)]
+        tui: bool,
+    },
+    /// Join a game hosted with `mancala host`
+    Join {
+        /// Where the game is hosted, as HOST:PORT (the port defaults to 7777).
+        address: String,
+        /// Have the training datafile play this side instead of a person.
+        #[arg(long, conflicts_with = "tui")]
+        ai: bool,
+        /// Play in the TUI, with move analysis from the training datafile.
+        #[arg(long)]
+        tui: bool,
+    },
+    /// Inspect, export, merge or prune value files
+    Model {
+        #[command(subcommand)]
+        command: ModelCommand,
+    },
+    /// Train and evaluate many hyperparameter settings, ranking them in a report
+    ///
+    /// The best configuration's table is only saved when --best-model is given.
+    Sweep {
+        /// Epsilons to try, as numbers or schedules [default: 0.02].
+        #[arg(short, long, value_name = "EPS", num_args = 1.., default_value = "0.02", value_parser = schedule::parse_epsilon)]
+        epsilon: Vec<Schedule>,
+        /// Learning rates to try, as numbers or schedules [default: 0.05].
+        #[arg(short, long, value_name = "RATE", num_args = 1.., default_value = "0.05")]
+        learning_rate: Vec<Schedule>,
+        /// Discount rates to try [default: 1.0].
+        #[arg(short, long, value_name = "DISC", num_args = 1.., default_value = "1.0")]
+        discount_rate: Vec<f64>,
+        /// Numbers of training games to try [default: 1000].
+        #[arg(short, long, value_name = "GAMES", num_args = 1.., default_value = "1000")]
+        num_runs: Vec<usize>,
+        /// Reward schemes to try [default: winloss].
+        #[arg(long, value_name = "SCHEME", num_args = 1.., default_value = "winloss")]
+        reward: Vec<reward::RewardScheme>,
+        /// Try every combination (`grid`) or N random ones (`random:N`) [default: grid].
+        #[arg(long, default_value = "grid")]
+        search: sweep::Search,
+        /// Configurations to train at once [default: number of CPUs].
+        #[arg(short, long, value_name = "N")]
+        jobs: Option<usize>,
+        /// Agent each configuration is evaluated against [default: random].
+        #[arg(long, value_name = "AGENT", default_value = "random")]
+        baseline: AgentSpec,
+        /// Evaluation games per configuration, split evenly between both seatings [default: 200].
+        #[arg(long, value_name = "GAMES", default_value_t = 200)]
+        eval_games: usize,
+        /// File to write the ranked report to [default: sweep.txt].
+        #[arg(long, value_name = "FILE", default_value = "sweep.txt")]
+        output: PathBuf,
+        /// File to save the best configuration's model to; left unsaved without it.
+        #[arg(long, value_name = "FILE")]
+        best_model: Option<PathBuf>,
+    },
+}
+
+#[derive(Subcommand)]
+enum ModelCommand {
+    /// Summarize the training datafile: its size, values, and coverage by seeds in play and game
+    /// phase
+    Info {},
+    /// Show the stored value of a position and of each move from it
+    Query {
+        /// Board with the player to move first: their six houses and store, then the opponent's,
+        /// e.g. `4,4,4,4,4,4,0,4,4,4,4,4,4,0`.
+        position: mancala::GameState,
+    },
+    /// Write every entry of the training datafile as JSON or CSV
+    Export {
+        /// File to write to, in the format given or else by extension (`.csv` for CSV) [default:
+        /// standard output, as JSON].
+        output: Option<PathBuf>,
+        /// Write JSON: the metadata and a list of entries.
+        #[arg(long, conflicts_with = "csv")]
+        json: bool,
+        /// Write CSV: one row per entry.
+        #[arg(long)]
+        csv: bool,
+    },
+    /// Combine value files trained for the same game into one
+    Merge {
+        /// Value files to merge.
+        #[arg(required = true, num_args = 2..)]
+        inputs: Vec<PathBuf>,
+        /// File to write the merged table to.
+        #[arg(short, long, value_name = "FILE")]
+        output: PathBuf,
+        /// How to combine values stored in several files: `average`, or `visits` to weight each
+        /// by its update count [default: average].
+        #[arg(short, long, default_value = "average")]
+        weighting: model::Weighting,
+    },
+    /// Drop rarely visited positions from the training datafile
+    Prune {
+        /// Keep positions updated at least N times; finished games are always kept.
+        #[arg(short, long, value_name = "N")]
+        min_visits: u32,
+        /// File to write the pruned table to [default: the training datafile].
+        #[arg(short, long, value_name = "FILE")]
+        output: Option<PathBuf>,
+    },
+}
+
+/// Load the value table or compact table at `path`, which has to be for games under `rules`.
+fn load_table(path: &Path, rules: mancala::Rules) -> io::Result<Box<dyn ValueTable>> {
+    let mut magic = [0; 4];
+    let is_compact = std::fs::File::open(path)?.read_exact(&mut magic).is_ok()
+        && &magic == compact::MAGIC;
+    let (table, table_rules): (Box<dyn ValueTable>, _) = if is_compact {
+        let table = compact::CompactTable::load(path)?;
+        let table_rules = table.rules();
+        (Box::new(table), table_rules)
+    } else {
+        let file = persist::load(path)?;
+        (Box::new(file.values), file.metadata.rules)
+    };
+    persist::check_rules(table_rules, rules)?;
+    Ok(table)
+}
+
+/// Load the value table an agent plays from, or an empty one for agents that need none.
+fn load_agent_table(spec: &AgentSpec, train_file: &Path, rules: mancala::Rules) -> Box<dyn ValueTable> {
+    match spec {
+        AgentSpec::Table(path) => {
+            let path = path.as_deref().unwrap_or(train_file);
+            let values = or_exit(
+                load_table(path, rules),
+                &format!("loading {}", path.display()),
+            );
+            println!("Loaded {} values from {}", values.entries(), path.display());
+            values
+        }
+        AgentSpec::Baseline(_) => Box::new(HashMap::new()),
+    }
+}
+
+/// Unwrap `result`, or report what went wrong while `doing` it and exit.
+fn or_exit<T>(result: io::Result<T>, doing: &str) -> T {
+    result.unwrap_or_else(|err| {
+        eprintln!("Error {}: {}", doing, err);
+        // Only a table that is not there yet wants training first, not a missing output directory
+        if err.kind() == io::ErrorKind::NotFound && doing.starts_with("loading") {
+            eprintln!("Train a table first with `mancala train`, or pick one with --train FILE");
+        }
+        std::process::exit(1);
+    })
+}
+
+/// Play a network game on this side: in the TUI if `tui`, by the table if one is given without
+/// it, and otherwise by whoever is at the terminal.
+fn play_network(mut peer: net::Peer, table: Option<Box<dyn ValueTable>>, tui: bool, mut rng: StdRng) {
+    match table {
+        Some(table) if tui => {
+            if let Err(err) = tui::run_network_tui(peer, table, rng) {
+                eprintln!("Error running TUI: {}", err);
+            }
+        }
+        Some(table) => or_exit(
+            net::play_cli(&mut peer, &mut |state| {
+                Ok(state.pick_action(0.0, table.as_ref(), &mut rng).0)
+            }),
+            "playing",
+        ),
+        None => or_exit(
+            net::play_cli(&mut peer, &mut |state| net::prompt_move(state)),
+            "playing",
+        ),
+    }
 }
 
 #[macro_use]
@@ -44,12 +368,40 @@ extern crate log;
 extern crate env_logger;
 extern crate rand;
 
+use rand::SeedableRng;
+use rand::rngs::StdRng;
+use eval::AgentSpec;
+use mancala::ValueTable;
+use schedule::Schedule;
 use std::collections::HashMap;
 
+mod analysis;
+mod animation;
+mod canonical;
+mod compact;
+mod engine;
+mod eval;
+mod index;
 mod learning;
 mod mancala;
+mod metrics;
+mod model;
+mod net;
 mod packed_actions;
+mod persist;
 mod player;
+mod pool;
+mod replay;
+mod reward;
+mod schedule;
+mod search;
+mod serve;
+mod session;
+mod setup;
+mod spectate;
+mod sweep;
+mod tournament;
+mod train_tui;
 mod tui;
 
 fn main() {
@@ -57,15 +409,30 @@ fn main() {
     info!("Hello, mancala!");
     let args = Args::parse();
 
-    let starting_state = mancala::GameState::new(4);
-    println!("{}", starting_state);
+    // Model commands play no games, and these two may be using standard output for their own ends
+    let quiet = matches!(
+        args.command,
+        Some(Commands::Model { .. } | Commands::Engine { .. })
+    );
+    let seed = args.seed.unwrap_or_else(rand::random);
+    if !quiet {
+        println!("Using random seed {}", seed);
+    }
+    let mut rng = StdRng::seed_from_u64(seed);
+    let train_file = PathBuf::from(args.train.as_deref().unwrap_or("train.dat"));
+
+    let rules = mancala::Rules::Kalah {
+        seeds: args.seeds_per_house,
+    };
+    let starting_state = rules.starting_state();
+    let loading = format!("loading {}", train_file.display());
+    if !quiet {
+        println!("{}", starting_state);
+    }
     match &args.command {
         Some(Commands::Play {}) => {
-            let mut f: File = File::open(args.train.unwrap_or("train.dat".to_string())).unwrap();
-            let mut encoded = Vec::new();
-            f.read_to_end(&mut encoded).unwrap();
-            let mut value_fun: HashMap<mancala::GameState, f64> = from_bytes(&encoded).unwrap();
-            println!("Number of values in hash: {}", value_fun.len());
+            let value_fun = or_exit(load_table(&train_file, rules), &loading);
+            println!("Number of values in hash: {}", value_fun.entries());
             println!();
             println!("Here are the first possible actions and their values: ");
             for action in starting_state.gen_actions() {
@@ -75,7 +442,7 @@ fn main() {
                     "\n----------------\n{}:\n{}\nqval: {:?}\n",
                     action,
                     state,
-                    value_fun.get(&state)
+                    canonical::value(value_fun.as_ref(), &state)
                 );
             }
             println!("\n----------------\n");
@@ -83,22 +450,27 @@ fn main() {
             use player::{AIPlayer, HumanPlayer, Player};
             let p1 = Box::new(HumanPlayer::new(starting_state));
             let p2 = Box::new({
-                let mut opp_starting_state = starting_state.clone();
+                let mut opp_starting_state = starting_state;
                 opp_starting_state.swap_board();
                 AIPlayer::new(opp_starting_state)
             });
 
-            player::play_loop(p1 as Box<dyn Player>, p2 as Box<dyn Player>, &mut value_fun);
+            player::play_loop(
+                p1 as Box<dyn Player>,
+                p2 as Box<dyn Player>,
+                value_fun.as_ref(),
+                &mut rng,
+            );
         }
-        Some(Commands::PlayTUI {}) => {
-            let mut f: File = File::open(args.train.unwrap_or("train.dat".to_string())).unwrap();
-            let mut encoded = Vec::new();
-            f.read_to_end(&mut encoded).unwrap();
-            let value_fun: HashMap<mancala::GameState, f64> = from_bytes(&encoded).unwrap();
-            println!("Number of values in hash: {}", value_fun.len());
+        Some(Commands::PlayTUI { hot_seat }) => {
             println!("Starting TUI interface...");
-            
-            if let Err(err) = tui::run_tui(starting_state, &value_fun) {
+            let mut game_setup = setup::GameSetup::new(train_file.clone());
+            game_setup.rules = rules;
+            if *hot_seat {
+                game_setup.opponent = setup::OpponentKind::HotSeat;
+            }
+            let load = |path: &Path, rules| load_table(path, rules);
+            if let Err(err) = tui::run_tui(game_setup, &load, rng) {
                 eprintln!("Error running TUI: {}", err);
             }
         }
@@ -107,37 +479,479 @@ fn main() {
             epsilon,
             discount_rate,
             learning_rate,
+            reward,
+            resume,
+            checkpoint_every,
+            metrics,
+            report_every,
+            baseline,
+            baseline_games,
+            replay_buffer,
+            replay_batch,
+            replay_sampling,
+            opponents,
+            snapshot_every,
+            max_snapshots,
+            tui,
         }) => {
-            let mut value_fun: HashMap<mancala::GameState, f64> = HashMap::with_capacity(1_000);
-            learning::sarsa_loop(
-                &mut value_fun,
-                starting_state,
-                *epsilon,
-                *learning_rate,
-                *discount_rate,
-                *num_runs,
+            let config = learning::TrainingConfig {
+                epsilon: epsilon.clone(),
+                learning_rate: learning_rate.clone(),
+                discount_factor: *discount_rate,
+                episodes: *num_runs,
+                reward: *reward,
+                replay: replay_buffer
+                    .filter(|&capacity| capacity > 0)
+                    .map(|capacity| replay::ReplayConfig {
+                        capacity,
+                        batch_size: *replay_batch,
+                        sampling: *replay_sampling,
+                    }),
+                pool: opponents.as_ref().map(|mix| pool::PoolConfig {
+                    mix: mix.clone(),
+                    snapshot_every: *snapshot_every,
+                    max_snapshots: *max_snapshots,
+                }),
+            };
+            let (mut session, mut value_fun, mut visits) = or_exit(
+                session::TrainingSession::start(&train_file, *resume, seed, &config, rules),
+                &loading,
             );
+            session.checkpoint_every = *checkpoint_every;
+            session.report_every = *report_every;
+            if let Some(path) = metrics {
+                session.metrics = Some(or_exit(
+                    metrics::MetricsLog::open(path, *resume),
+                    &format!("opening {}", path.display()),
+                ));
+            }
+            if metrics.is_some() || *tui {
+                let values = match baseline {
+                    AgentSpec::Table(path) => {
+                        let path = path.as_deref().unwrap_or(&train_file);
+                        or_exit(
+                            load_table(path, rules),
+                            &format!("loading baseline {}", path.display()),
+                        )
+                    }
+                    AgentSpec::Baseline(_) => Box::new(HashMap::new()),
+                };
+                session.baseline = Some(session::Baseline {
+                    spec: baseline.clone(),
+                    values,
+                    pairs: baseline_games.div_ceil(2),
+                    rng: StdRng::seed_from_u64(seed.wrapping_add(1)),
+                });
+            }
+            let episodes;
+            if *tui {
+                (session, value_fun, visits, episodes) = or_exit(
+                    train_tui::run_training_tui(
+                        session,
+                        value_fun,
+                        visits,
+                        starting_state,
+                        config,
+                        rng,
+                    ),
+                    "running the training dashboard",
+                );
+            } else {
+                episodes = learning::sarsa_loop(
+                    &mut value_fun,
+                    &mut visits,
+                    starting_state,
+                    &config,
+                    &mut rng,
+                    &mut session,
+                );
+            }
 
             println!("Number of entries in value function: {}", value_fun.len());
 
             let mut vals = value_fun.iter().collect::<Vec<_>>();
-            vals.sort_by(|a, b| b.1.partial_cmp(a.1).unwrap());
+            vals.sort_by(|a, b| b.1.total_cmp(a.1));
             println!("Here's a few of the top values and states:");
             for pair in vals.iter().take(2) {
                 println!("\n#########\n{}:\n", pair.1);
                 println!("{}", pair.0);
             }
-            vals.sort_by(|a, b| a.1.partial_cmp(b.1).unwrap());
+            vals.sort_by(|a, b| a.1.total_cmp(b.1));
             println!("Here's a few of the bottom values and states:");
             for pair in vals.iter().take(2) {
                 println!("\n#########\nValue: {}:\n{}", pair.1, pair.0);
             }
 
-            let encoded: Vec<u8> = to_allocvec(&value_fun).unwrap();
-            let mut f: File = File::create(args.train.unwrap_or("train.dat".to_string())).unwrap();
-            f.write_all(&encoded).unwrap();
-            drop(f);
+            or_exit(
+                session.save(&value_fun, &visits, episodes),
+                &format!("saving {}", train_file.display()),
+            );
+        }
+        Some(Commands::Eval {
+            agent,
+            opponent,
+            num_games,
+            opening_plies,
+        }) => {
+            let load = |spec| load_agent_table(spec, &train_file, rules);
+            let (agent_values, opponent_values) = (load(agent), load(opponent));
+            let a = eval::Agent {
+                spec: agent.clone(),
+                values: agent_values.as_ref(),
+            };
+            let b = eval::Agent {
+                spec: opponent.clone(),
+                values: opponent_values.as_ref(),
+            };
+            println!("Evaluating {} against {}", agent, opponent);
+            let stats = eval::evaluate(
+                &a,
+                &b,
+                starting_state,
+                num_games.div_ceil(2),
+                *opening_plies,
+                &mut rng,
+            );
+            println!("{}", stats);
+        }
+        Some(Commands::Watch {
+            agent,
+            opponent,
+            delay,
+        }) => {
+            let load = |spec| load_agent_table(spec, &train_file, rules);
+            let (agent_values, opponent_values) = (load(agent), load(opponent));
+            let agents = [
+                eval::Agent {
+                    spec: agent.clone(),
+                    values: agent_values.as_ref(),
+                },
+                eval::Agent {
+                    spec: opponent.clone(),
+                    values: opponent_values.as_ref(),
+                },
+            ];
+            let delay = std::time::Duration::from_millis(*delay);
+            if let Err(err) = spectate::run_spectator(agents, starting_state, delay, rng) {
+                eprintln!("Error running TUI: {}", err);
+            }
+        }
+        Some(Commands::Tournament {
+            agents,
+            format,
+            rating,
+            num_games,
+            opening_plies,
+            output,
+        }) => {
+            // Name table agents by their file so checkpoints can be told apart
+            let specs: Vec<AgentSpec> = agents
+                .iter()
+                .map(|spec| match spec {
+                    AgentSpec::Table(None) => AgentSpec::Table(Some(train_file.clone())),
+                    spec => spec.clone(),
+                })
+                .collect();
+            let tables: Vec<Box<dyn ValueTable>> = specs
+                .iter()
+                .map(|spec| match spec {
+                    AgentSpec::Table(Some(path)) => or_exit(
+                        load_table(path, rules),
+                        &format!("loading {}", path.display()),
+                    ),
+                    _ => Box::new(HashMap::new()),
+                })
+                .collect();
+            let entrants: Vec<eval::Agent> = specs
+                .into_iter()
+                .zip(&tables)
+                .map(|(spec, values)| eval::Agent {
+                    spec,
+                    values: values.as_ref(),
+                })
+                .collect();
+            let result = tournament::run(
+                &entrants,
+                *format,
+                *rating,
+                starting_state,
+                num_games.div_ceil(2),
+                *opening_plies,
+                &mut rng,
+            );
+            println!("{}", result);
+            or_exit(
+                std::fs::write(output, result.to_string()),
+                &format!("writing {}", output.display()),
+            );
+            println!("Results written to {}", output.display());
+        }
+        Some(Commands::Sweep {
+            epsilon,
+            learning_rate,
+            discount_rate,
+            num_runs,
+            reward,
+            search,
+            jobs,
+            baseline,
+            eval_games,
+            output,
+            best_model,
+        }) => {
+            let space = sweep::SearchSpace {
+                epsilon: epsilon.clone(),
+                learning_rate: learning_rate.clone(),
+                discount_factor: discount_rate.clone(),
+                episodes: num_runs.clone(),
+                reward: reward.clone(),
+            };
+            let configs = space.configs(*search, &mut rng);
+            let baseline_values = match baseline {
+                AgentSpec::Table(path) => {
+                    let path = path.as_deref().unwrap_or(&train_file);
+                    or_exit(
+                        load_table(path, rules),
+                        &format!("loading baseline {}", path.display()),
+                    )
+                }
+                AgentSpec::Baseline(_) => Box::new(HashMap::new()),
+            };
+            let baseline_agent = eval::Agent {
+                spec: baseline.clone(),
+                values: baseline_values.as_ref(),
+            };
+            let jobs = jobs.unwrap_or_else(|| {
+                std::thread::available_parallelism().map_or(1, |jobs| jobs.get())
+            });
+            println!(
+                "Training {} configurations on {} threads",
+                configs.len(),
+                jobs.min(configs.len())
+            );
+            let (result, best) = sweep::run(
+                &configs,
+                &baseline_agent,
+                starting_state,
+                eval_games.div_ceil(2),
+                seed,
+                jobs,
+            );
+            println!("{}", result);
+            or_exit(
+                std::fs::write(output, result.to_string()),
+                &format!("writing {}", output.display()),
+            );
+            println!("Report written to {}", output.display());
+            match (best, best_model) {
+                (Some(best), Some(best_model)) => {
+                    let config = &best.trial.config;
+                    let saving = format!("saving {}", best_model.display());
+                    let (mut session, _, _) = or_exit(
+                        session::TrainingSession::start(
+                            best_model,
+                            false,
+                            best.trial.seed,
+                            config,
+                            rules,
+                        ),
+                        &saving,
+                    );
+                    or_exit(
+                        session.save(&best.values, &best.visits, config.episodes),
+                        &saving,
+                    );
+                    println!("Best model saved to {}", best_model.display());
+                }
+                (Some(_), None) => println!("Pass --best-model FILE to keep the best model"),
+                (None, _) => {}
+            }
+        }
+        Some(Commands::Compact {
+            output,
+            encoding,
+            layout,
+        }) => {
+            let file = or_exit(persist::load(&train_file), &loading);
+            let table = compact::CompactTable::build(
+                &file.values,
+                file.metadata.rules,
+                *encoding,
+                *layout,
+            )
+            .unwrap_or_else(|err| {
+                eprintln!("Error packing {}: {}", train_file.display(), err);
+                std::process::exit(1);
+            });
+            or_exit(
+                table.save(output),
+                &format!("saving {}", output.display()),
+            );
+            println!("Wrote {}: {}", output.display(), table);
+            // Read it back the way play will, and report what packing cost in precision
+            let packed = or_exit(
+                compact::CompactTable::load(output),
+                &format!("checking {}", output.display()),
+            );
+            let mut checked = 0;
+            let mut max_error: f64 = 0.0;
+            let mismatch = |what: String| -> ! {
+                eprintln!("Error checking {}: {}", output.display(), what);
+                std::process::exit(1);
+            };
+            for (state, value) in packed.iter() {
+                let Some(original) = file.values.get(&state) else {
+                    mismatch(format!(
+                        "it has a position {} does not:\n{}",
+                        train_file.display(),
+                        state
+                    ));
+                };
+                checked += 1;
+                max_error = max_error.max((value - original).abs());
+            }
+            if checked != file.values.len() {
+                mismatch(format!(
+                    "it has {} entries where {} has {}",
+                    checked,
+                    train_file.display(),
+                    file.values.len()
+                ));
+            }
+            println!("Checked {} entries, largest error {:.2e}", checked, max_error);
+        }
+        Some(Commands::Engine { depth }) => {
+            let load = |path: &Path| load_table(path, rules);
+            let mut engine = or_exit(
+                engine::Engine::new(rules, train_file.clone(), &load, *depth),
+                &loading,
+            );
+            or_exit(
+                engine.run(&mut io::stdin().lock(), &mut io::stdout().lock()),
+                "running the engine",
+            );
+        }
+        Some(Commands::Serve { host, port }) => {
+            let table = or_exit(load_table(&train_file, rules), &loading);
+            let entries = table.entries();
+            let address = format!("{}:{}", host, port);
+            let listening = format!("listening on {}", address);
+            let listener = or_exit(std::net::TcpListener::bind(&address), &listening);
+            println!(
+                "Serving {} values from {} at http://{}",
+                entries,
+                train_file.display(),
+                address
+            );
+            let mut server = serve::Server::new(rules, table, rng);
+            or_exit(server.serve(&listener), &listening);
+        }
+        Some(Commands::Host {
+            host,
+            port,
+            guest_first,
+            ai,
+            tui,
+        }) => {
+            let table = (*ai || *tui).then(|| or_exit(load_table(&train_file, rules), &loading));
+            let address = format!("{}:{}", host, port);
+            let listening = format!("listening on {}", address);
+            let listener = or_exit(std::net::TcpListener::bind(&address), &listening);
+            println!("Waiting for an opponent to join at {}...", address);
+            let peer = or_exit(
+                net::Peer::host(listener, starting_state, *guest_first),
+                "waiting for an opponent",
+            );
+            play_network(peer, table, *tui, rng);
         }
+        Some(Commands::Join { address, ai, tui }) => {
+            let table = (*ai || *tui).then(|| or_exit(load_table(&train_file, rules), &loading));
+            let address = if address.contains(':') {
+                address.clone()
+            } else {
+                format!("{}:{}", address, net::DEFAULT_PORT)
+            };
+            let peer = or_exit(net::Peer::join(&address), &format!("joining {}", address));
+            play_network(peer, table, *tui, rng);
+        }
+        Some(Commands::Model { command }) => match command {
+            ModelCommand::Info {} => {
+                let file = or_exit(persist::load(&train_file), &loading);
+                print!("{}", model::Summary::new(&file));
+            }
+            ModelCommand::Query { position } => {
+                let file = or_exit(persist::load(&train_file), &loading);
+                print!("{}", model::Query::new(&file, *position));
+            }
+            ModelCommand::Export { output, json, csv } => {
+                let file = or_exit(persist::load(&train_file), &loading);
+                let format = if *csv {
+                    model::ExportFormat::Csv
+                } else if *json {
+                    model::ExportFormat::Json
+                } else {
+                    output
+                        .as_deref()
+                        .map_or(model::ExportFormat::Json, model::ExportFormat::from_path)
+                };
+                match output {
+                    Some(path) => {
+                        let writing = format!("writing {}", path.display());
+                        let mut out =
+                            io::BufWriter::new(or_exit(std::fs::File::create(path), &writing));
+                        or_exit(
+                            model::export(&file, format, &mut out).and_then(|_| out.flush()),
+                            &writing,
+                        );
+                        println!("Exported {} entries to {}", file.values.len(), path.display());
+                    }
+                    None => or_exit(
+                        model::export(&file, format, &mut io::stdout().lock()),
+                        "exporting",
+                    ),
+                }
+            }
+            ModelCommand::Merge {
+                inputs,
+                output,
+                weighting,
+            } => {
+                let files = inputs
+                    .iter()
+                    .map(|path| or_exit(persist::load(path), &format!("loading {}", path.display())))
+                    .collect();
+                let merged = model::merge(files, *weighting).unwrap_or_else(|err| {
+                    eprintln!("Error merging: {}", err);
+                    std::process::exit(1);
+                });
+                or_exit(
+                    persist::save(output, &merged.metadata, &merged.values, &merged.visits),
+                    &format!("saving {}", output.display()),
+                );
+                println!(
+                    "Merged {} files into {}: {} entries",
+                    inputs.len(),
+                    output.display(),
+                    merged.values.len()
+                );
+            }
+            ModelCommand::Prune { min_visits, output } => {
+                let mut file = or_exit(persist::load(&train_file), &loading);
+                let dropped = model::prune(&mut file, *min_visits);
+                let output = output.as_ref().unwrap_or(&train_file);
+                or_exit(
+                    persist::save(output, &file.metadata, &file.values, &file.visits),
+                    &format!("saving {}", output.display()),
+                );
+                println!(
+                    "Dropped {} entries updated fewer than {} times, {} left, written to {}",
+                    dropped,
+                    min_visits,
+                    file.values.len(),
+                    output.display()
+                );
+            }
+        },
         None => {}
     }
 }
@@ -1,19 +1,94 @@
+use crate::canonical;
 use crate::packed_actions::{Action, ActionQueue, SubAction};
+use rand::Rng;
 use rand::seq::SliceRandom;
 
 extern crate serde;
 use self::serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::fmt::{self, Display, Formatter};
+use std::str::FromStr;
 
-#[derive(Debug, PartialEq)]
+/// Final totals, with any seeds left on the board swept into their owner's store.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Score {
+    pub p1: u8,
+    pub p2: u8,
+}
+
+impl Score {
+    /// Player one's total minus player two's.
+    pub fn margin(&self) -> i32 {
+        self.p1 as i32 - self.p2 as i32
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
 pub enum Outcome {
-    P1win,
-    P2win,
-    Tie,
+    P1win(Score),
+    P2win(Score),
+    Tie(Score),
+}
+
+impl Outcome {
+    pub fn score(&self) -> Score {
+        match *self {
+            Outcome::P1win(score) | Outcome::P2win(score) | Outcome::Tie(score) => score,
+        }
+    }
+}
+
+/// The variant of the game being played. Value files record the rules they were trained under,
+/// so a table is never used for a game it knows nothing about.
+#[derive(Debug, Eq, PartialEq, Copy, Clone, Serialize, Deserialize)]
+pub enum Rules {
+    /// Six houses a side, captures and extra turns, starting with `seeds` in every house.
+    Kalah { seeds: u8 },
+}
+
+impl Rules {
+    pub fn starting_state(&self) -> GameState {
+        match *self {
+            Rules::Kalah { seeds } => GameState::new(seeds),
+        }
+    }
+
+    /// Check that `state` could come up in a game under these rules: no more seeds than it
+    /// started with. Fewer is fine, as sowing a full lap of the board drops seeds (see
+    /// `StateIndex`). That also bounds every house, so sowing cannot overflow one.
+    pub fn check_board(&self, state: &GameState) -> Result<(), String> {
+        let total: u32 = self.starting_state().houses.iter().map(|&seeds| seeds as u32).sum();
+        let seeds: u32 = state.houses.iter().map(|&seeds| seeds as u32).sum();
+        if seeds > total {
+            return Err(format!(
+                "board holds {} seeds, but {} starts with only {}",
+                seeds, self, total
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl Display for Rules {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Rules::Kalah { seeds } => write!(f, "kalah with {} seeds per house", seeds),
+        }
+    }
+}
+
+/// What one sowing did, in order: the house emptied, each house a seed was dropped into, and any
+/// capture at the end, as (house the last seed landed in, the house opposite, seeds banked).
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sowing {
+    pub house: usize,
+    pub drops: Vec<usize>,
+    pub capture: Option<(usize, usize, u8)>,
+    /// Whether it ends in the store, earning another sowing.
+    pub renewing: bool,
 }
 
-#[derive(Debug, Eq, PartialEq, Hash, Copy, Clone, Serialize, Deserialize)]
+#[derive(Debug, Eq, PartialEq, Ord, PartialOrd, Hash, Copy, Clone, Serialize, Deserialize)]
 pub struct GameState {
     pub houses: [u8; 14],
 }
@@ -37,10 +112,10 @@ impl GameState {
             return true;
         }
         info!("Checking if game is ended: no... {}, {}", p1_tot, p2_tot);
-        return false;
+        false
     }
 
-    /// Is the game a winning final state for current player?
+    /// Is the game a winning final state for current player, and with what score?
     /// None here means the game is not done.
     pub fn is_won(&self) -> Option<Outcome> {
         let p1_tot: u8 = self.houses[..6].iter().sum();
@@ -48,15 +123,17 @@ impl GameState {
         if p1_tot != 0 && p2_tot != 0 {
             return None;
         }
-        let p1_tot = p1_tot + self.houses[6];
-        let p2_tot = p2_tot + self.houses[13];
+        let score = Score {
+            p1: p1_tot + self.houses[6],
+            p2: p2_tot + self.houses[13],
+        };
         use self::Outcome::*;
-        if p1_tot > p2_tot {
-            Some(P1win)
-        } else if p2_tot > p1_tot {
-            Some(P2win)
+        if score.p1 > score.p2 {
+            Some(P1win(score))
+        } else if score.p2 > score.p1 {
+            Some(P2win(score))
         } else {
-            Some(Tie)
+            Some(Tie(score))
         }
     }
 
@@ -86,7 +163,7 @@ impl GameState {
     /// Return a new game state when playing out a sequence of actions (a string of capturing
     /// moves)
     fn evaluate_to_new_state(&self, action_list: Action) -> GameState {
-        let mut new_state = self.clone();
+        let mut new_state = *self;
         new_state.evaluate_action(action_list);
         new_state
     }
@@ -106,8 +183,14 @@ impl GameState {
 
     /// Mutate the current game state when playing out a single subaction
     fn evaluate_subaction(&mut self, subaction: SubAction) {
+        self.sow(subaction);
+    }
+
+    /// Play out a single subaction, reporting where each seed went.
+    pub fn sow(&mut self, subaction: SubAction) -> Sowing {
         let action = subaction as usize;
         assert!(action != 6 && action != 13);
+        let renewing = self.is_renewing_subaction(subaction);
         let seeds = self.houses[action] as usize;
         // Pickup seeds from starting house
         self.houses[action] = 0;
@@ -116,24 +199,36 @@ impl GameState {
         // Offset is to handle skipping of the opponents
         // scoring house as we go around the loop
         let mut offset = 0;
+        let mut drops = Vec::with_capacity(seeds);
         for i in action + 1..end_house + 1 {
-            if i > 0 && i % 13 == 0 {
-                self.houses[0] += 1;
+            let house = if i > 0 && i % 13 == 0 {
                 offset += 1;
+                0
             } else {
-                self.houses[(i + offset) % 14] += 1;
-            }
+                (i + offset) % 14
+            };
+            self.houses[house] += 1;
+            drops.push(house);
         }
         // Capture rule
+        let mut capture = None;
         if end_house < 6 && self.houses[end_house] == 1 {
             // add to capture pile
             let opposing_house = 12 - end_house;
-            self.houses[6] += 1 + self.houses[opposing_house];
+            let captured = 1 + self.houses[opposing_house];
+            self.houses[6] += captured;
             // clear houses on both sides
             self.houses[end_house] = 0;
             self.houses[opposing_house] = 0;
+            capture = Some((end_house, opposing_house, captured));
             info!("Capture detected!");
         }
+        Sowing {
+            house: action,
+            drops,
+            capture,
+            renewing,
+        }
     }
 
     /// Determine if subaction is 'renewing' and grants another turn
@@ -141,35 +236,41 @@ impl GameState {
         self.houses[sub as usize] + sub == 6
     }
 
-    pub fn gen_actions(&self) -> ActionIter {
+    pub fn gen_actions(&self) -> ActionIter<'_> {
         ActionIter {
             action: Action::new(),
-            base_state: &self,
+            base_state: self,
             state_stack: Vec::new(),
         }
     }
 
-    pub fn pick_action(self, epsilon: f64, values: &ValueFunction) -> (Action, f64) {
+    /// Choose an action epsilon-greedily with respect to `values`. All randomness is drawn from
+    /// `rng` so that a seeded generator gives reproducible play.
+    pub fn pick_action<R: Rng + ?Sized>(
+        self,
+        epsilon: f64,
+        values: &dyn ValueTable,
+        rng: &mut R,
+    ) -> (Action, f64) {
         let choices: Vec<(Action, f64)> = self
             .gen_actions()
             .map(|action| (action, self.evaluate_to_new_state(action)))
             .map(|(action, possible_state)| {
-                (action, *values.get(&possible_state).unwrap_or(&0.5f64))
+                (action, canonical::value(values, &possible_state))
             })
             .collect();
         info!("Actions available to choose from:");
         for action in &choices {
             info!("\t{}, {}", action.0, action.1);
         }
-        if choices.len() == 0 {
+        if choices.is_empty() {
             println!("state: {}", self);
         }
-        assert!(choices.len() > 0);
+        assert!(!choices.is_empty());
         let mut best = &choices[0];
-        if rand::random::<f64>() < epsilon {
+        if rng.gen_bool(epsilon) {
             // randomly make a move
-            let mut rng = rand::thread_rng();
-            best = choices.choose(&mut rng).unwrap();
+            best = choices.choose(rng).unwrap();
         } else {
             for choice in &choices {
                 if choice.1 > best.1 {
@@ -177,26 +278,17 @@ impl GameState {
                 }
             }
         }
-        best.clone()
+        *best
     }
 
     /// 'Rotate' the board so player one and two are swapped
     pub fn swap_board(&mut self) {
-        let n = self.houses.len();
-        for i in 0..n / 2 {
-            let temp = self.houses[i];
-            self.houses[i] = self.houses[n / 2 + i];
-            self.houses[n / 2 + i] = temp;
-        }
+        let (p1_side, p2_side) = self.houses.split_at_mut(7);
+        p1_side.swap_with_slice(p2_side);
     }
 
     fn find_next_subaction(&self, search_start: SubAction) -> Option<SubAction> {
-        for index in search_start..6 {
-            if self.houses[index as usize] > 0 {
-                return Some(index);
-            }
-        }
-        None
+        (search_start..6).find(|&index| self.houses[index as usize] > 0)
     }
 }
 
@@ -209,7 +301,7 @@ pub struct ActionIter<'a> {
 impl<'a> ActionIter<'a> {
     fn get_current_state(&self) -> GameState {
         if self.state_stack.is_empty() {
-            self.base_state.clone()
+            *self.base_state
         } else {
             self.state_stack[self.state_stack.len() - 1]
         }
@@ -318,18 +410,73 @@ impl Display for GameState {
     }
 }
 
+/// A board written as its fourteen slots in order, separated by commas or spaces: the six
+/// houses of the player it belongs to, their store, then the opponent's six houses and store.
+/// The starting board is `4,4,4,4,4,4,0,4,4,4,4,4,4,0`.
+impl FromStr for GameState {
+    type Err = String;
+
+    fn from_str(spec: &str) -> Result<GameState, String> {
+        let slots = spec
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|slot| !slot.is_empty())
+            .map(|slot| {
+                slot.parse::<u8>()
+                    .map_err(|_| format!("invalid seed count '{}' in board '{}'", slot, spec))
+            })
+            .collect::<Result<Vec<u8>, String>>()?;
+        let houses = slots.try_into().map_err(|slots: Vec<u8>| {
+            format!("a board has 14 slots, not {}: '{}'", slots.len(), spec)
+        })?;
+        Ok(GameState { houses })
+    }
+}
+
 pub type ValueFunction = HashMap<GameState, f64>;
 
+/// Read access to afterstate values, however they are stored.
+pub trait ValueTable: Send + Sync {
+    /// The value stored under `state`, which should be a canonical key.
+    fn get_value(&self, state: &GameState) -> Option<f64>;
+    /// Number of values stored.
+    fn entries(&self) -> usize;
+}
+
+impl ValueTable for ValueFunction {
+    fn get_value(&self, state: &GameState) -> Option<f64> {
+        self.get(state).copied()
+    }
+
+    fn entries(&self) -> usize {
+        self.len()
+    }
+}
+
+/// Number of TD updates made to each state, used by count-based learning rates.
+pub type VisitCounts = HashMap<GameState, u32>;
+
 #[cfg(test)]
 mod test {
     use super::*;
-    use packed_actions::*;
+    use crate::packed_actions::*;
+    use crate::player::Player;
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
     use std::collections::HashMap;
     extern crate env_logger;
 
+    #[test]
+    fn test_parse_board() {
+        let start = GameState::new(4);
+        assert_eq!("4,4,4,4,4,4,0,4,4,4,4,4,4,0".parse::<GameState>(), Ok(start));
+        assert_eq!("4 4 4 4 4 4 0  4 4 4 4 4 4 0".parse::<GameState>(), Ok(start));
+        assert!("4,4,4,4,4,4,0".parse::<GameState>().is_err());
+        assert!("4,4,4,4,4,4,0,4,4,4,4,4,4,x".parse::<GameState>().is_err());
+    }
+
     #[test]
     fn test_action_iter() {
-        let _ = env_logger::init();
+        let _ = env_logger::try_init();
         let state = GameState::new(4);
         let actions = state.gen_actions().collect::<Vec<_>>();
         assert_eq!(actions.len(), 10);
@@ -396,60 +543,173 @@ mod test {
         assert_eq!(state.houses, expected);
     }
 
+    #[test]
+    fn test_check_board() {
+        let rules = Rules::Kalah { seeds: 4 };
+        assert!(rules.check_board(&GameState::new(4)).is_ok());
+        let moved: GameState = "0,5,5,5,5,4,0,4,4,4,4,4,4,0".parse().unwrap();
+        assert!(rules.check_board(&moved).is_ok());
+        assert!(rules.check_board(&GameState::new(5)).is_err());
+        let mut overfull = GameState::new(0);
+        overfull.houses[0] = 255;
+        assert!(rules.check_board(&overfull).is_err());
+    }
+
+    #[test]
+    fn test_sow() {
+        let mut state = GameState::new(4);
+        let sowing = state.sow(2);
+        assert_eq!(sowing.drops, vec![3, 4, 5, 6]);
+        assert!(sowing.renewing && sowing.capture.is_none());
+
+        // The capture from `test_capture_rules`
+        let mut state = GameState::new(4);
+        state.houses[4] = 0;
+        let sowing = state.sow(0);
+        assert_eq!(sowing.house, 0);
+        assert_eq!(sowing.drops, vec![1, 2, 3, 4]);
+        assert_eq!(sowing.capture, Some((4, 8, 5)));
+        assert!(!sowing.renewing);
+
+        // Round the board, past the opponent's store
+        let mut state = GameState::new(0);
+        state.houses[4] = 10;
+        let sowing = state.sow(4);
+        assert_eq!(sowing.drops, vec![5, 6, 7, 8, 9, 10, 11, 12, 0, 1]);
+    }
+
     #[test]
     fn pick_actions() {
+        let mut rng = StdRng::seed_from_u64(0);
         let mut value_fun: HashMap<GameState, f64> = HashMap::new();
         let mut state = GameState::new(4);
         let action = Action::singleton(3);
-        let mut good_state = state.clone();
+        let mut good_state = state;
         good_state.evaluate_action(action);
         value_fun.insert(good_state, 10.0);
-        assert_eq!(state.pick_action(0.0, &value_fun).0, action);
+        assert_eq!(state.pick_action(0.0, &value_fun, &mut rng).0, action);
         // Now after performing that option and swapping the board, it should be a
         // different set of evaluations (ie: our value_fun info will not be useful
         // for any of these particular actions)
         state.evaluate_action(action);
         state.swap_board();
-        let mut p2_good_state = state.clone();
+        let mut p2_good_state = state;
         p2_good_state.evaluate_action(Action::singleton(1));
         value_fun.insert(p2_good_state, 4.0);
-        println!("{:?}", state.pick_action(0.0, &value_fun));
-        assert_eq!(state.pick_action(0.0, &value_fun).0, Action::singleton(1));
+        println!("{:?}", state.pick_action(0.0, &value_fun, &mut rng));
+        assert_eq!(
+            state.pick_action(0.0, &value_fun, &mut rng).0,
+            Action::singleton(1)
+        );
 
         let mut mut_flag = false;
         for _ in 0..10 {
-            if state.pick_action(1.0, &value_fun).0 != Action::singleton(1) {
+            if state.pick_action(1.0, &value_fun, &mut rng).0 != Action::singleton(1) {
                 mut_flag = true;
             }
         }
-        assert_eq!(mut_flag, true);
+        assert!(mut_flag);
+    }
+
+    #[test]
+    fn test_seeded_pick_action() {
+        let value_fun: HashMap<GameState, f64> = HashMap::new();
+        let state = GameState::new(4);
+        let picks = |seed| {
+            let mut rng = StdRng::seed_from_u64(seed);
+            (0..20)
+                .map(|_| state.pick_action(1.0, &value_fun, &mut rng).0)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(picks(7), picks(7));
+        assert_ne!(picks(7), picks(8));
+    }
+
+    #[test]
+    fn test_seeded_training() {
+        let train = |seed| {
+            let mut rng = StdRng::seed_from_u64(seed);
+            let mut value_fun: HashMap<GameState, f64> = HashMap::new();
+            let config = crate::learning::TrainingConfig {
+                epsilon: "linear:0.5,0.0,10".parse().unwrap(),
+                learning_rate: "visits".parse().unwrap(),
+                discount_factor: 1.0,
+                episodes: 20,
+                reward: crate::reward::RewardScheme::WinLoss,
+                replay: None,
+                pool: None,
+            };
+            crate::learning::sarsa_loop(
+                &mut value_fun,
+                &mut HashMap::new(),
+                GameState::new(4),
+                &config,
+                &mut rng,
+                &mut (),
+            );
+            let mut entries = value_fun.into_iter().collect::<Vec<_>>();
+            entries.sort_by_key(|(state, _)| *state);
+            entries
+        };
+        assert_eq!(train(3), train(3));
+    }
+
+    #[test]
+    fn test_discounted_training() {
+        // Three forced turns to a tie, which both players should value alike
+        let start: GameState = "0,2,0,0,0,0,0,2,0,0,0,0,0,0".parse().unwrap();
+        let mut value_fun: HashMap<GameState, f64> = HashMap::new();
+        let config = crate::learning::TrainingConfig {
+            epsilon: "0".parse().unwrap(),
+            learning_rate: "0.5".parse().unwrap(),
+            discount_factor: 0.5,
+            episodes: 50,
+            reward: crate::reward::RewardScheme::WinLoss,
+            replay: None,
+            pool: None,
+        };
+        crate::learning::sarsa_loop(
+            &mut value_fun,
+            &mut HashMap::new(),
+            start,
+            &config,
+            &mut StdRng::seed_from_u64(0),
+            &mut (),
+        );
+        assert!(value_fun.len() >= 3);
+        for (state, value) in &value_fun {
+            assert!((value - 0.5).abs() < 1e-6, "{:?} valued {}", state, value);
+        }
+        let to_move = crate::canonical::value_to_move(&value_fun, &start);
+        assert!((to_move - 0.5).abs() < 1e-6);
     }
 
     #[test]
     fn test_end_game() {
         let mut state = GameState::new(4);
         assert_eq!(state.is_won(), None);
+        state.houses[..6].fill(0);
+        state.houses[6] = 4 * 6;
         state.finalize_game();
-        assert_eq!(state.is_won(), Some(Tie));
+        assert_eq!(state.is_won(), Some(Outcome::Tie(Score { p1: 24, p2: 24 })));
         state.houses[13] = 50;
-        assert_eq!(state.is_won(), Some(P2Win));
+        assert_eq!(state.is_won(), Some(Outcome::P2win(Score { p1: 24, p2: 50 })));
         state.houses[0] = 100;
-        assert_eq!(state.is_won(), Some(P1Win));
+        let outcome = state.is_won().unwrap();
+        assert_eq!(outcome, Outcome::P1win(Score { p1: 124, p2: 50 }));
+        assert_eq!(outcome.score().margin(), 74);
         state.swap_board();
-        assert_eq!(state.is_won(), Some(P2Win));
+        assert_eq!(state.is_won(), Some(Outcome::P2win(Score { p1: 50, p2: 124 })));
     }
 
     #[test]
     fn test_finalize_game() {
         let mut state = GameState::new(4);
+        state.houses[..6].fill(0);
         state.finalize_game();
         assert_eq!(state.houses[13], 4 * 6);
-        for i in 7..13 {
-            assert_eq!(state.houses[i], 0);
-        }
-        for i in 0..6 {
-            assert_eq!(state.houses[i], 4);
-        }
+        assert!(state.houses[7..13].iter().all(|&house| house == 0));
+        assert!(state.houses[..6].iter().all(|&house| house == 0));
         assert_eq!(state.houses[6], 0);
     }
 
@@ -469,13 +729,17 @@ mod test {
     #[test]
     fn test_player() {
         let mut state = GameState::new(4);
+        let mut rng = StdRng::seed_from_u64(0);
         let mut p1 = crate::player::AIPlayer::new(state);
         let mut value_fun: HashMap<GameState, f64> = HashMap::new();
         let action = Action::singleton(4);
         state.evaluate_action(action);
         value_fun.insert(state, 10.0);
 
-        assert_eq!(p1.take_action(&value_fun, 0.0), action);
-        p1.td_update(&mut value_fun, 0.2, 0.3);
+        assert_eq!(p1.take_action(&value_fun, 0.0, &mut rng), action);
+        let mut visits = HashMap::new();
+        p1.td_update(&mut value_fun, &mut visits, &|_| 0.2, 0.3, 0.0);
+        p1.td_update(&mut value_fun, &mut visits, &|_| 0.2, 0.3, 0.0);
+        assert_eq!(visits.get(&p1.last_state), Some(&2));
     }
 }
@@ -0,0 +1,192 @@
+use crate::learning::{GAME_LENGTH_BUCKETS, IntervalStats};
+
+extern crate serde;
+use self::serde::Serialize;
+use std::fs::OpenOptions;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+/// One line of the training metrics log, written every reporting interval.
+#[derive(Debug, Clone, Serialize)]
+pub struct MetricsRecord {
+    /// Total episodes trained into the value file, including earlier runs.
+    pub episodes: usize,
+    /// Seconds since the start of this run.
+    pub wall_time_secs: f64,
+    pub epsilon: f64,
+    pub mean_td_error: f64,
+    pub table_size: usize,
+    /// Agent the table was evaluated against, if any.
+    pub baseline: Option<String>,
+    /// Fraction of games won against the baseline; draws count as not won.
+    pub baseline_win_rate: Option<f64>,
+    pub mean_game_length: f64,
+    /// Games per `GAME_LENGTH_BUCKETS` bucket during the interval.
+    pub game_length_histogram: Vec<usize>,
+}
+
+impl MetricsRecord {
+    pub fn new(stats: &IntervalStats, previous_episodes: usize) -> MetricsRecord {
+        let games = stats.game_lengths.len().max(1) as f64;
+        MetricsRecord {
+            episodes: previous_episodes + stats.episodes,
+            wall_time_secs: stats.elapsed.as_secs_f64(),
+            epsilon: stats.epsilon,
+            mean_td_error: stats.mean_td_error,
+            table_size: stats.table_size,
+            baseline: None,
+            baseline_win_rate: None,
+            mean_game_length: stats.game_lengths.iter().sum::<usize>() as f64 / games,
+            game_length_histogram: crate::learning::game_length_histogram(&stats.game_lengths),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MetricsFormat {
+    Csv,
+    JsonLines,
+}
+
+impl MetricsFormat {
+    /// CSV for `.csv` files, JSON lines for anything else.
+    pub fn from_path(path: &Path) -> MetricsFormat {
+        match path.extension() {
+            Some(ext) if ext.eq_ignore_ascii_case("csv") => MetricsFormat::Csv,
+            _ => MetricsFormat::JsonLines,
+        }
+    }
+}
+
+/// Appends `MetricsRecord`s to a CSV or JSON-lines file.
+pub struct MetricsLog {
+    out: BufWriter<std::fs::File>,
+    format: MetricsFormat,
+}
+
+impl MetricsLog {
+    /// Open `path` for appending, or truncate it unless `append` is set. A CSV header is
+    /// written if the file starts out empty.
+    pub fn open(path: &Path, append: bool) -> io::Result<MetricsLog> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(append)
+            .write(true)
+            .truncate(!append)
+            .open(path)?;
+        let is_empty = file.metadata()?.len() == 0;
+        let mut log = MetricsLog {
+            out: BufWriter::new(file),
+            format: MetricsFormat::from_path(path),
+        };
+        if log.format == MetricsFormat::Csv && is_empty {
+            write!(
+                log.out,
+                "episodes,wall_time_secs,epsilon,mean_td_error,table_size,\
+                 baseline,baseline_win_rate,mean_game_length"
+            )?;
+            for bucket in GAME_LENGTH_BUCKETS.windows(2) {
+                write!(log.out, ",len_{}_{}", bucket[0], bucket[1])?;
+            }
+            writeln!(log.out)?;
+        }
+        Ok(log)
+    }
+
+    pub fn write(&mut self, record: &MetricsRecord) -> io::Result<()> {
+        match self.format {
+            MetricsFormat::JsonLines => {
+                serde_json::to_writer(&mut self.out, record)?;
+                writeln!(self.out)?;
+            }
+            MetricsFormat::Csv => {
+                write!(
+                    self.out,
+                    "{},{:.3},{},{},{},{},{},{}",
+                    record.episodes,
+                    record.wall_time_secs,
+                    record.epsilon,
+                    record.mean_td_error,
+                    record.table_size,
+                    csv_field(record.baseline.as_deref().unwrap_or("")),
+                    record
+                        .baseline_win_rate
+                        .map_or(String::new(), |win_rate| win_rate.to_string()),
+                    record.mean_game_length,
+                )?;
+                for count in &record.game_length_histogram {
+                    write!(self.out, ",{}", count)?;
+                }
+                writeln!(self.out)?;
+            }
+        }
+        // Flush every record so the log can be watched while training runs
+        self.out.flush()
+    }
+}
+
+/// Quote a CSV field if it holds a separator, quote or line break, as agent specs such as
+/// `table:a,b.json` can.
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_metrics_log() {
+        let stats = IntervalStats {
+            episodes: 10,
+            epsilon: 0.1,
+            mean_td_error: 0.25,
+            table_size: 100,
+            game_lengths: vec![12, 14, 31],
+            elapsed: Duration::from_millis(1500),
+        };
+        let mut record = MetricsRecord::new(&stats, 5);
+        assert_eq!(record.episodes, 15);
+        assert_eq!(record.game_length_histogram[2], 2);
+        record.baseline = Some("random".to_string());
+        record.baseline_win_rate = Some(0.75);
+
+        let dir = std::env::temp_dir();
+        let csv = dir.join(format!("mancala-metrics-{}.csv", std::process::id()));
+        let json = dir.join(format!("mancala-metrics-{}.jsonl", std::process::id()));
+        for append in [false, true] {
+            MetricsLog::open(&csv, append)
+                .unwrap()
+                .write(&record)
+                .unwrap();
+            MetricsLog::open(&json, append)
+                .unwrap()
+                .write(&record)
+                .unwrap();
+        }
+        let csv_lines: Vec<String> = std::fs::read_to_string(&csv)
+            .unwrap()
+            .lines()
+            .map(String::from)
+            .collect();
+        assert_eq!(csv_lines.len(), 3);
+        assert!(csv_lines[0].starts_with("episodes,wall_time_secs"));
+        assert!(csv_lines[1].starts_with("15,1.500,0.1,0.25,100,random,0.75,19"));
+        let json_lines = std::fs::read_to_string(&json).unwrap();
+        assert_eq!(json_lines.lines().count(), 2);
+        let parsed: serde_json::Value =
+            serde_json::from_str(json_lines.lines().next().unwrap()).unwrap();
+        assert_eq!(parsed["baseline_win_rate"], 0.75);
+        std::fs::remove_file(csv).unwrap();
+        std::fs::remove_file(json).unwrap();
+
+        assert_eq!(csv_field("alphabeta:4"), "alphabeta:4");
+        assert_eq!(csv_field("table:a,b.json"), "\"table:a,b.json\"");
+        assert_eq!(csv_field("table:say \"hi\""), "\"table:say \"\"hi\"\"\"");
+    }
+}
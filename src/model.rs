@@ -0,0 +1,636 @@
+use crate::canonical;
+use crate::mancala::{GameState, ValueFunction, VisitCounts};
+use crate::packed_actions::Action;
+use crate::persist::{TrainingMetadata, ValueFile};
+use crate::player::DEFAULT_STATE_VAL;
+
+extern crate serde;
+use self::serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::{self, Display, Formatter};
+use std::io::{self, Write};
+use std::path::Path;
+use std::str::FromStr;
+
+/// Buckets in the value histogram printed by `model info`.
+const HISTOGRAM_BUCKETS: usize = 10;
+/// Seeds in play grouped into each row of the coverage table.
+const SEEDS_PER_ROW: usize = 4;
+/// Width of the longest bar in the value histogram.
+const BAR_WIDTH: usize = 40;
+
+/// How far a game has got, judged by the share of its seeds already banked in the stores.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Phase {
+    /// Under a third of the seeds banked.
+    Opening,
+    /// Between a third and two thirds banked.
+    Middlegame,
+    /// Over two thirds banked, with both sides still able to move.
+    Endgame,
+    /// One side is empty.
+    Finished,
+}
+
+impl Phase {
+    pub fn of(state: &GameState) -> Phase {
+        if state.is_ended() {
+            return Phase::Finished;
+        }
+        let total: usize = state.houses.iter().map(|&seeds| seeds as usize).sum();
+        let banked = 3 * (state.houses[6] as usize + state.houses[13] as usize);
+        if banked < total {
+            Phase::Opening
+        } else if banked < 2 * total {
+            Phase::Middlegame
+        } else {
+            Phase::Endgame
+        }
+    }
+}
+
+impl Display for Phase {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let name = match self {
+            Phase::Opening => "opening",
+            Phase::Middlegame => "middlegame",
+            Phase::Endgame => "endgame",
+            Phase::Finished => "finished",
+        };
+        // Pad like a string, so tables line up
+        f.pad(name)
+    }
+}
+
+/// Seeds still in the houses, rather than banked in a store.
+fn seeds_in_play(state: &GameState) -> usize {
+    state.houses[..6]
+        .iter()
+        .chain(&state.houses[7..13])
+        .map(|&seeds| seeds as usize)
+        .sum()
+}
+
+/// Entries, visits and values of one slice of a table.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Coverage {
+    pub entries: usize,
+    pub visits: u64,
+    pub value_sum: f64,
+}
+
+impl Coverage {
+    fn add(&mut self, value: f64, visits: u32) {
+        self.entries += 1;
+        self.visits += visits as u64;
+        self.value_sum += value;
+    }
+}
+
+/// What `model info` reports about a value file.
+#[derive(Debug, Clone)]
+pub struct Summary {
+    pub metadata: TrainingMetadata,
+    pub entries: usize,
+    /// Entries updated at least once; finished games are set rather than updated.
+    pub visited: usize,
+    pub total_visits: u64,
+    pub min: f64,
+    pub max: f64,
+    pub mean: f64,
+    /// Entries in each of `HISTOGRAM_BUCKETS` equal slices of [min, max].
+    pub histogram: Vec<usize>,
+    /// Keyed by the first number of seeds in play in each row of `SEEDS_PER_ROW`.
+    pub by_seeds: BTreeMap<usize, Coverage>,
+    pub by_phase: BTreeMap<Phase, Coverage>,
+}
+
+impl Summary {
+    pub fn new(file: &ValueFile) -> Summary {
+        let values = &file.values;
+        let (min, max) = values
+            .values()
+            .fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), &value| {
+                (lo.min(value), hi.max(value))
+            });
+        let width = (max - min) / HISTOGRAM_BUCKETS as f64;
+        let mut histogram = vec![0; HISTOGRAM_BUCKETS];
+        let mut by_seeds: BTreeMap<usize, Coverage> = BTreeMap::new();
+        let mut by_phase: BTreeMap<Phase, Coverage> = BTreeMap::new();
+        let mut visited = 0;
+        for (state, &value) in values {
+            let bucket = if width > 0.0 {
+                (((value - min) / width) as usize).min(HISTOGRAM_BUCKETS - 1)
+            } else {
+                0
+            };
+            histogram[bucket] += 1;
+            let visits = file.visits.get(state).copied().unwrap_or(0);
+            if visits > 0 {
+                visited += 1;
+            }
+            let row = seeds_in_play(state) / SEEDS_PER_ROW * SEEDS_PER_ROW;
+            by_seeds.entry(row).or_default().add(value, visits);
+            by_phase
+                .entry(Phase::of(state))
+                .or_default()
+                .add(value, visits);
+        }
+        Summary {
+            metadata: file.metadata.clone(),
+            entries: values.len(),
+            visited,
+            total_visits: file.visits.values().map(|&visits| visits as u64).sum(),
+            min,
+            max,
+            mean: values.values().sum::<f64>() / values.len().max(1) as f64,
+            histogram,
+            by_seeds,
+            by_phase,
+        }
+    }
+
+    fn write_coverage<K: Display>(
+        &self,
+        f: &mut Formatter,
+        rows: impl Iterator<Item = (K, Coverage)>,
+    ) -> fmt::Result {
+        writeln!(
+            f,
+            "  {:<10}  {:>9}  {:>6}  {:>10}  {:>10}",
+            "", "Entries", "Share", "Visits", "Mean value"
+        )?;
+        for (key, coverage) in rows {
+            writeln!(
+                f,
+                "  {:<10}  {:>9}  {:>5.1}%  {:>10}  {:>10.3}",
+                key,
+                coverage.entries,
+                100.0 * coverage.entries as f64 / self.entries.max(1) as f64,
+                coverage.visits,
+                coverage.value_sum / coverage.entries.max(1) as f64
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for Summary {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let metadata = &self.metadata;
+        writeln!(f, "{}, {}", metadata.rules, metadata.learner)?;
+        writeln!(
+            f,
+            "{} episodes over {} training runs",
+            metadata.total_episodes,
+            metadata.runs.len()
+        )?;
+        writeln!(
+            f,
+            "{} entries, {} of them updated, {} updates in all",
+            self.entries, self.visited, self.total_visits
+        )?;
+        if self.entries == 0 {
+            return Ok(());
+        }
+        writeln!(
+            f,
+            "Values: min {:.3}, mean {:.3}, max {:.3}",
+            self.min, self.mean, self.max
+        )?;
+        writeln!(f)?;
+        writeln!(f, "Value histogram:")?;
+        let width = (self.max - self.min) / HISTOGRAM_BUCKETS as f64;
+        let tallest = self.histogram.iter().copied().max().unwrap_or(0).max(1);
+        for (bucket, &count) in self.histogram.iter().enumerate() {
+            let lo = self.min + bucket as f64 * width;
+            writeln!(
+                f,
+                "  [{:>6.3}, {:>6.3}{}  {:>9}  {}",
+                lo,
+                lo + width,
+                if bucket + 1 == HISTOGRAM_BUCKETS {
+                    "]"
+                } else {
+                    ")"
+                },
+                count,
+                "#".repeat(count * BAR_WIDTH / tallest)
+            )?;
+        }
+        writeln!(f)?;
+        writeln!(f, "Coverage by seeds in play:")?;
+        self.write_coverage(
+            f,
+            self.by_seeds.iter().map(|(&first, &coverage)| {
+                (format!("{}-{}", first, first + SEEDS_PER_ROW - 1), coverage)
+            }),
+        )?;
+        writeln!(f)?;
+        writeln!(f, "Coverage by game phase:")?;
+        self.write_coverage(
+            f,
+            self.by_phase
+                .iter()
+                .map(|(&phase, &coverage)| (phase, coverage)),
+        )
+    }
+}
+
+/// A stored value and how often it was updated.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Entry {
+    pub value: f64,
+    pub visits: u32,
+}
+
+fn lookup(file: &ValueFile, key: &GameState) -> Option<Entry> {
+    file.values.get(key).map(|&value| Entry {
+        value,
+        visits: file.visits.get(key).copied().unwrap_or(0),
+    })
+}
+
+fn write_entry(f: &mut Formatter, entry: Option<Entry>, flip: bool) -> fmt::Result {
+    match entry {
+        Some(Entry { value, visits }) => {
+            let value = if flip { 1.0 - value } else { value };
+            write!(f, "{:.4} ({} updates)", value, visits)
+        }
+        None => write!(f, "not stored, {:.4} by default", DEFAULT_STATE_VAL),
+    }
+}
+
+/// What `model query` reports about a position, for the player to move.
+#[derive(Debug, Clone)]
+pub struct Query {
+    pub state: GameState,
+    /// The position's own entry, which is stored as the opponent's afterstate.
+    pub entry: Option<Entry>,
+    /// Every legal move with the entry for the position it leaves.
+    pub moves: Vec<(Action, Option<Entry>)>,
+    /// The move a greedy player would make.
+    pub best: Option<Action>,
+}
+
+impl Query {
+    pub fn new(file: &ValueFile, state: GameState) -> Query {
+        let (key, _) = canonical::to_move_key(&state);
+        let moves: Vec<(Action, Option<Entry>)> = if state.is_ended() {
+            Vec::new()
+        } else {
+            state
+                .gen_actions()
+                .map(|action| {
+                    let mut after = state;
+                    after.evaluate_action(action);
+                    (action, lookup(file, &canonical::key(&after)))
+                })
+                .collect()
+        };
+        // The first of the best, as `pick_action` plays
+        let mut best: Option<(Action, f64)> = None;
+        for &(action, entry) in &moves {
+            let value = entry.map_or(DEFAULT_STATE_VAL, |entry| entry.value);
+            if best.is_none_or(|(_, best_value)| value > best_value) {
+                best = Some((action, value));
+            }
+        }
+        Query {
+            state,
+            entry: lookup(file, &key),
+            moves,
+            best: best.map(|(action, _)| action),
+        }
+    }
+}
+
+impl Display for Query {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}", self.state)?;
+        let (_, flip) = canonical::to_move_key(&self.state);
+        if let Some(outcome) = self.state.is_won() {
+            let score = outcome.score();
+            writeln!(f, "Game over, {} to {}", score.p1, score.p2)?;
+        }
+        write!(f, "Value for the player to move: ")?;
+        write_entry(f, self.entry, flip)?;
+        writeln!(f)?;
+        if self.moves.is_empty() {
+            return Ok(());
+        }
+        writeln!(f, "Moves, valued for the player making them:")?;
+        for &(action, entry) in &self.moves {
+            write!(f, "  {:<24}", action.to_string())?;
+            write_entry(f, entry, false)?;
+            if self.best == Some(action) {
+                write!(f, "  <- best")?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+/// How `merge` combines the values a position has in several files.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Weighting {
+    /// The plain mean of the values.
+    Average,
+    /// The mean weighted by how often each file updated the position. Positions no file
+    /// updated, such as finished games, fall back to the plain mean.
+    Visits,
+}
+
+impl Display for Weighting {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Weighting::Average => write!(f, "average"),
+            Weighting::Visits => write!(f, "visits"),
+        }
+    }
+}
+
+impl FromStr for Weighting {
+    type Err = String;
+
+    fn from_str(spec: &str) -> Result<Weighting, String> {
+        match spec {
+            "average" => Ok(Weighting::Average),
+            "visits" => Ok(Weighting::Visits),
+            _ => Err(format!(
+                "unknown weighting '{}' (expected average or visits)",
+                spec
+            )),
+        }
+    }
+}
+
+/// Combine value files trained for the same game into one, summing their visit counts and
+/// keeping the history of every run.
+pub fn merge(files: Vec<ValueFile>, weighting: Weighting) -> Result<ValueFile, String> {
+    let mut files = files.into_iter();
+    let first = files.next().ok_or("nothing to merge")?;
+    let mut metadata = first.metadata.clone();
+    // Per position: sum and count of the values, and their visit-weighted sum and weight
+    let mut sums: HashMap<GameState, (f64, usize, f64, u64)> = HashMap::new();
+    let mut visits = VisitCounts::new();
+    for (n, file) in std::iter::once(first).chain(files).enumerate() {
+        if n > 0 {
+            if file.metadata.rules != metadata.rules {
+                return Err(format!(
+                    "cannot merge a table for {} into one for {}",
+                    file.metadata.rules, metadata.rules
+                ));
+            }
+            if file.metadata.learner != metadata.learner {
+                return Err(format!(
+                    "cannot merge {} into {}",
+                    file.metadata.learner, metadata.learner
+                ));
+            }
+            metadata.total_episodes += file.metadata.total_episodes;
+            metadata.runs.extend(file.metadata.runs);
+        }
+        for (state, value) in file.values {
+            let weight = file.visits.get(&state).copied().unwrap_or(0) as u64;
+            let sum = sums.entry(state).or_default();
+            sum.0 += value;
+            sum.1 += 1;
+            sum.2 += weight as f64 * value;
+            sum.3 += weight;
+        }
+        for (state, count) in file.visits {
+            *visits.entry(state).or_insert(0) += count;
+        }
+    }
+    let values: ValueFunction = sums
+        .into_iter()
+        .map(|(state, (sum, count, weighted_sum, weight))| {
+            let value = match weighting {
+                Weighting::Visits if weight > 0 => weighted_sum / weight as f64,
+                _ => sum / count as f64,
+            };
+            (state, value)
+        })
+        .collect();
+    visits.retain(|state, _| values.contains_key(state));
+    Ok(ValueFile {
+        metadata,
+        values,
+        visits,
+    })
+}
+
+/// Drop the positions updated fewer than `min_visits` times, returning how many went.
+/// Finished games are always kept: their values are exact rather than learned.
+pub fn prune(file: &mut ValueFile, min_visits: u32) -> usize {
+    let before = file.values.len();
+    let visits = &file.visits;
+    file.values.retain(|state, _| {
+        state.is_ended() || visits.get(state).copied().unwrap_or(0) >= min_visits
+    });
+    let values = &file.values;
+    file.visits.retain(|state, _| values.contains_key(state));
+    before - file.values.len()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExportFormat {
+    Csv,
+    Json,
+}
+
+impl ExportFormat {
+    /// CSV for `.csv` files, JSON for anything else.
+    pub fn from_path(path: &Path) -> ExportFormat {
+        match path.extension() {
+            Some(ext) if ext.eq_ignore_ascii_case("csv") => ExportFormat::Csv,
+            _ => ExportFormat::Json,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct ExportedEntry {
+    board: [u8; 14],
+    value: f64,
+    visits: u32,
+}
+
+#[derive(Serialize)]
+struct ExportedFile<'a> {
+    metadata: &'a TrainingMetadata,
+    entries: Vec<ExportedEntry>,
+}
+
+/// Write every entry of `file`, in board order. Boards are afterstates, listed as in a board
+/// argument: the houses and store of the player who just moved, then the opponent's.
+pub fn export(file: &ValueFile, format: ExportFormat, out: &mut dyn Write) -> io::Result<()> {
+    let mut entries: Vec<ExportedEntry> = file
+        .values
+        .iter()
+        .map(|(state, &value)| ExportedEntry {
+            board: state.houses,
+            value,
+            visits: file.visits.get(state).copied().unwrap_or(0),
+        })
+        .collect();
+    entries.sort_by_key(|entry| entry.board);
+    match format {
+        ExportFormat::Json => {
+            let exported = ExportedFile {
+                metadata: &file.metadata,
+                entries,
+            };
+            serde_json::to_writer(&mut *out, &exported)?;
+            writeln!(out)
+        }
+        ExportFormat::Csv => {
+            for side in ["own", "opp"] {
+                for house in 1..=6 {
+                    write!(out, "{}_{},", side, house)?;
+                }
+                write!(out, "{}_store,", side)?;
+            }
+            writeln!(out, "value,visits")?;
+            for entry in entries {
+                for seeds in entry.board {
+                    write!(out, "{},", seeds)?;
+                }
+                writeln!(out, "{},{}", entry.value, entry.visits)?;
+            }
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mancala::Rules;
+    use crate::packed_actions::ActionQueue;
+
+    fn file(entries: &[(GameState, f64, u32)], episodes: usize) -> ValueFile {
+        let mut metadata = TrainingMetadata::new(Rules::Kalah { seeds: 4 });
+        metadata.total_episodes = episodes;
+        ValueFile {
+            metadata,
+            values: entries
+                .iter()
+                .map(|&(state, value, _)| (state, value))
+                .collect(),
+            visits: entries
+                .iter()
+                .filter(|&&(_, _, visits)| visits > 0)
+                .map(|&(state, _, visits)| (state, visits))
+                .collect(),
+        }
+    }
+
+    fn boards() -> (GameState, GameState, GameState) {
+        let start = GameState::new(4);
+        let mut late = start;
+        late.houses[6] = 20;
+        late.houses[13] = 12;
+        let mut finished = late;
+        finished.houses[..6].copy_from_slice(&[0; 6]);
+        (start, late, finished)
+    }
+
+    #[test]
+    fn test_phase() {
+        let (start, late, finished) = boards();
+        assert_eq!(Phase::of(&start), Phase::Opening);
+        assert_eq!(Phase::of(&late), Phase::Middlegame);
+        assert_eq!(Phase::of(&finished), Phase::Finished);
+    }
+
+    #[test]
+    fn test_summary() {
+        let (start, late, finished) = boards();
+        let summary = Summary::new(&file(
+            &[(start, 0.5, 3), (late, 0.7, 1), (finished, 1.0, 0)],
+            10,
+        ));
+        assert_eq!(
+            (summary.entries, summary.visited, summary.total_visits),
+            (3, 2, 4)
+        );
+        assert_eq!((summary.min, summary.max), (0.5, 1.0));
+        assert_eq!(summary.histogram[0], 1);
+        assert_eq!(summary.histogram[HISTOGRAM_BUCKETS - 1], 1);
+        assert_eq!(summary.by_seeds[&48].entries, 2);
+        assert_eq!(summary.by_seeds[&24].entries, 1);
+        assert_eq!(summary.by_phase[&Phase::Middlegame].visits, 1);
+        assert!(summary.to_string().contains("3 entries, 2 of them updated"));
+    }
+
+    #[test]
+    fn test_query() {
+        let (start, _, _) = boards();
+        let mut after = start;
+        after.evaluate_action(Action::singleton(3));
+        let mut position = start;
+        position.swap_board();
+        let query = Query::new(&file(&[(after, 0.9, 2), (position, 0.25, 1)], 1), start);
+        assert_eq!(query.moves.len(), 10);
+        assert_eq!(query.best, Some(Action::singleton(3)));
+        assert_eq!(query.entry.unwrap().visits, 1);
+        let report = query.to_string();
+        assert!(report.contains("player to move: 0.7500 (1 updates)"));
+        assert!(report.contains("0.9000 (2 updates)  <- best"));
+    }
+
+    #[test]
+    fn test_merge() {
+        let (start, late, finished) = boards();
+        let a = || file(&[(start, 0.2, 1), (late, 0.4, 0), (finished, 1.0, 0)], 10);
+        let b = || file(&[(start, 0.6, 3), (finished, 1.0, 0)], 5);
+        let averaged = merge(vec![a(), b()], Weighting::Average).unwrap();
+        assert!((averaged.values[&start] - 0.4).abs() < 1e-12);
+        assert_eq!(averaged.values[&late], 0.4);
+        assert_eq!(averaged.visits[&start], 4);
+        assert_eq!(averaged.metadata.total_episodes, 15);
+        let weighted = merge(vec![a(), b()], Weighting::Visits).unwrap();
+        assert!((weighted.values[&start] - 0.5).abs() < 1e-12);
+        assert_eq!(weighted.values[&finished], 1.0);
+
+        let mut other = b();
+        other.metadata.rules = Rules::Kalah { seeds: 3 };
+        assert!(merge(vec![a(), other], Weighting::Average).is_err());
+        assert!("median".parse::<Weighting>().is_err());
+    }
+
+    #[test]
+    fn test_prune() {
+        let (start, late, finished) = boards();
+        let mut pruned = file(&[(start, 0.5, 3), (late, 0.7, 1), (finished, 1.0, 0)], 10);
+        assert_eq!(prune(&mut pruned, 2), 1);
+        assert!(pruned.values.contains_key(&start) && pruned.values.contains_key(&finished));
+        assert!(!pruned.visits.contains_key(&late));
+    }
+
+    #[test]
+    fn test_export() {
+        let (start, late, _) = boards();
+        let exported = file(&[(start, 0.5, 3), (late, 0.75, 1)], 10);
+        let mut csv = Vec::new();
+        export(&exported, ExportFormat::Csv, &mut csv).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].starts_with("own_1,") && lines[0].ends_with(",opp_store,value,visits"));
+        assert_eq!(lines[1], "4,4,4,4,4,4,0,4,4,4,4,4,4,0,0.5,3");
+
+        let mut json = Vec::new();
+        export(&exported, ExportFormat::Json, &mut json).unwrap();
+        let parsed: serde_json::Value = serde_json::from_slice(&json).unwrap();
+        assert_eq!(parsed["entries"][1]["value"], 0.75);
+        assert_eq!(parsed["metadata"]["total_episodes"], 10);
+        assert_eq!(
+            ExportFormat::from_path(Path::new("table.CSV")),
+            ExportFormat::Csv
+        );
+    }
+}
@@ -0,0 +1,924 @@
+use crate::engine::{format_move, parse_move};
+use crate::mancala::{GameState, Outcome};
+use crate::packed_actions::Action;
+
+extern crate serde;
+use self::serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender, TryRecvError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Version of the message protocol; a host only plays guests speaking the same one.
+pub const PROTOCOL_VERSION: u32 = 1;
+/// Default port for `host` and `join`.
+pub const DEFAULT_PORT: u16 = 7777;
+/// How long either side waits for the other's half of the handshake.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+/// How often, and how many times, a guest tries to get back into a game after losing its host.
+const RECONNECT_INTERVAL: Duration = Duration::from_secs(1);
+const RECONNECT_ATTEMPTS: u32 = 30;
+/// How long a host with no guest sleeps between checks for one coming back.
+const ACCEPT_INTERVAL: Duration = Duration::from_millis(50);
+/// Longest message line either side reads; anything longer ends the connection.
+const MAX_LINE: usize = 64 * 1024;
+/// Most connections a host has saying hello at once; any more are hung up on straight away.
+const MAX_PENDING: usize = 4;
+
+/// What the two sides of a network game say to each other, one JSON object per line. Boards are
+/// always seen by the side receiving them, with its houses and store first, and moves are
+/// written as in the engine protocol.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Message {
+    /// Guest to host on connecting. `token` is the one the host handed out, when rejoining.
+    Hello { version: u32, token: Option<u64> },
+    /// Host to guest: the game as it stands, on joining or rejoining.
+    Welcome {
+        token: u64,
+        board: [u8; 14],
+        your_turn: bool,
+        history: Vec<String>,
+    },
+    /// Guest to host: the guest's move.
+    Move {
+        #[serde(rename = "move")]
+        action: String,
+    },
+    /// Host to guest: a move was played, by the guest if `by_you`.
+    Moved {
+        #[serde(rename = "move")]
+        action: String,
+        by_you: bool,
+        board: [u8; 14],
+        your_turn: bool,
+    },
+    /// Host to guest: the guest's move was not played, and the game is as it was.
+    Rejected {
+        reason: String,
+        board: [u8; 14],
+        your_turn: bool,
+    },
+    /// Host to a client it will not play, just before hanging up.
+    Refused { reason: String },
+}
+
+/// Something the other side did, reported by `Peer::poll`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Event {
+    /// The opponent made a move, written from their side of the board.
+    Moved(Action),
+    /// The host turned down our move; the board is back as the host has it.
+    Rejected(String),
+    /// The connection dropped. A host waits for the guest to rejoin; a guest tries to.
+    Disconnected,
+    /// The other side is back, and the game picks up where it was.
+    Reconnected,
+}
+
+/// A connection to the other side: messages arrive on a channel fed by a reader thread.
+struct Link {
+    stream: TcpStream,
+    /// `None` once the connection has closed.
+    incoming: Receiver<Option<Message>>,
+}
+
+impl Link {
+    fn new(reader: BufReader<TcpStream>) -> io::Result<Link> {
+        let stream = reader.get_ref().try_clone()?;
+        stream.set_read_timeout(None)?;
+        let (sender, incoming) = mpsc::channel();
+        thread::spawn(move || {
+            let mut reader = reader;
+            loop {
+                match read_line(&mut reader, None) {
+                    Ok(None) => {
+                        let _ = sender.send(None);
+                        return;
+                    }
+                    Err(err) => {
+                        info!("Hanging up: {}", err);
+                        let _ = sender.send(None);
+                        return;
+                    }
+                    Ok(Some(line)) => match serde_json::from_str(line.trim()) {
+                        Ok(message) => {
+                            if sender.send(Some(message)).is_err() {
+                                return;
+                            }
+                        }
+                        Err(err) => warn!("Ignoring message {:?}: {}", line.trim(), err),
+                    },
+                }
+            }
+        });
+        Ok(Link { stream, incoming })
+    }
+
+    fn send(&mut self, message: &Message) -> io::Result<()> {
+        serde_json::to_writer(&mut self.stream, message)?;
+        self.stream.write_all(b"\n")?;
+        self.stream.flush()
+    }
+
+    /// The next message within `timeout`: `Ok(None)` if there is none yet, and an error if the
+    /// connection has closed.
+    fn receive(&self, timeout: Duration) -> io::Result<Option<Message>> {
+        match self.incoming.recv_timeout(timeout) {
+            Ok(Some(message)) => Ok(Some(message)),
+            Err(RecvTimeoutError::Timeout) => Ok(None),
+            Ok(None) | Err(RecvTimeoutError::Disconnected) => Err(io::Error::new(
+                io::ErrorKind::ConnectionAborted,
+                "connection closed",
+            )),
+        }
+    }
+}
+
+impl Drop for Link {
+    fn drop(&mut self) {
+        // Also wakes the reader thread, which holds its own handle on the socket
+        let _ = self.stream.shutdown(Shutdown::Both);
+    }
+}
+
+/// Send one message on a connection that has no `Link` yet.
+fn send_raw(stream: &mut TcpStream, message: &Message) -> io::Result<()> {
+    serde_json::to_writer(&mut *stream, message)?;
+    stream.write_all(b"\n")?;
+    stream.flush()
+}
+
+/// Someone knocking on a host: their opening message, read on a thread of its own.
+type Arrival = (SocketAddr, io::Result<(BufReader<TcpStream>, Message)>);
+
+/// Wait for the first message on a new connection, for as long as a handshake is allowed.
+fn greet(stream: TcpStream) -> io::Result<(BufReader<TcpStream>, Message)> {
+    stream.set_nonblocking(false)?;
+    let mut reader = BufReader::new(stream);
+    let message = receive_raw(&mut reader)?;
+    Ok((reader, message))
+}
+
+/// Read a line of at most `MAX_LINE` bytes, giving up at `deadline` if there is one however the
+/// bytes trickle in. `None` once the connection has closed.
+fn read_line(
+    reader: &mut BufReader<TcpStream>,
+    deadline: Option<Instant>,
+) -> io::Result<Option<String>> {
+    let mut line = Vec::new();
+    loop {
+        if let Some(deadline) = deadline {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    "timed out reading a message",
+                ));
+            }
+            reader.get_ref().set_read_timeout(Some(remaining))?;
+        }
+        let available = reader.fill_buf()?;
+        if available.is_empty() {
+            return Ok(None);
+        }
+        let (taken, ended) = match available.iter().position(|&byte| byte == b'\n') {
+            Some(end) => (end + 1, true),
+            None => (available.len(), false),
+        };
+        line.extend_from_slice(&available[..taken]);
+        reader.consume(taken);
+        if line.len() > MAX_LINE {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "message too long",
+            ));
+        }
+        if ended {
+            return String::from_utf8(line)
+                .map(Some)
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err));
+        }
+    }
+}
+
+/// Read one message during a handshake, which has `HANDSHAKE_TIMEOUT` to arrive.
+fn receive_raw(reader: &mut BufReader<TcpStream>) -> io::Result<Message> {
+    let Some(line) = read_line(reader, Some(Instant::now() + HANDSHAKE_TIMEOUT))? else {
+        return Err(io::Error::new(
+            io::ErrorKind::ConnectionAborted,
+            "connection closed during the handshake",
+        ));
+    };
+    serde_json::from_str(line.trim()).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
+
+fn swapped(state: &GameState) -> GameState {
+    let mut state = *state;
+    state.swap_board();
+    state
+}
+
+enum Role {
+    Host {
+        listener: TcpListener,
+        /// Connections still saying hello, off the thread calling `poll`.
+        arrivals: (Sender<Arrival>, Receiver<Arrival>),
+        /// How many of them have not been answered yet.
+        pending: usize,
+    },
+    Guest {
+        address: String,
+        /// When to next try to rejoin, and how many tries are left, while disconnected.
+        retry: Option<(Instant, u32)>,
+    },
+}
+
+/// One side of a game played over TCP. The host keeps the game: it checks every move the guest
+/// sends and tells the guest how the board stands after each one, so the guest can never drift
+/// out of step with it. A guest whose connection drops rejoins with the token it was given.
+pub struct Peer {
+    role: Role,
+    link: Option<Link>,
+    token: u64,
+    /// The board seen by this side.
+    board: GameState,
+    my_turn: bool,
+    /// Every move so far, each written from the side of the player who made it.
+    history: Vec<String>,
+    /// Events noticed while doing something else, for `poll` to report.
+    pending: VecDeque<Event>,
+}
+
+impl Peer {
+    /// Wait on `listener` for a guest to join a game starting from `starting_state`.
+    pub fn host(
+        listener: TcpListener,
+        starting_state: GameState,
+        guest_first: bool,
+    ) -> io::Result<Peer> {
+        let mut peer = Peer {
+            role: Role::Host {
+                listener,
+                arrivals: mpsc::channel(),
+                pending: 0,
+            },
+            link: None,
+            token: rand::random(),
+            board: starting_state,
+            my_turn: !guest_first,
+            history: Vec::new(),
+            pending: VecDeque::new(),
+        };
+        while peer.link.is_none() {
+            let Role::Host { listener, .. } = &peer.role else {
+                unreachable!()
+            };
+            let (stream, address) = listener.accept()?;
+            match greet(stream).and_then(|(reader, hello)| peer.welcome(reader, hello, false)) {
+                Ok(link) => {
+                    info!("{} joined", address);
+                    peer.link = Some(link);
+                }
+                Err(err) => warn!("Turned away {}: {}", address, err),
+            }
+        }
+        if let Role::Host { listener, .. } = &peer.role {
+            // From now on only a returning guest is waited for, between other work
+            listener.set_nonblocking(true)?;
+        }
+        Ok(peer)
+    }
+
+    /// Join the game hosted at `address`.
+    pub fn join(address: &str) -> io::Result<Peer> {
+        let mut peer = Peer {
+            role: Role::Guest {
+                address: address.to_string(),
+                retry: None,
+            },
+            link: None,
+            token: 0,
+            board: GameState { houses: [0; 14] },
+            my_turn: false,
+            history: Vec::new(),
+            pending: VecDeque::new(),
+        };
+        peer.link = Some(peer.hello(None)?);
+        Ok(peer)
+    }
+
+    /// The board, seen by this side.
+    pub fn board(&self) -> GameState {
+        self.board
+    }
+
+    pub fn my_turn(&self) -> bool {
+        self.my_turn && !self.board.is_ended()
+    }
+
+    pub fn is_host(&self) -> bool {
+        matches!(self.role, Role::Host { .. })
+    }
+
+    /// Every move so far, each written from the side of the player who made it.
+    pub fn history(&self) -> &[String] {
+        &self.history
+    }
+
+    /// Host side of the handshake with a new connection, answering its opening message. A
+    /// returning guest has to bring the token for this game.
+    fn welcome(
+        &self,
+        reader: BufReader<TcpStream>,
+        hello: Message,
+        rejoining: bool,
+    ) -> io::Result<Link> {
+        let mut stream = reader.get_ref().try_clone()?;
+        let refusal = match hello {
+            Message::Hello { version, .. } if version != PROTOCOL_VERSION => Some(format!(
+                "protocol version {} is not {}",
+                version, PROTOCOL_VERSION
+            )),
+            Message::Hello { token, .. } if rejoining && token != Some(self.token) => {
+                Some("a game is already in progress".to_string())
+            }
+            Message::Hello { .. } => None,
+            message => Some(format!("expected hello, not {:?}", message)),
+        };
+        if let Some(reason) = refusal {
+            send_raw(
+                &mut stream,
+                &Message::Refused {
+                    reason: reason.clone(),
+                },
+            )?;
+            return Err(io::Error::new(io::ErrorKind::PermissionDenied, reason));
+        }
+        send_raw(
+            &mut stream,
+            &Message::Welcome {
+                token: self.token,
+                board: swapped(&self.board).houses,
+                your_turn: !self.my_turn && !self.board.is_ended(),
+                history: self.history.clone(),
+            },
+        )?;
+        Link::new(reader)
+    }
+
+    /// Guest side of the handshake, taking on the game the host describes.
+    fn hello(&mut self, token: Option<u64>) -> io::Result<Link> {
+        let Role::Guest { address, .. } = &self.role else {
+            unreachable!("only guests say hello")
+        };
+        let socket_address = address.to_socket_addrs()?.next().ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("no address for {}", address),
+            )
+        })?;
+        let mut stream = TcpStream::connect_timeout(&socket_address, HANDSHAKE_TIMEOUT)?;
+        stream.set_read_timeout(Some(HANDSHAKE_TIMEOUT))?;
+        let mut reader = BufReader::new(stream.try_clone()?);
+        send_raw(
+            &mut stream,
+            &Message::Hello {
+                version: PROTOCOL_VERSION,
+                token,
+            },
+        )?;
+        match receive_raw(&mut reader)? {
+            Message::Welcome {
+                token,
+                board,
+                your_turn,
+                history,
+            } => {
+                self.token = token;
+                self.board = GameState { houses: board };
+                self.my_turn = your_turn;
+                self.history = history;
+                Link::new(reader)
+            }
+            Message::Refused { reason } => {
+                Err(io::Error::new(io::ErrorKind::PermissionDenied, reason))
+            }
+            message => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("expected welcome, not {:?}", message),
+            )),
+        }
+    }
+
+    /// Send `message` if the other side is connected, noting a dropped connection for `poll`.
+    fn send(&mut self, message: &Message) {
+        if let Some(link) = &mut self.link
+            && let Err(err) = link.send(message)
+        {
+            info!("Lost the connection: {}", err);
+            self.disconnected();
+        }
+    }
+
+    fn disconnected(&mut self) {
+        self.link = None;
+        if let Role::Guest { retry, .. } = &mut self.role {
+            *retry = Some((Instant::now(), RECONNECT_ATTEMPTS));
+        }
+        self.pending.push_back(Event::Disconnected);
+    }
+
+    /// Play our move, which has to be legal, and tell the other side.
+    pub fn play(&mut self, action: Action) -> io::Result<()> {
+        if !self.my_turn() {
+            return Err(io::Error::other("it is not our turn"));
+        }
+        let text = format_move(action);
+        parse_move(&self.board, &text).map_err(io::Error::other)?;
+        self.board.evaluate_action(action);
+        self.my_turn = false;
+        self.history.push(text.clone());
+        let message = if self.is_host() {
+            Message::Moved {
+                action: text,
+                by_you: false,
+                board: swapped(&self.board).houses,
+                your_turn: !self.board.is_ended(),
+            }
+        } else {
+            // Played here straight away; the host's answer settles it
+            Message::Move { action: text }
+        };
+        self.send(&message);
+        Ok(())
+    }
+
+    /// Wait up to `timeout` for something from the other side, reporting anything that changes
+    /// the game. Fails only once a guest has given up on getting back to its host.
+    pub fn poll(&mut self, timeout: Duration) -> io::Result<Option<Event>> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            if let Some(event) = self.pending.pop_front() {
+                return Ok(Some(event));
+            }
+            let mut remaining = deadline.saturating_duration_since(Instant::now());
+            if self.is_host() {
+                // Keep an eye out for the guest coming back before its old connection is missed
+                if let Some(event) = self.accept_returning()? {
+                    return Ok(Some(event));
+                }
+                remaining = remaining.min(ACCEPT_INTERVAL);
+            }
+            let event = match &self.link {
+                Some(link) => match link.receive(remaining) {
+                    Ok(Some(message)) => self.handle(message)?,
+                    Ok(None) => None,
+                    Err(err) => {
+                        info!("Lost the connection: {}", err);
+                        self.disconnected();
+                        None
+                    }
+                },
+                None => self.reconnect(remaining)?,
+            };
+            if event.is_some() || !self.pending.is_empty() {
+                return Ok(event.or_else(|| self.pending.pop_front()));
+            }
+            if Instant::now() >= deadline {
+                return Ok(None);
+            }
+        }
+    }
+
+    /// Host only: take back a guest knocking with this game's token, turning anyone else away.
+    /// Never waits on the other end: hellos are read on their own threads and answered here once
+    /// they have come in.
+    fn accept_returning(&mut self) -> io::Result<Option<Event>> {
+        let Role::Host {
+            listener,
+            arrivals: (sender, arrived),
+            pending,
+        } = &mut self.role
+        else {
+            return Ok(None);
+        };
+        match listener.accept() {
+            Ok((stream, address)) if *pending >= MAX_PENDING => {
+                warn!("Turned away {}: too many connections saying hello", address);
+                drop(stream);
+            }
+            Ok((stream, address)) => {
+                *pending += 1;
+                let sender = sender.clone();
+                thread::spawn(move || {
+                    let _ = sender.send((address, greet(stream)));
+                });
+            }
+            Err(err) if err.kind() == io::ErrorKind::WouldBlock => {}
+            Err(err) => return Err(err),
+        }
+        let (address, greeted) = match arrived.try_recv() {
+            Ok(arrival) => arrival,
+            Err(TryRecvError::Empty | TryRecvError::Disconnected) => return Ok(None),
+        };
+        *pending -= 1;
+        match greeted.and_then(|(reader, hello)| self.welcome(reader, hello, true)) {
+            Ok(link) => {
+                info!("{} rejoined", address);
+                // Replaces the guest's old connection, if it had not been missed yet
+                self.link = Some(link);
+                Ok(Some(Event::Reconnected))
+            }
+            Err(err) => {
+                warn!("Turned away {}: {}", address, err);
+                Ok(None)
+            }
+        }
+    }
+
+    /// Try to get the other side back, waiting at most `timeout`.
+    fn reconnect(&mut self, timeout: Duration) -> io::Result<Option<Event>> {
+        match &mut self.role {
+            Role::Host { .. } => {
+                // `poll` has just looked for the guest
+                thread::sleep(timeout);
+                Ok(None)
+            }
+            Role::Guest { retry, .. } => {
+                let Some((next_try, tries_left)) = *retry else {
+                    return Ok(None);
+                };
+                if self.board.is_ended() {
+                    // Nothing left to play for
+                    thread::sleep(timeout);
+                    return Ok(None);
+                }
+                let now = Instant::now();
+                if now < next_try {
+                    thread::sleep(timeout.min(next_try - now));
+                    return Ok(None);
+                }
+                match self.hello(Some(self.token)) {
+                    Ok(link) => {
+                        self.link = Some(link);
+                        if let Role::Guest { retry, .. } = &mut self.role {
+                            *retry = None;
+                        }
+                        Ok(Some(Event::Reconnected))
+                    }
+                    Err(err)
+                        if err.kind() == io::ErrorKind::PermissionDenied || tries_left <= 1 =>
+                    {
+                        // Give up for good
+                        if let Role::Guest { retry, .. } = &mut self.role {
+                            *retry = None;
+                        }
+                        Err(io::Error::new(
+                            io::ErrorKind::ConnectionAborted,
+                            format!("could not get back to the host: {}", err),
+                        ))
+                    }
+                    Err(err) => {
+                        info!("Rejoining failed: {}", err);
+                        if let Role::Guest { retry, .. } = &mut self.role {
+                            *retry = Some((now + RECONNECT_INTERVAL, tries_left - 1));
+                        }
+                        Ok(None)
+                    }
+                }
+            }
+        }
+    }
+
+    fn handle(&mut self, message: Message) -> io::Result<Option<Event>> {
+        let out_of_step = |what: &str| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("unexpected {} from the other side", what),
+            )
+        };
+        match (self.is_host(), message) {
+            (true, Message::Move { action }) => {
+                let guest_view = swapped(&self.board);
+                let checked = if self.my_turn() || self.board.is_ended() {
+                    Err("it is not your turn".to_string())
+                } else {
+                    parse_move(&guest_view, &action)
+                };
+                match checked {
+                    Ok(parsed) => {
+                        let mut after = guest_view;
+                        after.evaluate_action(parsed);
+                        self.board = swapped(&after);
+                        self.my_turn = true;
+                        self.history.push(action.clone());
+                        self.send(&Message::Moved {
+                            action,
+                            by_you: true,
+                            board: after.houses,
+                            your_turn: false,
+                        });
+                        Ok(Some(Event::Moved(parsed)))
+                    }
+                    Err(reason) => {
+                        self.send(&Message::Rejected {
+                            reason,
+                            board: guest_view.houses,
+                            your_turn: !self.my_turn() && !self.board.is_ended(),
+                        });
+                        Ok(None)
+                    }
+                }
+            }
+            (
+                false,
+                Message::Moved {
+                    action,
+                    by_you,
+                    board,
+                    your_turn,
+                },
+            ) => {
+                let event = if by_you {
+                    None
+                } else {
+                    let opponent_view = swapped(&self.board);
+                    let parsed =
+                        parse_move(&opponent_view, &action).map_err(|_| out_of_step("move"))?;
+                    self.history.push(action);
+                    Some(Event::Moved(parsed))
+                };
+                self.board = GameState { houses: board };
+                self.my_turn = your_turn;
+                Ok(event)
+            }
+            (
+                false,
+                Message::Rejected {
+                    reason,
+                    board,
+                    your_turn,
+                },
+            ) => {
+                if self.board.houses != board {
+                    // Take back the move played ahead of the host's answer
+                    self.history.pop();
+                }
+                self.board = GameState { houses: board };
+                self.my_turn = your_turn;
+                Ok(Some(Event::Rejected(reason)))
+            }
+            (_, message) => {
+                // Nothing the game can use, but no reason to end it either
+                warn!("Ignoring {:?} from the other side", message);
+                Ok(None)
+            }
+        }
+    }
+
+    /// Cut the connection as if the network had dropped it.
+    #[cfg(test)]
+    fn drop_connection(&mut self) {
+        if let Some(link) = &self.link {
+            let _ = link.stream.shutdown(Shutdown::Both);
+        }
+    }
+}
+
+/// Read a move for the player to move at `state` from standard input: its number in the list
+/// shown, or the move itself, e.g. `3-1`.
+pub fn prompt_move(state: &GameState) -> io::Result<Action> {
+    let choices: Vec<Action> = state.gen_actions().collect();
+    loop {
+        println!("Your moves:");
+        for (i, &choice) in choices.iter().enumerate() {
+            println!("\t({}): {}", i, format_move(choice));
+        }
+        let mut input = String::new();
+        if io::stdin().read_line(&mut input)? == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "no more input",
+            ));
+        }
+        let input = input.trim();
+        if let Ok(index) = input.parse::<usize>()
+            && index < choices.len()
+        {
+            return Ok(choices[index]);
+        }
+        match parse_move(state, input) {
+            Ok(action) => return Ok(action),
+            Err(err) => println!("{}", err),
+        }
+    }
+}
+
+/// Play a game over `peer` on the terminal, with `choose` picking our moves.
+pub fn play_cli(
+    peer: &mut Peer,
+    choose: &mut dyn FnMut(&GameState) -> io::Result<Action>,
+) -> io::Result<()> {
+    println!(
+        "Playing as the {}:\n{}",
+        if peer.is_host() { "host" } else { "guest" },
+        peer.board()
+    );
+    let mut waiting = false;
+    while !peer.board().is_ended() {
+        if peer.my_turn() {
+            waiting = false;
+            let action = choose(&peer.board())?;
+            peer.play(action)?;
+            println!("You played {}:\n{}", format_move(action), peer.board());
+            continue;
+        }
+        if !waiting {
+            println!("Waiting for the opponent...");
+            waiting = true;
+        }
+        match peer.poll(Duration::from_millis(500))? {
+            Some(Event::Moved(action)) => {
+                println!("Opponent played {}:\n{}", format_move(action), peer.board());
+                waiting = false;
+            }
+            Some(Event::Rejected(reason)) => println!("Move not played: {}", reason),
+            Some(Event::Disconnected) => {
+                println!("Lost the opponent; waiting for them to come back...")
+            }
+            Some(Event::Reconnected) => println!("Reconnected:\n{}", peer.board()),
+            None => {}
+        }
+    }
+    let mut final_state = peer.board();
+    final_state.finalize_game();
+    println!(
+        "Game over after {}:\n{}",
+        peer.history().join(" "),
+        final_state
+    );
+    match final_state.is_won() {
+        Some(Outcome::P1win(score)) => println!("You won! Final score: {}-{}", score.p1, score.p2),
+        Some(Outcome::P2win(score)) => println!("You lost! Final score: {}-{}", score.p1, score.p2),
+        Some(Outcome::Tie(score)) => println!("It's a tie! Final score: {}-{}", score.p1, score.p2),
+        None => {}
+    }
+    // Give the host's last word time to reach the guest before hanging up
+    peer.poll(Duration::from_millis(200))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Read;
+
+    const WAIT: Duration = Duration::from_secs(5);
+
+    /// A host and a guest connected over the loopback interface.
+    fn pair() -> (Peer, Peer) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let host = thread::spawn(move || Peer::host(listener, GameState::new(4), false).unwrap());
+        let guest = Peer::join(&address).unwrap();
+        (host.join().unwrap(), guest)
+    }
+
+    fn first_move(peer: &Peer) -> Action {
+        peer.board().gen_actions().next().unwrap()
+    }
+
+    #[test]
+    fn test_play_over_tcp() {
+        let (mut host, mut guest) = pair();
+        assert!(host.my_turn() && !guest.my_turn());
+        assert_eq!(guest.board(), GameState::new(4));
+
+        let action = first_move(&host);
+        host.play(action).unwrap();
+        assert_eq!(guest.poll(WAIT).unwrap(), Some(Event::Moved(action)));
+        assert_eq!(guest.board(), swapped(&host.board()));
+        assert!(guest.my_turn() && !host.my_turn());
+        assert!(host.play(action).is_err());
+
+        let reply = first_move(&guest);
+        guest.play(reply).unwrap();
+        assert_eq!(host.poll(WAIT).unwrap(), Some(Event::Moved(reply)));
+        // The guest hears the host's confirmation, which changes nothing
+        assert_eq!(guest.poll(Duration::from_millis(200)).unwrap(), None);
+        assert_eq!(host.board(), swapped(&guest.board()));
+        assert_eq!(guest.history(), host.history());
+        assert_eq!(host.history().len(), 2);
+    }
+
+    #[test]
+    fn test_host_checks_moves() {
+        let (mut host, guest) = pair();
+        // A guest speaking out of turn is put right
+        let mut link = guest.link.unwrap();
+        link.send(&Message::Move {
+            action: "1".to_string(),
+        })
+        .unwrap();
+        assert_eq!(host.poll(Duration::from_millis(500)).unwrap(), None);
+        match link.receive(WAIT).unwrap() {
+            Some(Message::Rejected {
+                reason, your_turn, ..
+            }) => assert_eq!((reason.as_str(), your_turn), ("it is not your turn", false)),
+            message => panic!("expected a rejection, got {:?}", message),
+        }
+        // So is one saying something out of place, and the game goes on
+        link.send(&Message::Hello {
+            version: PROTOCOL_VERSION,
+            token: None,
+        })
+        .unwrap();
+        assert_eq!(host.poll(Duration::from_millis(500)).unwrap(), None);
+        assert!(host.link.is_some());
+        let action = first_move(&host);
+        host.play(action).unwrap();
+        link.receive(WAIT).unwrap();
+        link.send(&Message::Move {
+            action: "9".to_string(),
+        })
+        .unwrap();
+        host.poll(Duration::from_millis(500)).unwrap();
+        assert!(matches!(
+            link.receive(WAIT).unwrap(),
+            Some(Message::Rejected {
+                your_turn: true,
+                ..
+            })
+        ));
+        assert_eq!(host.history().len(), 1);
+    }
+
+    #[test]
+    fn test_limits() {
+        let (mut host, guest) = pair();
+        let address = match &host.role {
+            Role::Host { listener, .. } => listener.local_addr().unwrap(),
+            Role::Guest { .. } => unreachable!(),
+        };
+        // Only so many connections get to say hello at once
+        let mut silent: Vec<TcpStream> = (0..MAX_PENDING + 1)
+            .map(|_| TcpStream::connect(address).unwrap())
+            .collect();
+        for _ in 0..MAX_PENDING + 1 {
+            assert_eq!(host.poll(Duration::ZERO).unwrap(), None);
+        }
+        let extra = silent.pop().unwrap();
+        extra.set_read_timeout(Some(WAIT)).unwrap();
+        assert_eq!((&extra).read(&mut [0; 1]).unwrap(), 0);
+
+        // A line that never ends is cut off rather than read into memory for good
+        let mut stream = guest.link.as_ref().unwrap().stream.try_clone().unwrap();
+        stream.write_all(&vec![b'x'; MAX_LINE + 1]).unwrap();
+        assert_eq!(host.poll(WAIT).unwrap(), Some(Event::Disconnected));
+    }
+
+    #[test]
+    fn test_reconnect() {
+        let (mut host, mut guest) = pair();
+        let action = first_move(&host);
+        guest.drop_connection();
+        assert_eq!(host.poll(WAIT).unwrap(), Some(Event::Disconnected));
+        // Played while the guest is away; it learns of the move on rejoining
+        host.play(action).unwrap();
+        assert_eq!(guest.poll(WAIT).unwrap(), Some(Event::Disconnected));
+        let host = thread::spawn(move || {
+            assert_eq!(host.poll(WAIT).unwrap(), Some(Event::Reconnected));
+            host
+        });
+        assert_eq!(guest.poll(WAIT).unwrap(), Some(Event::Reconnected));
+        let host = host.join().unwrap();
+        assert_eq!(guest.board(), swapped(&host.board()));
+        assert!(guest.my_turn());
+
+        // Someone else cannot take the guest's place
+        let address = match &host.role {
+            Role::Host { listener, .. } => listener.local_addr().unwrap().to_string(),
+            Role::Guest { .. } => unreachable!(),
+        };
+        let host = thread::spawn(move || {
+            let mut host = host;
+            assert_eq!(host.poll(Duration::from_millis(500)).unwrap(), None);
+            host
+        });
+        let refused = Peer::join(&address).err().unwrap();
+        assert_eq!(refused.kind(), io::ErrorKind::PermissionDenied);
+        let mut host = host.join().unwrap();
+        assert!(host.link.is_some());
+
+        // Nor can a connection that never says hello hold the host up
+        let _silent = TcpStream::connect(&address).unwrap();
+        let start = Instant::now();
+        for _ in 0..10 {
+            assert_eq!(host.poll(Duration::ZERO).unwrap(), None);
+        }
+        assert!(start.elapsed() < HANDSHAKE_TIMEOUT / 5);
+    }
+}
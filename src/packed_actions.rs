@@ -6,7 +6,7 @@ pub type SubAction = u8;
 
 impl Display for Action {
     fn fmt(&self, f: &mut Formatter) -> fmt::Result {
-        let mut copy = self.clone();
+        let mut copy = *self;
         let mut vals = Vec::new();
         while !copy.is_empty() {
             vals.push(copy.pop_front());
@@ -28,6 +28,7 @@ pub trait ActionQueue {
     fn is_empty(&self) -> bool;
     fn length(&self) -> u32;
     fn new() -> Self;
+    #[allow(dead_code)]
     fn singleton(subaction: u8) -> Self;
 }
 
@@ -0,0 +1,378 @@
+use crate::canonical;
+use crate::compact;
+use crate::learning::TrainingConfig;
+use crate::mancala::{GameState, Rules, ValueFunction, VisitCounts};
+use crate::postcard::{from_bytes, to_allocvec};
+
+extern crate serde;
+use self::serde::de::DeserializeOwned;
+use self::serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt::{self, Display, Formatter};
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+
+/// Bytes every value file starts with.
+const MAGIC: &[u8; 4] = b"MNCL";
+
+/// Version of the value file format written by `save`. Bump it whenever the encoding of
+/// `StoredFile` changes, including any change to `TrainingConfig`, and teach `decode` to read
+/// the old version. Version 1 is the original headerless dump of the value table.
+pub const FORMAT_VERSION: u16 = 2;
+
+/// Magic, format version and checksum.
+const HEADER_LEN: usize = 4 + 2 + 8;
+
+/// The kind of learner that produced a table, which decides how its values are read.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum LearnerKind {
+    /// TD(0) on afterstate values from the mover's point of view, keyed canonically.
+    TdAfterstate,
+}
+
+impl Display for LearnerKind {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            LearnerKind::TdAfterstate => write!(f, "TD(0) afterstate values"),
+        }
+    }
+}
+
+/// What a value file says about itself.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TrainingMetadata {
+    pub rules: Rules,
+    pub learner: LearnerKind,
+    /// Total number of episodes trained into the value file, across all runs.
+    pub total_episodes: usize,
+    /// Every training run that contributed to the value file, oldest first.
+    pub runs: Vec<TrainingRun>,
+}
+
+impl TrainingMetadata {
+    pub fn new(rules: Rules) -> TrainingMetadata {
+        TrainingMetadata {
+            rules,
+            learner: LearnerKind::TdAfterstate,
+            total_episodes: 0,
+            runs: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TrainingRun {
+    pub seed: u64,
+    /// Episodes completed so far (less than `config.episodes` for an interrupted run).
+    pub episodes: usize,
+    pub config: TrainingConfig,
+}
+
+/// Everything kept in a value file.
+#[derive(Debug)]
+pub struct ValueFile {
+    pub metadata: TrainingMetadata,
+    pub values: ValueFunction,
+    pub visits: VisitCounts,
+}
+
+/// The body of a value file as it is encoded, after the header.
+#[derive(Serialize)]
+struct StoredFileRef<'a> {
+    metadata: &'a TrainingMetadata,
+    values: Vec<(&'a GameState, &'a f64)>,
+    visits: Vec<(&'a GameState, &'a u32)>,
+}
+
+#[derive(Deserialize)]
+struct StoredFile {
+    metadata: TrainingMetadata,
+    values: Vec<(GameState, f64)>,
+    visits: Vec<(GameState, u32)>,
+}
+
+/// The part of the metadata that version 1 kept in a `.meta` file which is still readable: the
+/// runs' settings have changed shape since.
+#[derive(Deserialize)]
+struct LegacyMetadata {
+    total_episodes: usize,
+}
+
+/// Path of a file that accompanies the value file at `path`.
+fn sidecar_path(path: &Path, extension: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".");
+    name.push(extension);
+    PathBuf::from(name)
+}
+
+fn read_file(path: &Path) -> io::Result<Vec<u8>> {
+    let mut f = File::open(path)?;
+    let mut encoded = Vec::new();
+    f.read_to_end(&mut encoded)?;
+    Ok(encoded)
+}
+
+/// Write `parts` one after another to a temporary file and rename it over `path`, so a crash
+/// mid-write never leaves a truncated file behind.
+pub fn write_atomic(path: &Path, parts: &[&[u8]]) -> io::Result<()> {
+    let tmp_path = sidecar_path(path, "tmp");
+    let mut f = File::create(&tmp_path)?;
+    for part in parts {
+        f.write_all(part)?;
+    }
+    f.sync_all()?;
+    drop(f);
+    fs::rename(&tmp_path, path)
+}
+
+fn invalid(message: impl Display) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+fn load_optional<T: DeserializeOwned>(path: &Path) -> io::Result<Option<T>> {
+    match read_file(path) {
+        Ok(encoded) => from_bytes(&encoded)
+            .map(Some)
+            .map_err(|err| invalid(format!("{}: {}", path.display(), err))),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(err),
+    }
+}
+
+/// 64-bit FNV-1a, enough to catch truncated or damaged files.
+pub fn checksum(bytes: &[u8]) -> u64 {
+    checksum_parts(&[bytes])
+}
+
+/// `checksum` of several byte strings one after another, without copying them together.
+pub fn checksum_parts(parts: &[&[u8]]) -> u64 {
+    parts
+        .iter()
+        .flat_map(|part| part.iter())
+        .fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+            (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+        })
+}
+
+/// Entries in a fixed order, so the same seed and settings always produce the same file.
+fn sorted<V>(map: &HashMap<GameState, V>) -> Vec<(&GameState, &V)> {
+    let mut entries: Vec<(&GameState, &V)> = map.iter().collect();
+    entries.sort_by_key(|(state, _)| **state);
+    entries
+}
+
+/// Save a value table with its visit counts and metadata at the current format version.
+pub fn save(
+    path: &Path,
+    metadata: &TrainingMetadata,
+    values: &ValueFunction,
+    visits: &VisitCounts,
+) -> io::Result<()> {
+    let body = StoredFileRef {
+        metadata,
+        values: sorted(values),
+        visits: sorted(visits),
+    };
+    let payload = to_allocvec(&body).map_err(invalid)?;
+    let mut header = Vec::with_capacity(HEADER_LEN);
+    header.extend_from_slice(MAGIC);
+    header.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+    header.extend_from_slice(&checksum(&payload).to_le_bytes());
+    write_atomic(path, &[&header, &payload])
+}
+
+/// Load a value file, migrating it from an older format version if need be.
+pub fn load(path: &Path) -> io::Result<ValueFile> {
+    let bytes = read_file(path)?;
+    if bytes.starts_with(compact::MAGIC) {
+        return Err(invalid(
+            "a compact table, which can be played but not trained or edited; use the value file \
+             it was made from",
+        ));
+    }
+    if !bytes.starts_with(MAGIC) {
+        return load_legacy(path, &bytes);
+    }
+    if bytes.len() < HEADER_LEN {
+        return Err(invalid("truncated header"));
+    }
+    let version = u16::from_le_bytes([bytes[4], bytes[5]]);
+    if version > FORMAT_VERSION {
+        // A newer format may not even checksum the same way
+        return Err(unsupported(version));
+    }
+    let expected = u64::from_le_bytes(bytes[6..HEADER_LEN].try_into().unwrap());
+    let payload = &bytes[HEADER_LEN..];
+    if checksum(payload) != expected {
+        return Err(invalid(
+            "checksum mismatch, the file is damaged or truncated",
+        ));
+    }
+    decode(version, payload)
+}
+
+/// Decode the body of a value file written at format `version`.
+fn decode(version: u16, payload: &[u8]) -> io::Result<ValueFile> {
+    match version {
+        2 => {
+            let stored: StoredFile = from_bytes(payload).map_err(invalid)?;
+            Ok(ValueFile {
+                metadata: stored.metadata,
+                values: stored.values.into_iter().collect(),
+                visits: stored.visits.into_iter().collect(),
+            })
+        }
+        _ => Err(unsupported(version)),
+    }
+}
+
+/// Check that a table trained under `trained` is one for games under `rules`.
+pub fn check_rules(trained: Rules, rules: Rules) -> io::Result<()> {
+    if trained != rules {
+        return Err(invalid(format!("trained for {}, not {}", trained, rules)));
+    }
+    Ok(())
+}
+
+fn unsupported(version: u16) -> io::Error {
+    invalid(format!(
+        "format version {} is not supported (this build reads versions 1 to {})",
+        version, FORMAT_VERSION
+    ))
+}
+
+/// Migrate a version 1 file: a bare value table, with visit counts and metadata in `.visits` and
+/// `.meta` files next to it, keyed before keys were canonical. Only the standard game existed.
+fn load_legacy(path: &Path, bytes: &[u8]) -> io::Result<ValueFile> {
+    let values: ValueFunction =
+        from_bytes(bytes).map_err(|_| invalid("not a mancala value file"))?;
+    let (values, stats) = canonical::canonicalize_values(values);
+    let visits = load_optional(&sidecar_path(path, "visits"))?.unwrap_or_default();
+    let visits = canonical::canonicalize_visits(visits);
+    let mut metadata = TrainingMetadata::new(Rules::Kalah { seeds: 4 });
+    if let Some(legacy) = load_optional::<LegacyMetadata>(&sidecar_path(path, "meta"))? {
+        metadata.total_episodes = legacy.total_episodes;
+    }
+    // On standard error, as `engine` and `model export` keep standard output for their own use
+    eprintln!(
+        "Migrated {} from format version 1; the settings of earlier runs were not kept",
+        path.display()
+    );
+    if !stats.is_unchanged() {
+        eprintln!("Canonicalized its keys: {}", stats);
+    }
+    Ok(ValueFile {
+        metadata,
+        values,
+        visits,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("mancala-{}-{}.dat", name, std::process::id()))
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let path = temp_path("persist");
+        let mut values: ValueFunction = HashMap::new();
+        values.insert(GameState::new(4), 0.75);
+        values.insert(GameState::new(3), 0.25);
+        let mut visits: VisitCounts = HashMap::new();
+        visits.insert(GameState::new(4), 3);
+        let mut metadata = TrainingMetadata::new(Rules::Kalah { seeds: 4 });
+        metadata.total_episodes = 30;
+        metadata.runs.push(TrainingRun {
+            seed: 1,
+            episodes: 30,
+            config: TrainingConfig {
+                epsilon: "0.1".parse().unwrap(),
+                learning_rate: "visits".parse().unwrap(),
+                discount_factor: 1.0,
+                episodes: 50,
+                reward: crate::reward::RewardScheme::WinLoss,
+                replay: None,
+                pool: None,
+            },
+        });
+        save(&path, &metadata, &values, &visits).unwrap();
+        let loaded = load(&path).unwrap();
+        assert_eq!(loaded.values, values);
+        assert_eq!(loaded.visits, visits);
+        assert_eq!(loaded.metadata.rules, Rules::Kalah { seeds: 4 });
+        assert!(check_rules(loaded.metadata.rules, Rules::Kalah { seeds: 4 }).is_ok());
+        let wrong_game = check_rules(loaded.metadata.rules, Rules::Kalah { seeds: 3 }).unwrap_err();
+        assert!(
+            wrong_game
+                .to_string()
+                .starts_with("trained for kalah with 4 seeds")
+        );
+        assert_eq!(loaded.metadata.total_episodes, 30);
+        assert_eq!(loaded.metadata.runs[0].config.episodes, 50);
+
+        // A flipped byte is caught by the checksum
+        let good = fs::read(&path).unwrap();
+        let mut damaged = good.clone();
+        *damaged.last_mut().unwrap() ^= 1;
+        fs::write(&path, &damaged).unwrap();
+        assert!(load(&path).unwrap_err().to_string().contains("checksum"));
+
+        let mut newer = good;
+        newer[4] = 99;
+        fs::write(&path, &newer).unwrap();
+        assert!(load(&path).unwrap_err().to_string().contains("version 99"));
+
+        fs::write(&path, b"hello").unwrap();
+        assert!(
+            load(&path)
+                .unwrap_err()
+                .to_string()
+                .contains("not a mancala value file")
+        );
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_migrate_legacy() {
+        let path = temp_path("legacy");
+        let mut finished = GameState { houses: [0; 14] };
+        finished.houses[1] = 2;
+        finished.houses[6] = 20;
+        finished.houses[13] = 26;
+        let mut values: ValueFunction = HashMap::new();
+        values.insert(GameState::new(4), 0.75);
+        values.insert(finished, 1.0);
+        let mut visits: VisitCounts = HashMap::new();
+        visits.insert(GameState::new(4), 3);
+        fs::write(&path, to_allocvec(&sorted(&values)).unwrap()).unwrap();
+        fs::write(
+            sidecar_path(&path, "visits"),
+            to_allocvec(&sorted(&visits)).unwrap(),
+        )
+        .unwrap();
+        // Version 1 metadata started with the episode count, then runs in an older shape
+        fs::write(sidecar_path(&path, "meta"), [40, 1, 7, 7, 7]).unwrap();
+
+        let loaded = load(&path).unwrap();
+        assert_eq!(loaded.values.len(), 2);
+        assert_eq!(loaded.values[&canonical::key(&finished)], 1.0);
+        assert_eq!(loaded.visits, visits);
+        assert_eq!(loaded.metadata.total_episodes, 40);
+        assert!(loaded.metadata.runs.is_empty());
+
+        // Saving upgrades it to the current format
+        save(&path, &loaded.metadata, &loaded.values, &loaded.visits).unwrap();
+        assert!(fs::read(&path).unwrap().starts_with(MAGIC));
+        assert_eq!(load(&path).unwrap().values, loaded.values);
+        for extension in ["meta", "visits"] {
+            fs::remove_file(sidecar_path(&path, extension)).unwrap();
+        }
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -1,31 +1,70 @@
-use crate::mancala::GameState;
+use crate::canonical;
+use crate::mancala::{GameState, ValueTable, VisitCounts};
 use crate::packed_actions::Action;
+use crate::replay::Transition;
+use crate::search;
+use rand::RngCore;
 use std::collections::HashMap;
 
+extern crate serde;
+use self::serde::{Deserialize, Serialize};
+
 pub trait Player {
     fn opponent_plays(&mut self, action: Action);
     fn current_state(&self) -> GameState;
-    fn take_action(&mut self, values: &HashMap<GameState, f64>, epsilon: f64) -> Action;
+    fn take_action(
+        &mut self,
+        values: &dyn ValueTable,
+        epsilon: f64,
+        rng: &mut dyn RngCore,
+    ) -> Action;
+    /// Back up `reward` plus the value of the current state into the last state, returning the
+    /// TD error. `learning_rate` maps the number of times the last state has been updated
+    /// (including this update) to a step size.
     fn td_update(
         &self,
         values: &mut HashMap<GameState, f64>,
-        learning_rate: f64,
+        visits: &mut VisitCounts,
+        learning_rate: &dyn Fn(u32) -> f64,
         discount_factor: f64,
-    );
+        reward: f64,
+    ) -> f64;
 }
 
-const DEFAULT_STATE_VAL: f64 = 0.5f64;
+pub const DEFAULT_STATE_VAL: f64 = 0.5f64;
 
 pub struct AIPlayer {
     pub curr_state: GameState,
     pub last_state: GameState,
+    /// Position the move producing `curr_state` was made from.
+    position: GameState,
+    /// Position the move producing `last_state` was made from.
+    last_position: GameState,
+    /// Whether it is this player's turn at `curr_state`, rather than `curr_state` being the
+    /// result of its own move.
+    to_move: bool,
 }
 
 impl AIPlayer {
     pub fn new(starting_state: GameState) -> AIPlayer {
         AIPlayer {
-            curr_state: starting_state.clone(),
-            last_state: starting_state.clone(),
+            curr_state: starting_state,
+            last_state: starting_state,
+            position: starting_state,
+            last_position: starting_state,
+            to_move: true,
+        }
+    }
+
+    /// The experience behind the last `td_update`: moving from `last_state` on to `curr_state`,
+    /// with `reward` for getting there.
+    pub fn transition(&self, reward: f64) -> Transition {
+        Transition {
+            state: self.last_position,
+            afterstate: self.last_state,
+            reward,
+            next_state: self.curr_state,
+            next_to_move: self.to_move,
         }
     }
 }
@@ -33,14 +72,23 @@ impl AIPlayer {
 impl Player for AIPlayer {
     fn opponent_plays(&mut self, action: Action) {
         self.last_state = self.curr_state;
+        self.last_position = self.position;
+        self.to_move = true;
         self.curr_state.swap_board();
         self.curr_state.evaluate_action(action);
         self.curr_state.swap_board();
     }
 
-    fn take_action(&mut self, values: &HashMap<GameState, f64>, epsilon: f64) -> Action {
-        let (action, _) = self.curr_state.pick_action(epsilon, values);
+    fn take_action(
+        &mut self,
+        values: &dyn ValueTable,
+        epsilon: f64,
+        rng: &mut dyn RngCore,
+    ) -> Action {
+        let (action, _) = self.curr_state.pick_action(epsilon, values, rng);
         debug!("Picked action {} at state \n{}", action, self.curr_state);
+        self.position = self.curr_state;
+        self.to_move = false;
         self.curr_state.evaluate_action(action);
         debug!(
             "Evaluated action {}, now at state\n{}",
@@ -52,23 +100,35 @@ impl Player for AIPlayer {
     fn td_update(
         &self,
         values: &mut HashMap<GameState, f64>,
-        learning_rate: f64,
+        visits: &mut VisitCounts,
+        learning_rate: &dyn Fn(u32) -> f64,
         discount_factor: f64,
-    ) {
-        let q_next = *values.entry(self.curr_state).or_insert(DEFAULT_STATE_VAL);
-        let q_last = values.entry(self.last_state).or_insert(DEFAULT_STATE_VAL);
+        reward: f64,
+    ) -> f64 {
+        let last_key = canonical::key(&self.last_state);
+        let visit_count = visits.entry(last_key).or_insert(0);
+        *visit_count += 1;
+        let learning_rate = learning_rate(*visit_count);
+        let q_next = if self.to_move {
+            canonical::value_to_move(values, &self.curr_state)
+        } else {
+            canonical::value(values, &self.curr_state)
+        };
+        let q_last = values.entry(last_key).or_insert(DEFAULT_STATE_VAL);
         let q_tmp = *q_last; // just for printing
-        *q_last += learning_rate * (discount_factor * q_next - q_tmp);
+        let td_error = canonical::td_target(reward, discount_factor, q_next) - q_tmp;
+        *q_last += learning_rate * td_error;
         debug!(
             "Doing TD update from (self.last_state) q_last:\n{}\n\
              to (self.curr_state) q_next:\n{}",
             self.last_state, self.curr_state
         );
         debug!(
-            "q_last += learning_rate * (discount_factor * q_next - q_last)\n\
-             {} += {} * ({} * {} - {})",
-            *q_last, learning_rate, discount_factor, q_next, q_tmp
+            "q_last += learning_rate * (reward + 0.5 + discount_factor * (q_next - 0.5) - q_last)\n\
+             {} += {} * ({} + 0.5 + {} * ({} - 0.5) - {})",
+            *q_last, learning_rate, reward, discount_factor, q_next, q_tmp
         );
+        td_error
     }
 
     fn current_state(&self) -> GameState {
@@ -83,7 +143,7 @@ pub struct HumanPlayer {
 impl HumanPlayer {
     pub fn new(starting_state: GameState) -> HumanPlayer {
         HumanPlayer {
-            curr_state: starting_state.clone(),
+            curr_state: starting_state,
         }
     }
 }
@@ -95,7 +155,12 @@ impl Player for HumanPlayer {
         self.curr_state.swap_board();
     }
 
-    fn take_action(&mut self, values: &HashMap<GameState, f64>, _: f64) -> Action {
+    fn take_action(
+        &mut self,
+        values: &dyn ValueTable,
+        _: f64,
+        _: &mut dyn RngCore,
+    ) -> Action {
         println!(
             "Computer went. State now (from your perspective):\n{}",
             self.curr_state
@@ -109,7 +174,7 @@ impl Player for HumanPlayer {
                 "\n----------------\n{}:\n{}\nqval: {:?}\n",
                 action,
                 state,
-                values.get(&state)
+                canonical::value(values, &state)
             );
         }
 
@@ -122,13 +187,13 @@ impl Player for HumanPlayer {
             let mut input = String::new();
             use std::io::stdin;
             use std::str::FromStr;
-            if let Err(_) = stdin().read_line(&mut input) {
+            if stdin().read_line(&mut input).is_err() {
                 continue;
             }
-            if let Ok(index) = u8::from_str(&input.trim()) {
-                if (index as usize) < choices.len() {
-                    break index;
-                }
+            if let Ok(index) = u8::from_str(input.trim())
+                && (index as usize) < choices.len()
+            {
+                break index;
             }
         };
 
@@ -143,7 +208,92 @@ impl Player for HumanPlayer {
         action
     }
 
-    fn td_update(&self, _: &mut HashMap<GameState, f64>, _: f64, _: f64) {}
+    fn td_update(
+        &self,
+        _: &mut HashMap<GameState, f64>,
+        _: &mut VisitCounts,
+        _: &dyn Fn(u32) -> f64,
+        _: f64,
+        _: f64,
+    ) -> f64 {
+        0.0
+    }
+
+    fn current_state(&self) -> GameState {
+        self.curr_state
+    }
+}
+
+/// Fixed, non-learning ways of choosing a move, used as opponents and yardsticks.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Strategy {
+    /// Pick uniformly among the legal moves.
+    Random,
+    /// Pick the move that leaves the most seeds in our store.
+    Greedy,
+    /// Alpha-beta search this many turns deep, scoring positions by store margin.
+    AlphaBeta(u32),
+}
+
+pub struct BaselinePlayer {
+    curr_state: GameState,
+    strategy: Strategy,
+}
+
+impl BaselinePlayer {
+    pub fn new(starting_state: GameState, strategy: Strategy) -> BaselinePlayer {
+        BaselinePlayer {
+            curr_state: starting_state,
+            strategy,
+        }
+    }
+}
+
+impl Player for BaselinePlayer {
+    fn opponent_plays(&mut self, action: Action) {
+        self.curr_state.swap_board();
+        self.curr_state.evaluate_action(action);
+        self.curr_state.swap_board();
+    }
+
+    fn take_action(
+        &mut self,
+        values: &dyn ValueTable,
+        _: f64,
+        rng: &mut dyn RngCore,
+    ) -> Action {
+        let action = match self.strategy {
+            Strategy::Random => self.curr_state.pick_action(1.0, values, rng).0,
+            Strategy::Greedy => {
+                let mut best = None;
+                for action in self.curr_state.gen_actions() {
+                    let mut state = self.curr_state;
+                    state.evaluate_action(action);
+                    if best.is_none_or(|(_, store)| state.houses[6] > store) {
+                        best = Some((action, state.houses[6]));
+                    }
+                }
+                best.unwrap().0
+            }
+            Strategy::AlphaBeta(depth) => {
+                search::alpha_beta(&self.curr_state, depth, &search::store_margin).pv[0]
+            }
+        };
+        debug!("Picked action {} at state \n{}", action, self.curr_state);
+        self.curr_state.evaluate_action(action);
+        action
+    }
+
+    fn td_update(
+        &self,
+        _: &mut HashMap<GameState, f64>,
+        _: &mut VisitCounts,
+        _: &dyn Fn(u32) -> f64,
+        _: f64,
+        _: f64,
+    ) -> f64 {
+        0.0
+    }
 
     fn current_state(&self) -> GameState {
         self.curr_state
@@ -153,12 +303,13 @@ impl Player for HumanPlayer {
 pub fn play_loop(
     mut p1: Box<dyn Player>,
     mut p2: Box<dyn Player>,
-    values: &mut HashMap<GameState, f64>,
+    values: &dyn ValueTable,
+    rng: &mut dyn RngCore,
 ) {
     println!("Starting play loop:");
     println!("Starting state:\n{}", p1.current_state());
     loop {
-        let action = p1.take_action(values, 0.0);
+        let action = p1.take_action(values, 0.0, rng);
         p2.opponent_plays(action);
         if p1.current_state().is_ended() {
             break;
@@ -166,7 +317,7 @@ pub fn play_loop(
         std::mem::swap(&mut p1, &mut p2);
     }
     // Get a mutable copy of the current state to properly finalize it
-    let mut final_state = p1.current_state().clone();
+    let mut final_state = p1.current_state();
     // Finalize the game to move stones to the correct stores
     final_state.finalize_game();
     
@@ -177,12 +328,9 @@ pub fn play_loop(
     
     use crate::mancala::Outcome::*;
     match final_state.is_won() {
-        Some(P1win) => println!("You won! Final score: {}-{}", 
-                               final_state.houses[6], final_state.houses[13]),
-        Some(P2win) => println!("You Lost! Final score: {}-{}", 
-                              final_state.houses[6], final_state.houses[13]),
-        Some(Tie) => println!("It's a tie! Final score: {}-{}", 
-                            final_state.houses[6], final_state.houses[13]),
+        Some(P1win(score)) => println!("You won! Final score: {}-{}", score.p1, score.p2),
+        Some(P2win(score)) => println!("You Lost! Final score: {}-{}", score.p1, score.p2),
+        Some(Tie(score)) => println!("It's a tie! Final score: {}-{}", score.p1, score.p2),
         _ => println!("Not over yet?"),
     }
 }
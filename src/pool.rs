@@ -0,0 +1,226 @@
+use crate::eval::AgentSpec;
+use crate::mancala::ValueFunction;
+use crate::player::Strategy;
+use rand::{Rng, RngCore};
+use std::collections::VecDeque;
+use std::fmt::{self, Display, Formatter};
+use std::str::FromStr;
+
+extern crate serde;
+use self::serde::{Deserialize, Serialize};
+
+/// A kind of opponent the learner can be paired with.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum PoolEntry {
+    /// The learner plays itself, and both sides learn.
+    SelfPlay,
+    /// A frozen copy of the value table from earlier in the run.
+    Snapshot,
+    Baseline(Strategy),
+}
+
+impl Display for PoolEntry {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            PoolEntry::SelfPlay => write!(f, "self"),
+            PoolEntry::Snapshot => write!(f, "snapshot"),
+            PoolEntry::Baseline(strategy) => write!(f, "{}", AgentSpec::Baseline(*strategy)),
+        }
+    }
+}
+
+impl FromStr for PoolEntry {
+    type Err = String;
+
+    fn from_str(spec: &str) -> Result<PoolEntry, String> {
+        match spec {
+            "self" => Ok(PoolEntry::SelfPlay),
+            "snapshot" => Ok(PoolEntry::Snapshot),
+            _ => match spec.parse::<AgentSpec>() {
+                Ok(AgentSpec::Baseline(strategy)) => Ok(PoolEntry::Baseline(strategy)),
+                _ => Err(format!(
+                    "unknown opponent '{}' (expected self, snapshot, random, greedy or \
+                     alphabeta:DEPTH)",
+                    spec
+                )),
+            },
+        }
+    }
+}
+
+/// Opponents and their relative weights, written as `OPPONENT=WEIGHT,...`, e.g.
+/// `self=0.5,snapshot=0.3,random=0.1,alphabeta:2=0.1`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OpponentMix(pub Vec<(PoolEntry, f64)>);
+
+impl Display for OpponentMix {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        for (i, (entry, weight)) in self.0.iter().enumerate() {
+            if i > 0 {
+                write!(f, ",")?;
+            }
+            write!(f, "{}={}", entry, weight)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for OpponentMix {
+    type Err = String;
+
+    fn from_str(spec: &str) -> Result<OpponentMix, String> {
+        let mix = spec
+            .split(',')
+            .map(|part| {
+                let (entry, weight) = part
+                    .split_once('=')
+                    .ok_or_else(|| format!("expected OPPONENT=WEIGHT, got '{}'", part))?;
+                let weight = weight
+                    .trim()
+                    .parse::<f64>()
+                    .ok()
+                    .filter(|weight| *weight >= 0.0)
+                    .ok_or_else(|| format!("bad weight '{}' for {}", weight, entry))?;
+                Ok((entry.trim().parse()?, weight))
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+        if mix.iter().map(|(_, weight)| weight).sum::<f64>() <= 0.0 {
+            return Err("opponent weights must not all be zero".to_string());
+        }
+        Ok(OpponentMix(mix))
+    }
+}
+
+/// Settings for training against a pool of opponents instead of pure self-play.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PoolConfig {
+    pub mix: OpponentMix,
+    /// Episodes between snapshots of the value table.
+    pub snapshot_every: usize,
+    /// Snapshots kept; the oldest is dropped first.
+    pub max_snapshots: usize,
+}
+
+/// Exploration rate of snapshot opponents. They play their table greedily whatever the learner's
+/// epsilon, so the learner is measured against the best its earlier self could do.
+pub const SNAPSHOT_EPSILON: f64 = 0.0;
+
+/// The opponent for one training episode.
+pub enum Opponent<'a> {
+    SelfPlay,
+    /// Play from this frozen table, exploring at `SNAPSHOT_EPSILON`.
+    Snapshot(&'a ValueFunction),
+    Baseline(Strategy),
+}
+
+/// Frozen snapshots of the learner plus fixed baselines, sampled as training opponents.
+pub struct OpponentPool {
+    config: PoolConfig,
+    snapshots: VecDeque<ValueFunction>,
+}
+
+impl OpponentPool {
+    pub fn new(config: &PoolConfig) -> OpponentPool {
+        OpponentPool {
+            config: config.clone(),
+            snapshots: VecDeque::new(),
+        }
+    }
+
+    /// Draw an opponent according to the mixing weights. Snapshots are drawn uniformly, and fall
+    /// back to self-play until the first one has been taken.
+    pub fn choose(&self, rng: &mut dyn RngCore) -> Opponent<'_> {
+        let mix = &self.config.mix.0;
+        let total: f64 = mix.iter().map(|(_, weight)| weight).sum();
+        let mut mass = rng.gen_range(0.0..total);
+        let entry = mix
+            .iter()
+            .find(|(_, weight)| {
+                mass -= weight;
+                mass < 0.0
+            })
+            .unwrap_or(mix.last().unwrap())
+            .0;
+        match entry {
+            PoolEntry::SelfPlay => Opponent::SelfPlay,
+            PoolEntry::Snapshot if self.snapshots.is_empty() => Opponent::SelfPlay,
+            PoolEntry::Snapshot => {
+                Opponent::Snapshot(&self.snapshots[rng.gen_range(0..self.snapshots.len())])
+            }
+            PoolEntry::Baseline(strategy) => Opponent::Baseline(strategy),
+        }
+    }
+
+    /// Take a snapshot of `values` if one is due after `episodes` episodes.
+    pub fn after_episode(&mut self, values: &ValueFunction, episodes: usize) {
+        let every = self.config.snapshot_every;
+        if every == 0 || self.config.max_snapshots == 0 || !episodes.is_multiple_of(every) {
+            return;
+        }
+        if self.snapshots.len() == self.config.max_snapshots {
+            self.snapshots.pop_front();
+        }
+        self.snapshots.push_back(values.clone());
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mancala::GameState;
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_parse_mix() {
+        let spec = "self=0.5,snapshot=0.3,random=0.1,greedy=0.05,alphabeta:2=0.05";
+        let mix: OpponentMix = spec.parse().unwrap();
+        assert_eq!(mix.0.len(), 5);
+        assert_eq!(
+            mix.0[4],
+            (PoolEntry::Baseline(Strategy::AlphaBeta(2)), 0.05)
+        );
+        assert_eq!(mix.to_string(), spec);
+        assert!("self".parse::<OpponentMix>().is_err());
+        assert!("self=-1".parse::<OpponentMix>().is_err());
+        assert!("self=0,random=0".parse::<OpponentMix>().is_err());
+        assert!("table=1".parse::<OpponentMix>().is_err());
+    }
+
+    #[test]
+    fn test_pool() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut pool = OpponentPool::new(&PoolConfig {
+            mix: "snapshot=3,random=1".parse().unwrap(),
+            snapshot_every: 10,
+            max_snapshots: 2,
+        });
+        // No snapshots yet, so self-play stands in
+        assert!((0..20).all(|_| !matches!(pool.choose(&mut rng), Opponent::Snapshot(_))));
+
+        let mut values: ValueFunction = HashMap::new();
+        for episodes in 1..=30 {
+            values.insert(GameState::new(episodes as u8), 0.5);
+            pool.after_episode(&values, episodes);
+        }
+        assert_eq!(pool.snapshots.len(), 2);
+        assert_eq!(pool.snapshots[0].len(), 20);
+
+        let draws: Vec<Opponent> = (0..1000).map(|_| pool.choose(&mut rng)).collect();
+        let snapshots = draws
+            .iter()
+            .filter(|opponent| matches!(opponent, Opponent::Snapshot(_)))
+            .count();
+        assert!(
+            (700..800).contains(&snapshots),
+            "{} snapshot games",
+            snapshots
+        );
+        assert!(
+            draws
+                .iter()
+                .all(|opponent| !matches!(opponent, Opponent::SelfPlay))
+        );
+    }
+}
@@ -0,0 +1,374 @@
+use crate::mancala::GameState;
+use rand::{Rng, RngCore};
+use std::fmt::{self, Display, Formatter};
+use std::str::FromStr;
+
+extern crate serde;
+use self::serde::{Deserialize, Serialize};
+
+/// One step of experience from a player's point of view. The value being learned is that of
+/// `afterstate`, bootstrapped from `next_state`, whose value is already set if the game ended.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Transition {
+    /// Position the player moved from.
+    pub state: GameState,
+    /// Position the player's move produced.
+    pub afterstate: GameState,
+    /// Reward received on reaching `next_state`.
+    pub reward: f64,
+    /// The player's next afterstate, the position it faces if the opponent has just replied, or
+    /// the final position if the game ended.
+    pub next_state: GameState,
+    /// Whether it is the player's turn at `next_state`.
+    pub next_to_move: bool,
+}
+
+/// How transitions are drawn from a replay buffer.
+///
+/// Written on the command line as `uniform` or `prioritized[:ALPHA,BETA]`.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Sampling {
+    Uniform,
+    /// Draw transitions in proportion to |TD error|^alpha, correcting the bias with importance
+    /// weights raised to beta.
+    Prioritized {
+        alpha: f64,
+        beta: f64,
+    },
+}
+
+impl Display for Sampling {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Sampling::Uniform => write!(f, "uniform"),
+            Sampling::Prioritized { alpha, beta } => write!(f, "prioritized:{},{}", alpha, beta),
+        }
+    }
+}
+
+impl FromStr for Sampling {
+    type Err = String;
+
+    fn from_str(spec: &str) -> Result<Sampling, String> {
+        match spec.split_once(':') {
+            None if spec == "uniform" => Ok(Sampling::Uniform),
+            None if spec == "prioritized" => Ok(Sampling::Prioritized {
+                alpha: 0.6,
+                beta: 0.4,
+            }),
+            Some(("prioritized", args)) => {
+                let args: Vec<f64> = args
+                    .split(',')
+                    .map(|arg| arg.trim().parse::<f64>())
+                    .collect::<Result<_, _>>()
+                    .map_err(|err| format!("bad prioritized sampling '{}': {}", spec, err))?;
+                match args[..] {
+                    [alpha, beta] if alpha >= 0.0 && beta >= 0.0 => {
+                        Ok(Sampling::Prioritized { alpha, beta })
+                    }
+                    _ => Err(format!(
+                        "expected prioritized:ALPHA,BETA with non-negative values, got '{}'",
+                        spec
+                    )),
+                }
+            }
+            _ => Err(format!(
+                "unknown sampling '{}' (expected uniform or prioritized[:ALPHA,BETA])",
+                spec
+            )),
+        }
+    }
+}
+
+/// Experience replay settings for a training run.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ReplayConfig {
+    /// Transitions kept; the oldest are dropped first.
+    pub capacity: usize,
+    /// Transitions replayed after each game.
+    pub batch_size: usize,
+    pub sampling: Sampling,
+}
+
+/// Priority given to a transition with no TD error, so it can still be drawn.
+const MIN_PRIORITY: f64 = 1e-3;
+
+/// Binary tree of partial sums over leaf priorities, for drawing a leaf in proportion to its
+/// priority in O(log n).
+struct SumTree {
+    /// Node `i` has children `2i` and `2i + 1`; leaves start at `capacity`.
+    nodes: Vec<f64>,
+    capacity: usize,
+}
+
+impl SumTree {
+    fn new(capacity: usize) -> SumTree {
+        SumTree {
+            nodes: vec![0.0; 2 * capacity],
+            capacity,
+        }
+    }
+
+    fn total(&self) -> f64 {
+        self.nodes[1]
+    }
+
+    fn get(&self, leaf: usize) -> f64 {
+        self.nodes[self.capacity + leaf]
+    }
+
+    fn set(&mut self, leaf: usize, priority: f64) {
+        let mut node = self.capacity + leaf;
+        self.nodes[node] = priority;
+        while node > 1 {
+            node /= 2;
+            self.nodes[node] = self.nodes[2 * node] + self.nodes[2 * node + 1];
+        }
+    }
+
+    /// The leaf where the running sum of priorities passes `mass`.
+    fn find(&self, mut mass: f64) -> usize {
+        let mut node = 1;
+        while node < self.capacity {
+            let left = self.nodes[2 * node];
+            // Rounding can leave `mass` just past the total, so never step into an empty subtree
+            if mass < left || self.nodes[2 * node + 1] == 0.0 {
+                node *= 2;
+            } else {
+                mass -= left;
+                node = 2 * node + 1;
+            }
+        }
+        node - self.capacity
+    }
+}
+
+/// A transition drawn from a `ReplayBuffer`, with the importance weight to scale its update by.
+#[derive(Debug, Clone, Copy)]
+pub struct Sample {
+    pub index: usize,
+    pub weight: f64,
+}
+
+/// Fixed-size store of past transitions, replayed to reuse experience and break up the
+/// correlation between consecutive updates.
+///
+/// The buffer knows nothing about how values are represented: learners replay it by passing an
+/// update function that applies a transition and returns its new TD error.
+pub struct ReplayBuffer {
+    config: ReplayConfig,
+    transitions: Vec<Transition>,
+    /// Slot the next transition is written to once the buffer is full.
+    next: usize,
+    priorities: SumTree,
+    max_priority: f64,
+}
+
+impl ReplayBuffer {
+    pub fn new(config: &ReplayConfig) -> ReplayBuffer {
+        let capacity = config.capacity.max(1);
+        ReplayBuffer {
+            config: config.clone(),
+            transitions: Vec::with_capacity(capacity),
+            next: 0,
+            priorities: SumTree::new(capacity),
+            max_priority: 1.0,
+        }
+    }
+
+    /// Add a transition, replacing the oldest one if the buffer is full. New transitions get the
+    /// highest priority seen so far, so each is likely to be replayed at least once.
+    pub fn push(&mut self, transition: Transition) {
+        let index = if self.transitions.len() < self.priorities.capacity {
+            self.transitions.push(transition);
+            self.transitions.len() - 1
+        } else {
+            let index = self.next;
+            self.transitions[index] = transition;
+            self.next = (self.next + 1) % self.transitions.len();
+            index
+        };
+        if let Sampling::Prioritized { .. } = self.config.sampling {
+            self.priorities.set(index, self.max_priority);
+        }
+    }
+
+    /// Draw `batch_size` transitions, with replacement.
+    pub fn sample(&self, batch_size: usize, rng: &mut dyn RngCore) -> Vec<Sample> {
+        if self.transitions.is_empty() {
+            return Vec::new();
+        }
+        let total = self.priorities.total();
+        match self.config.sampling {
+            // Priorities that underflow to zero (a large alpha) or overflow leave nothing to draw
+            // in proportion to, so fall back to drawing uniformly
+            Sampling::Prioritized { beta, .. } if total > 0.0 && total.is_finite() => {
+                let n = self.transitions.len() as f64;
+                let mut samples: Vec<Sample> = (0..batch_size)
+                    .map(|_| {
+                        let index = self.priorities.find(rng.gen_range(0.0..total));
+                        let probability = self.priorities.get(index) / total;
+                        Sample {
+                            index,
+                            weight: (n * probability).powf(-beta),
+                        }
+                    })
+                    .collect();
+                // Normalise so weights only ever scale updates down
+                let max_weight = samples.iter().map(|s| s.weight).fold(0.0, f64::max);
+                for sample in &mut samples {
+                    sample.weight /= max_weight;
+                }
+                samples
+            }
+            _ => (0..batch_size)
+                .map(|_| Sample {
+                    index: rng.gen_range(0..self.transitions.len()),
+                    weight: 1.0,
+                })
+                .collect(),
+        }
+    }
+
+    /// Record the latest TD error of a transition, which sets its priority.
+    pub fn update_priority(&mut self, index: usize, td_error: f64) {
+        if let Sampling::Prioritized { alpha, .. } = self.config.sampling {
+            let priority = (td_error.abs() + MIN_PRIORITY).powf(alpha);
+            self.max_priority = self.max_priority.max(priority);
+            self.priorities.set(index, priority);
+        }
+    }
+
+    /// Replay a batch: call `update` with each sampled transition and its importance weight, and
+    /// use the TD error it returns as the transition's new priority.
+    pub fn replay(
+        &mut self,
+        rng: &mut dyn RngCore,
+        update: &mut dyn FnMut(&Transition, f64) -> f64,
+    ) {
+        for sample in self.sample(self.config.batch_size, rng) {
+            let td_error = update(&self.transitions[sample.index], sample.weight);
+            self.update_priority(sample.index, td_error);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
+
+    fn transition(seeds: u8) -> Transition {
+        let state = GameState::new(seeds);
+        Transition {
+            state,
+            afterstate: state,
+            reward: 0.0,
+            next_state: state,
+            next_to_move: false,
+        }
+    }
+
+    #[test]
+    fn test_parse_sampling() {
+        for spec in ["uniform", "prioritized:0.6,0.4"] {
+            assert_eq!(spec.parse::<Sampling>().unwrap().to_string(), spec);
+        }
+        assert_eq!(
+            "prioritized".parse::<Sampling>().unwrap(),
+            Sampling::Prioritized {
+                alpha: 0.6,
+                beta: 0.4
+            }
+        );
+        assert!("prioritized:0.6".parse::<Sampling>().is_err());
+        assert!("greedy".parse::<Sampling>().is_err());
+    }
+
+    #[test]
+    fn test_buffer_wraps() {
+        let mut buffer = ReplayBuffer::new(&ReplayConfig {
+            capacity: 3,
+            batch_size: 2,
+            sampling: Sampling::Uniform,
+        });
+        for seeds in 1..=5 {
+            buffer.push(transition(seeds));
+        }
+        let mut kept: Vec<u8> = buffer
+            .transitions
+            .iter()
+            .map(|t| t.state.houses[0])
+            .collect();
+        kept.sort();
+        assert_eq!(kept, vec![3, 4, 5]);
+    }
+
+    #[test]
+    fn test_prioritized_sampling() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut buffer = ReplayBuffer::new(&ReplayConfig {
+            capacity: 5,
+            batch_size: 1000,
+            sampling: Sampling::Prioritized {
+                alpha: 1.0,
+                beta: 1.0,
+            },
+        });
+        for seeds in 1..=5 {
+            buffer.push(transition(seeds));
+        }
+        for index in 0..5 {
+            buffer.update_priority(index, 0.0);
+        }
+        buffer.update_priority(2, 1.0);
+        let samples = buffer.sample(1000, &mut rng);
+        let hits = samples.iter().filter(|s| s.index == 2).count();
+        // Priority 1.001 against four of 0.001
+        assert!(hits > 980, "high-priority transition drawn {} times", hits);
+        for sample in &samples {
+            if sample.index == 2 {
+                assert!(sample.weight < 0.01);
+            } else {
+                assert!((sample.weight - 1.0).abs() < 1e-9);
+            }
+        }
+
+        // Replaying feeds the TD errors back in as priorities
+        buffer.replay(&mut rng, &mut |_, _| 0.0);
+        let hits = buffer
+            .sample(1000, &mut rng)
+            .iter()
+            .filter(|s| s.index == 2)
+            .count();
+        assert!(hits < 400, "priority not updated: {} hits", hits);
+    }
+
+    #[test]
+    fn test_sampling_without_priority_mass() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut buffer = ReplayBuffer::new(&ReplayConfig {
+            capacity: 4,
+            batch_size: 8,
+            sampling: Sampling::Prioritized {
+                alpha: 1000.0,
+                beta: 0.4,
+            },
+        });
+        for seeds in 1..=4 {
+            buffer.push(transition(seeds));
+        }
+        // Every priority underflows to zero
+        for index in 0..4 {
+            buffer.update_priority(index, 0.0);
+        }
+        assert_eq!(buffer.priorities.total(), 0.0);
+        let samples = buffer.sample(100, &mut rng);
+        assert_eq!(samples.len(), 100);
+        assert!(samples.iter().all(|s| s.index < 4 && s.weight == 1.0));
+
+        buffer.update_priority(1, f64::NAN);
+        assert_eq!(buffer.sample(10, &mut rng).len(), 10);
+    }
+}
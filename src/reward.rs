@@ -0,0 +1,130 @@
+use crate::canonical;
+use crate::mancala::{GameState, Outcome};
+use std::fmt::{self, Display, Formatter};
+use std::str::FromStr;
+
+extern crate serde;
+use self::serde::{Deserialize, Serialize};
+
+/// What the learner is rewarded for. Every scheme keeps values in the mover's frame, with the
+/// opponent's view of a position worth `1 - v`.
+///
+/// Written on the command line as `winloss`, `margin` or `shaped`.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum RewardScheme {
+    /// 1 for a win, 0 for a loss and 0.5 for a draw.
+    WinLoss,
+    /// The final store margin scaled into [0, 1]: 0.5 + margin / (2 * seeds in play).
+    Margin,
+    /// Win/loss/draw plus the final store margin, scaled as for `Margin`. Under discounting the
+    /// margin is credited move by move as it is banked, so seeds stored early are not
+    /// discounted away.
+    Shaped,
+}
+
+/// Store margin of `state`, scaled by the seeds in play so that it lies in [-0.5, 0.5].
+fn banked(state: &GameState) -> f64 {
+    let seeds = state.houses.iter().map(|&seeds| seeds as f64).sum::<f64>();
+    (state.houses[6] as f64 - state.houses[13] as f64) / (2.0 * seeds.max(1.0))
+}
+
+impl RewardScheme {
+    /// Value of a finished game for the player whose perspective `outcome` is from.
+    pub fn terminal_value(&self, outcome: &Outcome) -> f64 {
+        let score = outcome.score();
+        let margin = score.margin() as f64 / (2.0 * (score.p1 as f64 + score.p2 as f64).max(1.0));
+        let result = match outcome {
+            Outcome::P1win(_) => 1.0,
+            Outcome::P2win(_) => 0.0,
+            Outcome::Tie(_) => 0.5,
+        };
+        match self {
+            RewardScheme::WinLoss => result,
+            RewardScheme::Margin => 0.5 + margin,
+            RewardScheme::Shaped => result + margin,
+        }
+    }
+
+    /// Reward for reaching `to`, seen by the player being rewarded. Only `Shaped` pays anything
+    /// before the end of the game, and only when discounting: its values count the margin
+    /// banked so far, and this pays back the part of it the discount would take off.
+    pub fn step_reward(&self, to: &GameState, discount_factor: f64) -> f64 {
+        match self {
+            // Finished games count the seeds swept into the stores
+            RewardScheme::Shaped => (1.0 - discount_factor) * banked(&canonical::key(to)),
+            _ => 0.0,
+        }
+    }
+}
+
+impl Display for RewardScheme {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            RewardScheme::WinLoss => write!(f, "winloss"),
+            RewardScheme::Margin => write!(f, "margin"),
+            RewardScheme::Shaped => write!(f, "shaped"),
+        }
+    }
+}
+
+impl FromStr for RewardScheme {
+    type Err = String;
+
+    fn from_str(spec: &str) -> Result<RewardScheme, String> {
+        match spec {
+            "winloss" => Ok(RewardScheme::WinLoss),
+            "margin" => Ok(RewardScheme::Margin),
+            "shaped" => Ok(RewardScheme::Shaped),
+            _ => Err(format!(
+                "unknown reward scheme '{}' (expected winloss, margin or shaped)",
+                spec
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mancala::Score;
+
+    #[test]
+    fn test_parse_reward() {
+        for spec in ["winloss", "margin", "shaped"] {
+            assert_eq!(spec.parse::<RewardScheme>().unwrap().to_string(), spec);
+        }
+        assert!("score".parse::<RewardScheme>().is_err());
+    }
+
+    #[test]
+    fn test_terminal_value() {
+        let win = Outcome::P1win(Score { p1: 36, p2: 12 });
+        let tie = Outcome::Tie(Score { p1: 24, p2: 24 });
+        assert_eq!(RewardScheme::WinLoss.terminal_value(&win), 1.0);
+        assert_eq!(RewardScheme::Shaped.terminal_value(&tie), 0.5);
+        assert_eq!(RewardScheme::Shaped.terminal_value(&win), 1.25);
+        assert_eq!(RewardScheme::Margin.terminal_value(&win), 0.75);
+        assert_eq!(RewardScheme::Margin.terminal_value(&tie), 0.5);
+        let loss = Outcome::P2win(Score { p1: 12, p2: 36 });
+        assert_eq!(RewardScheme::Margin.terminal_value(&loss), 0.25);
+    }
+
+    #[test]
+    fn test_step_reward() {
+        let mut state = GameState::new(4);
+        state.houses[2] = 0;
+        state.houses[6] = 4;
+        assert_eq!(RewardScheme::WinLoss.step_reward(&state, 0.5), 0.0);
+        assert_eq!(RewardScheme::Shaped.step_reward(&state, 1.0), 0.0);
+        assert_eq!(
+            RewardScheme::Shaped.step_reward(&state, 0.5),
+            0.5 * 4.0 / 96.0
+        );
+        // Zero-sum: the opponent's view of the same position pays the opposite
+        state.swap_board();
+        assert_eq!(
+            RewardScheme::Shaped.step_reward(&state, 0.5),
+            -0.5 * 4.0 / 96.0
+        );
+    }
+}
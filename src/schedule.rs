@@ -0,0 +1,294 @@
+extern crate serde;
+use self::serde::{Deserialize, Serialize};
+use std::fmt::{self, Display, Formatter};
+use std::str::FromStr;
+
+/// How a training parameter (epsilon or learning rate) changes over a run.
+///
+/// Schedules are written on the command line as `<kind>:<args>`, or just a number for a constant:
+///
+/// - `0.05` or `const:0.05`
+/// - `linear:START,END,EPISODES`: interpolate from START to END, then hold END
+/// - `exp:START,DECAY[,MIN]`: START * DECAY^episode, never below MIN
+/// - `step:START,FACTOR,EVERY`: multiply by FACTOR every EVERY episodes
+/// - `visits[:MIN]`: 1/n where n is the number of times the updated state has been visited
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Schedule {
+    Constant(f64),
+    Linear {
+        start: f64,
+        end: f64,
+        episodes: usize,
+    },
+    Exponential {
+        start: f64,
+        decay: f64,
+        min: f64,
+    },
+    Step {
+        start: f64,
+        factor: f64,
+        every: usize,
+    },
+    InverseVisits {
+        min: f64,
+    },
+}
+
+impl Schedule {
+    /// The parameter value during `episode` (counting from zero) for a state that has been
+    /// visited `visits` times.
+    pub fn value(&self, episode: usize, visits: u32) -> f64 {
+        use self::Schedule::*;
+        match *self {
+            Constant(value) => value,
+            Linear {
+                start,
+                end,
+                episodes,
+            } => {
+                if episode >= episodes {
+                    end
+                } else {
+                    start + (end - start) * episode as f64 / episodes as f64
+                }
+            }
+            Exponential { start, decay, min } => (start * decay.powf(episode as f64)).max(min),
+            Step {
+                start,
+                factor,
+                every,
+            } => start * factor.powi((episode / every) as i32),
+            InverseVisits { min } => (1.0 / visits.max(1) as f64).max(min),
+        }
+    }
+
+    /// Whether the schedule depends on per-state visit counts rather than only the episode.
+    pub fn uses_visits(&self) -> bool {
+        matches!(self, Schedule::InverseVisits { .. })
+    }
+
+    /// Whether every value the schedule takes is a probability, from 0 to 1.
+    pub fn is_probability(&self) -> bool {
+        use self::Schedule::*;
+        let unit = |value: f64| (0.0..=1.0).contains(&value);
+        match *self {
+            Constant(value) => unit(value),
+            Linear { start, end, .. } => unit(start) && unit(end),
+            // Shrinking from START, so it can only fall towards MIN or 0
+            Exponential { start, decay, min } => unit(start) && unit(decay) && unit(min),
+            Step { start, factor, .. } => unit(start) && unit(factor),
+            InverseVisits { min } => unit(min),
+        }
+    }
+}
+
+/// Parse an exploration rate schedule, which has to stay a probability and cannot depend on
+/// visit counts.
+pub fn parse_epsilon(spec: &str) -> Result<Schedule, String> {
+    let schedule: Schedule = spec.parse()?;
+    if schedule.uses_visits() {
+        return Err("visit-count schedules only apply to the learning rate".to_string());
+    }
+    if !schedule.is_probability() {
+        return Err(format!("epsilon '{}' has to stay between 0 and 1", spec));
+    }
+    Ok(schedule)
+}
+
+impl Display for Schedule {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        use self::Schedule::*;
+        match *self {
+            Constant(value) => write!(f, "{}", value),
+            Linear {
+                start,
+                end,
+                episodes,
+            } => write!(f, "linear:{},{},{}", start, end, episodes),
+            Exponential { start, decay, min } => write!(f, "exp:{},{},{}", start, decay, min),
+            Step {
+                start,
+                factor,
+                every,
+            } => write!(f, "step:{},{},{}", start, factor, every),
+            InverseVisits { min } => write!(f, "visits:{}", min),
+        }
+    }
+}
+
+impl FromStr for Schedule {
+    type Err = String;
+
+    fn from_str(spec: &str) -> Result<Schedule, String> {
+        let (kind, args) = match spec.split_once(':') {
+            Some((kind, args)) => (kind, args),
+            None if spec.parse::<f64>().is_ok() => ("const", spec),
+            None => (spec, ""),
+        };
+        let args: Vec<&str> = if args.is_empty() {
+            Vec::new()
+        } else {
+            args.split(',').map(str::trim).collect()
+        };
+        let float = |i: usize| -> Result<f64, String> {
+            args.get(i)
+                .ok_or(format!("schedule '{}' is missing argument {}", spec, i + 1))?
+                .parse::<f64>()
+                .map_err(|err| format!("bad number in schedule '{}': {}", spec, err))
+        };
+        let count = |i: usize| -> Result<usize, String> {
+            let count = args
+                .get(i)
+                .ok_or(format!("schedule '{}' is missing argument {}", spec, i + 1))?
+                .parse::<usize>()
+                .map_err(|err| format!("bad count in schedule '{}': {}", spec, err))?;
+            if count == 0 {
+                return Err(format!(
+                    "episode count in schedule '{}' must be positive",
+                    spec
+                ));
+            }
+            Ok(count)
+        };
+        let (schedule, expected_args) = match kind {
+            "const" => (Schedule::Constant(float(0)?), 1..=1),
+            "linear" => (
+                Schedule::Linear {
+                    start: float(0)?,
+                    end: float(1)?,
+                    episodes: count(2)?,
+                },
+                3..=3,
+            ),
+            "exp" => (
+                Schedule::Exponential {
+                    start: float(0)?,
+                    decay: float(1)?,
+                    min: if args.len() > 2 { float(2)? } else { 0.0 },
+                },
+                2..=3,
+            ),
+            "step" => (
+                Schedule::Step {
+                    start: float(0)?,
+                    factor: float(1)?,
+                    every: count(2)?,
+                },
+                3..=3,
+            ),
+            "visits" => (
+                Schedule::InverseVisits {
+                    min: if args.is_empty() { 0.0 } else { float(0)? },
+                },
+                0..=1,
+            ),
+            _ => return Err(format!("unknown schedule kind '{}'", kind)),
+        };
+        if !expected_args.contains(&args.len()) {
+            return Err(format!("wrong number of arguments in schedule '{}'", spec));
+        }
+        Ok(schedule)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_schedule() {
+        assert_eq!("0.05".parse(), Ok(Schedule::Constant(0.05)));
+        assert_eq!("const:0.5".parse(), Ok(Schedule::Constant(0.5)));
+        assert_eq!(
+            "linear:0.2,0.0,100".parse(),
+            Ok(Schedule::Linear {
+                start: 0.2,
+                end: 0.0,
+                episodes: 100
+            })
+        );
+        assert_eq!(
+            "exp:1,0.5".parse(),
+            Ok(Schedule::Exponential {
+                start: 1.0,
+                decay: 0.5,
+                min: 0.0
+            })
+        );
+        assert_eq!("visits".parse(), Ok(Schedule::InverseVisits { min: 0.0 }));
+        assert!("linear:0.2,0.0".parse::<Schedule>().is_err());
+        assert!("step:0.2,0.5,0".parse::<Schedule>().is_err());
+        assert!("cosine:1".parse::<Schedule>().is_err());
+        for spec in [
+            "0.05",
+            "linear:0.2,0,100",
+            "exp:1,0.5,0.1",
+            "step:1,0.5,10",
+            "visits:0.01",
+        ] {
+            let schedule: Schedule = spec.parse().unwrap();
+            assert_eq!(schedule.to_string().parse(), Ok(schedule));
+        }
+    }
+
+    #[test]
+    fn test_schedule_values() {
+        let linear = Schedule::Linear {
+            start: 1.0,
+            end: 0.0,
+            episodes: 10,
+        };
+        assert_eq!(linear.value(0, 0), 1.0);
+        assert_eq!(linear.value(5, 0), 0.5);
+        assert_eq!(linear.value(20, 0), 0.0);
+
+        let exp = Schedule::Exponential {
+            start: 1.0,
+            decay: 0.5,
+            min: 0.2,
+        };
+        assert_eq!(exp.value(1, 0), 0.5);
+        assert_eq!(exp.value(10, 0), 0.2);
+
+        let step = Schedule::Step {
+            start: 1.0,
+            factor: 0.5,
+            every: 10,
+        };
+        assert_eq!(step.value(9, 0), 1.0);
+        assert_eq!(step.value(25, 0), 0.25);
+
+        let visits = Schedule::InverseVisits { min: 0.0 };
+        assert_eq!(visits.value(0, 1), 1.0);
+        assert_eq!(visits.value(0, 4), 0.25);
+        assert!(visits.uses_visits());
+    }
+
+    #[test]
+    fn test_parse_epsilon() {
+        for spec in [
+            "0",
+            "0.02",
+            "1",
+            "linear:1,0.01,100",
+            "exp:0.2,0.999,0.01",
+            "step:0.2,0.5,1000",
+        ] {
+            assert!(parse_epsilon(spec).is_ok(), "{}", spec);
+        }
+        // Anything that could hand `gen_bool` a NaN or a number outside [0, 1]
+        for spec in [
+            "NaN",
+            "-0.1",
+            "1.5",
+            "inf",
+            "linear:0.2,NaN,10",
+            "exp:0.5,1.1",
+            "step:0.5,2,10",
+        ] {
+            assert!(parse_epsilon(spec).is_err(), "{}", spec);
+        }
+        assert!(parse_epsilon("visits").is_err());
+    }
+}
@@ -0,0 +1,200 @@
+use crate::mancala::GameState;
+use crate::packed_actions::Action;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Instant;
+
+/// Result of searching a position: the score for the player to move and the principal
+/// variation (best line of play, starting with the move to make).
+#[derive(Debug, Clone, PartialEq)]
+pub struct SearchResult {
+    pub score: f64,
+    pub pv: Vec<Action>,
+    /// Positions evaluated or expanded on the way.
+    pub nodes: u64,
+}
+
+/// How far ahead the player whose side is `houses[..6]` is in their store. Finished games are
+/// scored by their final margin.
+pub fn store_margin(state: &GameState) -> f64 {
+    let mut state = *state;
+    if state.is_ended() {
+        state.finalize_game();
+    }
+    state.houses[6] as f64 - state.houses[13] as f64
+}
+
+/// Nodes searched between checks of the deadline and the stop flag.
+const DEADLINE_CHECK_NODES: u64 = 1024;
+
+/// What a search needs as it goes: how to score positions, when to give up, and how much it has
+/// done so far.
+struct Search<'a> {
+    evaluate: &'a dyn Fn(&GameState) -> f64,
+    deadline: Option<Instant>,
+    stop: Option<&'a AtomicBool>,
+    nodes: u64,
+    timed_out: bool,
+}
+
+/// Negamax alpha-beta search `depth` turns deep from the perspective of the player to move.
+///
+/// `evaluate` scores a position reached right after a turn from the perspective of the player who
+/// just moved (like the afterstates in a value table). It must be zero-sum: the opponent's score
+/// for the same position is the negation.
+pub fn alpha_beta(
+    state: &GameState,
+    depth: u32,
+    evaluate: &dyn Fn(&GameState) -> f64,
+) -> SearchResult {
+    alpha_beta_until(state, depth, evaluate, None, None).unwrap()
+}
+
+/// `alpha_beta`, giving up and returning `None` if the search is still running at `deadline` or
+/// once `stop` is set.
+pub fn alpha_beta_until(
+    state: &GameState,
+    depth: u32,
+    evaluate: &dyn Fn(&GameState) -> f64,
+    deadline: Option<Instant>,
+    stop: Option<&AtomicBool>,
+) -> Option<SearchResult> {
+    if state.is_ended() {
+        return Some(SearchResult {
+            score: evaluate(state),
+            pv: Vec::new(),
+            nodes: 1,
+        });
+    }
+    let mut search = Search {
+        evaluate,
+        deadline,
+        stop,
+        nodes: 0,
+        timed_out: false,
+    };
+    let (score, pv) = negamax(
+        &mut search,
+        state,
+        depth.max(1),
+        f64::NEG_INFINITY,
+        f64::INFINITY,
+    );
+    if search.timed_out {
+        return None;
+    }
+    Some(SearchResult {
+        score,
+        pv,
+        nodes: search.nodes,
+    })
+}
+
+fn negamax(
+    search: &mut Search,
+    state: &GameState,
+    depth: u32,
+    mut alpha: f64,
+    beta: f64,
+) -> (f64, Vec<Action>) {
+    let mut best = (f64::NEG_INFINITY, Vec::new());
+    for action in state.gen_actions() {
+        search.nodes += 1;
+        if search.nodes.is_multiple_of(DEADLINE_CHECK_NODES)
+            && (search
+                .deadline
+                .is_some_and(|deadline| Instant::now() >= deadline)
+                || search.stop.is_some_and(|stop| stop.load(Ordering::Relaxed)))
+        {
+            search.timed_out = true;
+        }
+        if search.timed_out {
+            break;
+        }
+        let mut after = *state;
+        after.evaluate_action(action);
+        let (score, mut pv) = if depth == 1 || after.is_ended() {
+            ((search.evaluate)(&after), Vec::new())
+        } else {
+            after.swap_board();
+            let (score, pv) = negamax(search, &after, depth - 1, -beta, -alpha);
+            (-score, pv)
+        };
+        if score > best.0 {
+            pv.insert(0, action);
+            best = (score, pv);
+        }
+        alpha = alpha.max(score);
+        if alpha >= beta {
+            break;
+        }
+    }
+    best
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn minimax(state: &GameState, depth: u32) -> f64 {
+        state
+            .gen_actions()
+            .map(|action| {
+                let mut after = *state;
+                after.evaluate_action(action);
+                if depth == 1 || after.is_ended() {
+                    store_margin(&after)
+                } else {
+                    after.swap_board();
+                    -minimax(&after, depth - 1)
+                }
+            })
+            .fold(f64::NEG_INFINITY, f64::max)
+    }
+
+    #[test]
+    fn test_alpha_beta_matches_minimax() {
+        let mut state = GameState::new(3);
+        for depth in 1..4 {
+            let result = alpha_beta(&state, depth, &store_margin);
+            assert_eq!(result.score, minimax(&state, depth));
+            assert!(!result.pv.is_empty() && result.pv.len() <= depth as usize);
+        }
+        state.evaluate_action(alpha_beta(&state, 2, &store_margin).pv[0]);
+        state.swap_board();
+        assert_eq!(
+            alpha_beta(&state, 3, &store_margin).score,
+            minimax(&state, 3)
+        );
+    }
+
+    #[test]
+    fn test_alpha_beta_finds_capture() {
+        // Sowing the single seed in house 1 lands in the empty house 2, capturing the 9
+        // seeds opposite it.
+        let mut state = GameState::new(0);
+        state.houses[1] = 1;
+        state.houses[4] = 1;
+        state.houses[10] = 9;
+        state.houses[12] = 1;
+        let result = alpha_beta(&state, 1, &store_margin);
+        assert_eq!(result.pv[0].to_string(), "Cell 2");
+        assert_eq!(result.score, 10.0);
+    }
+
+    #[test]
+    fn test_deadline() {
+        let state = GameState::new(4);
+        assert_eq!(
+            alpha_beta_until(&state, 12, &store_margin, Some(Instant::now()), None),
+            None
+        );
+        let stop = AtomicBool::new(true);
+        assert_eq!(
+            alpha_beta_until(&state, 12, &store_margin, None, Some(&stop)),
+            None
+        );
+        let result = alpha_beta_until(&state, 2, &store_margin, None, None).unwrap();
+        assert_eq!(result, alpha_beta(&state, 2, &store_margin));
+        assert!(result.nodes >= 10);
+    }
+}
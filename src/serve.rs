@@ -0,0 +1,748 @@
+use crate::canonical;
+use crate::engine::{format_move, parse_move};
+use crate::mancala::{GameState, Outcome, Rules, ValueTable};
+use crate::packed_actions::Action;
+use rand::rngs::StdRng;
+
+extern crate serde;
+use self::serde::de::DeserializeOwned;
+use self::serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Largest request body accepted, which is plenty for any of the API's requests.
+const MAX_BODY: usize = 64 * 1024;
+/// Largest request line and headers accepted, together.
+const MAX_HEAD: usize = 8 * 1024;
+/// How long a client has to send its whole request. Connections are served one at a time, so a
+/// client that never finishes, or sends a byte at a time, would otherwise hold up everyone else.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+/// Most games kept at once; starting another ends the oldest.
+const MAX_GAMES: usize = 1000;
+/// How long to wait before accepting again after accepting failed, e.g. for want of file
+/// descriptors.
+const ACCEPT_BACKOFF: Duration = Duration::from_millis(100);
+
+/// A request as far as the API cares: its method, path and body.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Request {
+    pub method: String,
+    pub path: String,
+    pub body: Vec<u8>,
+}
+
+/// A reply: the HTTP status and a JSON body.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Response {
+    pub status: u16,
+    pub body: String,
+}
+
+impl Response {
+    fn json<T: Serialize>(status: u16, body: &T) -> Response {
+        Response {
+            status,
+            body: serde_json::to_string(body).unwrap(),
+        }
+    }
+
+    fn ok<T: Serialize>(body: &T) -> Response {
+        Response::json(200, body)
+    }
+
+    fn error(status: u16, message: impl Into<String>) -> Response {
+        Response::json(
+            status,
+            &ErrorBody {
+                error: message.into(),
+            },
+        )
+    }
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        201 => "Created",
+        204 => "No Content",
+        400 => "Bad Request",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        408 => "Request Timeout",
+        413 => "Payload Too Large",
+        431 => "Request Header Fields Too Large",
+        _ => "Error",
+    }
+}
+
+/// A connection that refuses to be read from once `deadline` has passed, however the bytes
+/// before it trickle in.
+struct DeadlineStream {
+    stream: TcpStream,
+    deadline: Instant,
+}
+
+impl Read for DeadlineStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let remaining = self.deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            return Err(io::Error::new(io::ErrorKind::TimedOut, "request timed out"));
+        }
+        self.stream.set_read_timeout(Some(remaining))?;
+        self.stream.read(buf)
+    }
+}
+
+/// The response to a request that could not be read, saying `unreadable` unless it took too long.
+fn read_failed(err: io::Error, unreadable: &str) -> Response {
+    match err.kind() {
+        io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock => {
+            Response::error(408, "request timed out")
+        }
+        _ => Response::error(400, unreadable),
+    }
+}
+
+/// Read the next line of a request's head into `line`, counting it in `head`, the bytes read
+/// so far. Fails once the head runs past `MAX_HEAD`.
+fn read_head_line(
+    stream: &mut dyn BufRead,
+    line: &mut String,
+    head: &mut usize,
+    unreadable: &str,
+) -> Result<(), Response> {
+    line.clear();
+    let limit = (MAX_HEAD - *head) as u64;
+    *head += Read::take(&mut *stream, limit)
+        .read_line(line)
+        .map_err(|err| read_failed(err, unreadable))?;
+    if *head >= MAX_HEAD && !line.ends_with('\n') {
+        return Err(Response::error(431, "request headers too large"));
+    }
+    Ok(())
+}
+
+/// Read one HTTP/1.1 request from `stream`. Only bodies sized by `Content-Length` are read.
+pub fn read_request(stream: &mut dyn BufRead) -> Result<Request, Response> {
+    let bad = |message: &str| Response::error(400, message);
+    let mut line = String::new();
+    let mut head = 0;
+    read_head_line(stream, &mut line, &mut head, "unreadable request")?;
+    let mut parts = line.split_whitespace();
+    let (Some(method), Some(path)) = (parts.next(), parts.next()) else {
+        return Err(bad("malformed request line"));
+    };
+    let (method, path) = (method.to_string(), path.to_string());
+    let mut length = 0;
+    loop {
+        read_head_line(stream, &mut line, &mut head, "unreadable headers")?;
+        let header = line.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':')
+            && name.eq_ignore_ascii_case("content-length")
+        {
+            length = value
+                .trim()
+                .parse()
+                .map_err(|_| bad("bad Content-Length"))?;
+        }
+    }
+    if length > MAX_BODY {
+        return Err(Response::error(413, "request body too large"));
+    }
+    let mut body = vec![0; length];
+    stream
+        .read_exact(&mut body)
+        .map_err(|err| read_failed(err, "request body shorter than its Content-Length"))?;
+    Ok(Request { method, path, body })
+}
+
+pub fn write_response(stream: &mut dyn Write, response: &Response) -> io::Result<()> {
+    write!(
+        stream,
+        "HTTP/1.1 {} {}\r\n\
+         Content-Type: application/json\r\n\
+         Content-Length: {}\r\n\
+         Access-Control-Allow-Origin: *\r\n\
+         Access-Control-Allow-Methods: GET, POST, DELETE, OPTIONS\r\n\
+         Access-Control-Allow-Headers: Content-Type\r\n\
+         Connection: close\r\n\r\n{}",
+        response.status,
+        reason(response.status),
+        response.body.len(),
+        response.body
+    )?;
+    stream.flush()
+}
+
+#[derive(Serialize)]
+struct ErrorBody {
+    error: String,
+}
+
+#[derive(Serialize)]
+struct InfoBody {
+    rules: String,
+    entries: usize,
+    start: [u8; 14],
+}
+
+/// A position, with the player to move first as in the engine protocol.
+#[derive(Deserialize)]
+struct PositionRequest {
+    board: [u8; 14],
+}
+
+#[derive(Deserialize)]
+struct AiMoveRequest {
+    board: [u8; 14],
+    /// Chance of a random move instead of the best one.
+    #[serde(default)]
+    epsilon: f64,
+}
+
+#[derive(Serialize)]
+struct MovesBody {
+    moves: Vec<String>,
+}
+
+#[derive(Serialize)]
+struct MoveValue {
+    #[serde(rename = "move")]
+    action: String,
+    /// Value for the player making the move.
+    value: f64,
+    /// The board after the move, seen by the player who made it.
+    board: [u8; 14],
+}
+
+#[derive(Serialize)]
+struct EvaluationBody {
+    /// Value of the position for the player to move.
+    value: f64,
+    moves: Vec<MoveValue>,
+    /// The move the AI would make, if the game is not over.
+    best: Option<String>,
+}
+
+#[derive(Serialize)]
+struct AiMoveBody {
+    #[serde(rename = "move")]
+    action: String,
+    value: f64,
+    /// The board after the move, seen by the player to move next.
+    board: [u8; 14],
+}
+
+#[derive(Deserialize, Default)]
+struct NewGameRequest {
+    /// Let the AI make the first move.
+    #[serde(default)]
+    ai_first: bool,
+    #[serde(default)]
+    epsilon: f64,
+}
+
+#[derive(Deserialize)]
+struct GameMoveRequest {
+    #[serde(rename = "move")]
+    action: String,
+}
+
+#[derive(Serialize)]
+struct GameResult {
+    /// `human`, `ai` or `draw`.
+    winner: &'static str,
+    human: u8,
+    ai: u8,
+}
+
+#[derive(Serialize)]
+struct GameBody {
+    id: u64,
+    /// The board seen by the human, whose houses and store come first.
+    board: [u8; 14],
+    /// `human` or `ai`, or nothing once the game is over.
+    to_move: Option<&'static str>,
+    /// Every move so far, each from the point of view of the player making it.
+    history: Vec<String>,
+    legal_moves: Vec<String>,
+    last_ai_move: Option<String>,
+    result: Option<GameResult>,
+}
+
+/// A game between a client and the AI.
+struct Game {
+    /// The board seen by the human.
+    board: GameState,
+    human_to_move: bool,
+    epsilon: f64,
+    history: Vec<String>,
+    last_ai_move: Option<String>,
+}
+
+/// Answers the JSON API, holding the table and the games in progress.
+///
+/// - `GET /`: the rules, table size and starting board
+/// - `POST /moves` `{"board"}`: the legal moves
+/// - `POST /evaluate` `{"board"}`: the position's value and each move's, with the best move
+/// - `POST /ai-move` `{"board", "epsilon"?}`: the AI's move and the board after it
+/// - `POST /games` `{"ai_first"?, "epsilon"?}`: start a game against the AI
+/// - `GET /games/ID`: a game's state
+/// - `POST /games/ID/move` `{"move"}`: play a move and get the AI's reply
+/// - `DELETE /games/ID`: end a game
+///
+/// Boards are the fourteen slots with the player to move first (the human's for games), holding
+/// no more seeds than the rules start with, and moves are written as in the engine protocol,
+/// e.g. `3-1`. At most `MAX_GAMES` games are kept; starting another ends the oldest.
+pub struct Server {
+    rules: Rules,
+    table: Box<dyn ValueTable>,
+    rng: StdRng,
+    games: HashMap<u64, Game>,
+    next_id: u64,
+    request_timeout: Duration,
+}
+
+fn parse_body<T: DeserializeOwned>(request: &Request) -> Result<T, Response> {
+    serde_json::from_slice(&request.body)
+        .map_err(|err| Response::error(400, format!("bad request body: {}", err)))
+}
+
+fn legal_moves(state: &GameState) -> Vec<String> {
+    if state.is_ended() {
+        return Vec::new();
+    }
+    state.gen_actions().map(format_move).collect()
+}
+
+impl Server {
+    pub fn new(rules: Rules, table: Box<dyn ValueTable>, rng: StdRng) -> Server {
+        Server {
+            rules,
+            table,
+            rng,
+            games: HashMap::new(),
+            next_id: 1,
+            request_timeout: REQUEST_TIMEOUT,
+        }
+    }
+
+    /// Answer requests one connection at a time, forever.
+    pub fn serve(&mut self, listener: &TcpListener) -> io::Result<()> {
+        for stream in listener.incoming() {
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(err) => {
+                    // Most likely the client gave up, or we are out of file descriptors for now
+                    warn!("Could not accept a connection: {}", err);
+                    thread::sleep(ACCEPT_BACKOFF);
+                    continue;
+                }
+            };
+            if let Err(err) = self.serve_connection(stream) {
+                warn!("Dropped a connection: {}", err);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn serve_connection(&mut self, stream: TcpStream) -> io::Result<()> {
+        let mut reader = BufReader::new(DeadlineStream {
+            stream: stream.try_clone()?,
+            deadline: Instant::now() + self.request_timeout,
+        });
+        let response = match read_request(&mut reader) {
+            Ok(request) => {
+                let response = self.handle(&request);
+                info!("{} {} -> {}", request.method, request.path, response.status);
+                response
+            }
+            Err(response) => response,
+        };
+        write_response(&mut &stream, &response)
+    }
+
+    /// The response to `request`.
+    pub fn handle(&mut self, request: &Request) -> Response {
+        let path: Vec<&str> = request
+            .path
+            .split('?')
+            .next()
+            .unwrap_or("")
+            .split('/')
+            .filter(|part| !part.is_empty())
+            .collect();
+        let result = match (request.method.as_str(), path.as_slice()) {
+            // Browsers check before calling from another origin
+            ("OPTIONS", _) => Ok(Response {
+                status: 204,
+                body: String::new(),
+            }),
+            ("GET", []) => Ok(Response::ok(&InfoBody {
+                rules: self.rules.to_string(),
+                entries: self.table.entries(),
+                start: self.rules.starting_state().houses,
+            })),
+            ("POST", ["moves"]) => parse_body::<PositionRequest>(request)
+                .and_then(|body| self.board(body.board))
+                .map(|state| {
+                    Response::ok(&MovesBody {
+                        moves: legal_moves(&state),
+                    })
+                }),
+            ("POST", ["evaluate"]) => parse_body::<PositionRequest>(request)
+                .and_then(|body| self.board(body.board))
+                .map(|state| self.evaluate(state)),
+            ("POST", ["ai-move"]) => {
+                parse_body::<AiMoveRequest>(request).and_then(|body| self.ai_move(body))
+            }
+            ("POST", ["games"]) => {
+                let body = if request.body.is_empty() {
+                    Ok(NewGameRequest::default())
+                } else {
+                    parse_body::<NewGameRequest>(request)
+                };
+                body.map(|body| self.new_game(body))
+            }
+            (method, ["games", id, rest @ ..]) => match id.parse::<u64>() {
+                Ok(id) if self.games.contains_key(&id) => match (method, rest) {
+                    ("GET", []) => Ok(Response::ok(&self.game_body(id))),
+                    ("DELETE", []) => {
+                        self.games.remove(&id);
+                        Ok(Response {
+                            status: 204,
+                            body: String::new(),
+                        })
+                    }
+                    ("POST", ["move"]) => parse_body::<GameMoveRequest>(request)
+                        .and_then(|body| self.play(id, &body.action)),
+                    (_, [] | ["move"]) => Err(Response::error(405, "method not allowed")),
+                    _ => Err(Response::error(404, "no such endpoint")),
+                },
+                _ => Err(Response::error(404, format!("no game {}", id))),
+            },
+            (_, [] | ["moves" | "evaluate" | "ai-move" | "games"]) => {
+                Err(Response::error(405, "method not allowed"))
+            }
+            _ => Err(Response::error(404, "no such endpoint")),
+        };
+        result.unwrap_or_else(|response| response)
+    }
+
+    /// A board sent by a client, if it could come up under the rules being played.
+    fn board(&self, houses: [u8; 14]) -> Result<GameState, Response> {
+        let state = GameState { houses };
+        self.rules
+            .check_board(&state)
+            .map_err(|err| Response::error(400, err))?;
+        Ok(state)
+    }
+
+    fn evaluate(&self, state: GameState) -> Response {
+        let table = self.table.as_ref();
+        let mut moves = Vec::new();
+        if !state.is_ended() {
+            for action in state.gen_actions() {
+                let mut after = state;
+                after.evaluate_action(action);
+                moves.push(MoveValue {
+                    action: format_move(action),
+                    value: canonical::value(table, &after),
+                    board: after.houses,
+                });
+            }
+        }
+        // The first of the best, as the AI plays
+        let mut best: Option<&MoveValue> = None;
+        for choice in &moves {
+            if best.is_none_or(|best| choice.value > best.value) {
+                best = Some(choice);
+            }
+        }
+        Response::ok(&EvaluationBody {
+            value: canonical::value_to_move(table, &state),
+            best: best.map(|best| best.action.clone()),
+            moves,
+        })
+    }
+
+    /// The AI's move at `state`, seen by the AI, and what it is worth to it.
+    fn pick(&mut self, state: &GameState, epsilon: f64) -> (Action, f64) {
+        state.pick_action(epsilon.clamp(0.0, 1.0), self.table.as_ref(), &mut self.rng)
+    }
+
+    fn ai_move(&mut self, body: AiMoveRequest) -> Result<Response, Response> {
+        let mut state = self.board(body.board)?;
+        if state.is_ended() {
+            return Err(Response::error(400, "the game is over"));
+        }
+        let (action, value) = self.pick(&state, body.epsilon);
+        state.evaluate_action(action);
+        state.swap_board();
+        Ok(Response::ok(&AiMoveBody {
+            action: format_move(action),
+            value,
+            board: state.houses,
+        }))
+    }
+
+    fn new_game(&mut self, body: NewGameRequest) -> Response {
+        if self.games.len() >= MAX_GAMES
+            && let Some(&oldest) = self.games.keys().min()
+        {
+            self.games.remove(&oldest);
+        }
+        let id = self.next_id;
+        self.next_id += 1;
+        self.games.insert(
+            id,
+            Game {
+                board: self.rules.starting_state(),
+                human_to_move: !body.ai_first,
+                epsilon: body.epsilon,
+                history: Vec::new(),
+                last_ai_move: None,
+            },
+        );
+        if body.ai_first {
+            self.ai_turn(id);
+        }
+        Response::json(201, &self.game_body(id))
+    }
+
+    /// Play the AI's turn in game `id`, if it is the AI's to play.
+    fn ai_turn(&mut self, id: u64) {
+        let game = &self.games[&id];
+        if game.human_to_move || game.board.is_ended() {
+            return;
+        }
+        let mut board = game.board;
+        board.swap_board();
+        let (action, _) = self.pick(&board, game.epsilon);
+        board.evaluate_action(action);
+        board.swap_board();
+        let game = self.games.get_mut(&id).unwrap();
+        game.board = board;
+        game.human_to_move = true;
+        game.history.push(format_move(action));
+        game.last_ai_move = Some(format_move(action));
+    }
+
+    fn play(&mut self, id: u64, text: &str) -> Result<Response, Response> {
+        let game = self.games.get_mut(&id).unwrap();
+        if game.board.is_ended() {
+            return Err(Response::error(400, "the game is over"));
+        }
+        let action = parse_move(&game.board, text).map_err(|err| Response::error(400, err))?;
+        game.board.evaluate_action(action);
+        game.history.push(format_move(action));
+        game.human_to_move = false;
+        self.ai_turn(id);
+        Ok(Response::ok(&self.game_body(id)))
+    }
+
+    fn game_body(&self, id: u64) -> GameBody {
+        let game = &self.games[&id];
+        let result = game.board.is_won().map(|outcome| {
+            let score = outcome.score();
+            GameResult {
+                winner: match outcome {
+                    Outcome::P1win(_) => "human",
+                    Outcome::P2win(_) => "ai",
+                    Outcome::Tie(_) => "draw",
+                },
+                human: score.p1,
+                ai: score.p2,
+            }
+        });
+        let to_move = match (&result, game.human_to_move) {
+            (Some(_), _) => None,
+            (None, true) => Some("human"),
+            (None, false) => Some("ai"),
+        };
+        GameBody {
+            id,
+            board: game.board.houses,
+            to_move,
+            history: game.history.clone(),
+            legal_moves: if to_move == Some("human") {
+                legal_moves(&game.board)
+            } else {
+                Vec::new()
+            },
+            last_ai_move: game.last_ai_move.clone(),
+            result,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mancala::ValueFunction;
+    use rand::SeedableRng;
+    use serde_json::Value;
+
+    fn server() -> Server {
+        let rules = Rules::Kalah { seeds: 4 };
+        let mut table = ValueFunction::new();
+        let mut after = rules.starting_state();
+        after.evaluate_action(parse_move(&after, "5").unwrap());
+        table.insert(after, 0.75);
+        Server::new(rules, Box::new(table), StdRng::seed_from_u64(0))
+    }
+
+    fn call(server: &mut Server, method: &str, path: &str, body: &str) -> (u16, Value) {
+        let response = server.handle(&Request {
+            method: method.to_string(),
+            path: path.to_string(),
+            body: body.as_bytes().to_vec(),
+        });
+        let body = if response.body.is_empty() {
+            Value::Null
+        } else {
+            serde_json::from_str(&response.body).unwrap()
+        };
+        (response.status, body)
+    }
+
+    const START: &str = r#"{"board": [4,4,4,4,4,4,0,4,4,4,4,4,4,0]}"#;
+
+    #[test]
+    fn test_analysis() {
+        let mut server = server();
+        let (status, info) = call(&mut server, "GET", "/", "");
+        assert_eq!((status, info["entries"].as_u64()), (200, Some(1)));
+        let (_, moves) = call(&mut server, "POST", "/moves", START);
+        assert_eq!(moves["moves"].as_array().unwrap().len(), 10);
+        assert_eq!(moves["moves"][2], "3-1");
+        let (_, evaluation) = call(&mut server, "POST", "/evaluate", START);
+        assert_eq!(evaluation["best"], "5");
+        assert_eq!(evaluation["moves"][8]["value"], 0.75);
+        let (_, ai) = call(&mut server, "POST", "/ai-move", START);
+        assert_eq!(ai["move"], "5");
+        assert_eq!(ai["board"][13], 1);
+
+        assert_eq!(call(&mut server, "POST", "/moves", "{}").0, 400);
+        // Boards that could not come up under the rules, like one that overflows a house
+        let overfull = r#"{"board": [255,4,4,4,4,4,0,4,4,4,4,4,4,0]}"#;
+        for endpoint in ["/moves", "/evaluate", "/ai-move"] {
+            let (status, error) = call(&mut server, "POST", endpoint, overfull);
+            assert_eq!(status, 400);
+            assert!(error["error"].as_str().unwrap().contains("seeds"));
+        }
+        assert_eq!(call(&mut server, "GET", "/moves", "").0, 405);
+        assert_eq!(call(&mut server, "GET", "/nowhere", "").0, 404);
+    }
+
+    #[test]
+    fn test_game() {
+        let mut server = server();
+        let (status, game) = call(&mut server, "POST", "/games", "");
+        assert_eq!((status, game["id"].as_u64()), (201, Some(1)));
+        assert_eq!(game["to_move"], "human");
+        let (status, error) = call(&mut server, "POST", "/games/1/move", r#"{"move": "3"}"#);
+        assert_eq!(status, 400);
+        assert_eq!(error["error"], "illegal move '3'");
+        let (status, game) = call(&mut server, "POST", "/games/1/move", r#"{"move": "3-1"}"#);
+        assert_eq!(status, 200);
+        assert_eq!(game["history"].as_array().unwrap().len(), 2);
+        assert_eq!(game["to_move"], "human");
+        assert_eq!(game["history"][1], game["last_ai_move"]);
+
+        let (_, second) = call(&mut server, "POST", "/games", r#"{"ai_first": true}"#);
+        assert_eq!(second["id"], 2);
+        // The AI opened with the move its table likes
+        assert_eq!(second["history"][0], "5");
+        assert_eq!(call(&mut server, "GET", "/games/1", "").1, game);
+        assert_eq!(call(&mut server, "DELETE", "/games/1", "").0, 204);
+        assert_eq!(call(&mut server, "GET", "/games/1", "").0, 404);
+
+        // Games left lying around make way for new ones
+        for _ in 0..MAX_GAMES {
+            call(&mut server, "POST", "/games", "");
+        }
+        assert_eq!(server.games.len(), MAX_GAMES);
+        assert_eq!(call(&mut server, "GET", "/games/2", "").0, 404);
+        assert_eq!(call(&mut server, "GET", "/games/3", "").0, 200);
+    }
+
+    #[test]
+    fn test_head_limit() {
+        let long = format!(
+            "GET / HTTP/1.1\r\nX-Padding: {}\r\n\r\n",
+            "x".repeat(MAX_HEAD)
+        );
+        let response = read_request(&mut io::Cursor::new(long)).unwrap_err();
+        assert_eq!(response.status, 431);
+        let fine = "GET / HTTP/1.1\r\nHost: localhost\r\n\r\n";
+        assert_eq!(read_request(&mut io::Cursor::new(fine)).unwrap().path, "/");
+    }
+
+    #[test]
+    fn test_slow_client() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let handle = std::thread::spawn(move || {
+            let mut server = server();
+            server.request_timeout = Duration::from_millis(300);
+            let (stream, _) = listener.accept().unwrap();
+            server.serve_connection(stream).unwrap();
+        });
+        // Every byte comes in well within a read's time, but the request as a whole takes too long
+        let mut stream = TcpStream::connect(address).unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_millis(20)))
+            .unwrap();
+        let request = format!("GET / HTTP/1.1\r\nX-Padding: {}", "x".repeat(200));
+        for &byte in request.as_bytes() {
+            stream.write_all(&[byte]).unwrap();
+            // Stop as soon as the server answers
+            if matches!(stream.peek(&mut [0; 1]), Ok(read) if read > 0) {
+                break;
+            }
+        }
+        stream.set_read_timeout(None).unwrap();
+        let mut reply = String::new();
+        stream.read_to_string(&mut reply).unwrap();
+        assert!(reply.starts_with("HTTP/1.1 408 Request Timeout\r\n"));
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn test_local_client() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let handle = std::thread::spawn(move || {
+            let mut server = server();
+            for _ in 0..2 {
+                let (stream, _) = listener.accept().unwrap();
+                server.serve_connection(stream).unwrap();
+            }
+        });
+        let request = |raw: String| {
+            let mut stream = TcpStream::connect(address).unwrap();
+            stream.write_all(raw.as_bytes()).unwrap();
+            let mut reply = String::new();
+            stream.read_to_string(&mut reply).unwrap();
+            reply
+        };
+        let reply = request(format!(
+            "POST /ai-move HTTP/1.1\r\nHost: localhost\r\nContent-Type: application/json\r\n\
+             Content-Length: {}\r\n\r\n{}",
+            START.len(),
+            START
+        ));
+        assert!(reply.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(reply.ends_with(r#""value":0.75,"board":[5,5,4,4,4,4,0,4,4,4,4,0,5,1]}"#));
+        let reply = request("garbage\r\n\r\n".to_string());
+        assert!(reply.starts_with("HTTP/1.1 400 Bad Request\r\n"));
+        handle.join().unwrap();
+    }
+}
@@ -0,0 +1,245 @@
+use crate::eval::{self, Agent, AgentSpec};
+use crate::learning::{self, IntervalStats, TrainingConfig, TrainingObserver};
+use crate::mancala::{GameState, Rules, ValueFunction, ValueTable, VisitCounts};
+use crate::metrics::{MetricsLog, MetricsRecord};
+use crate::persist::{self, TrainingMetadata, TrainingRun};
+use rand::rngs::StdRng;
+use std::collections::HashMap;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Opponent the table is measured against at every report.
+pub struct Baseline {
+    pub spec: AgentSpec,
+    pub values: Box<dyn ValueTable>,
+    /// Pairs of games per evaluation.
+    pub pairs: usize,
+    /// Kept apart from the training RNG so evaluating does not change what is learned.
+    pub rng: StdRng,
+}
+
+/// A training run writing to a value file: keeps the file's metadata up to date, saves
+/// checkpoints and logs metrics.
+pub struct TrainingSession {
+    path: PathBuf,
+    metadata: TrainingMetadata,
+    previous_episodes: usize,
+    pub starting_state: GameState,
+    pub checkpoint_every: Option<usize>,
+    pub report_every: usize,
+    pub metrics: Option<MetricsLog>,
+    pub baseline: Option<Baseline>,
+}
+
+impl TrainingSession {
+    /// Start a run of `config` under `rules` on the value file at `path`, continuing from its
+    /// contents if `resume` is set. Returns the session with the values and visit counts to
+    /// train.
+    pub fn start(
+        path: &Path,
+        resume: bool,
+        seed: u64,
+        config: &TrainingConfig,
+        rules: Rules,
+    ) -> io::Result<(TrainingSession, ValueFunction, VisitCounts)> {
+        let (values, visits, mut metadata) = if resume {
+            let file = persist::load(path)?;
+            persist::check_rules(file.metadata.rules, rules)?;
+            println!(
+                "Resuming from {} entries after {} episodes",
+                file.values.len(),
+                file.metadata.total_episodes
+            );
+            (file.values, file.visits, file.metadata)
+        } else {
+            (
+                HashMap::with_capacity(1_000),
+                HashMap::with_capacity(1_000),
+                TrainingMetadata::new(rules),
+            )
+        };
+        let previous_episodes = metadata.total_episodes;
+        metadata.runs.push(TrainingRun {
+            seed,
+            episodes: 0,
+            config: config.clone(),
+        });
+        let session = TrainingSession {
+            path: path.to_path_buf(),
+            metadata,
+            previous_episodes,
+            starting_state: rules.starting_state(),
+            checkpoint_every: None,
+            report_every: 1000,
+            metrics: None,
+            baseline: None,
+        };
+        Ok((session, values, visits))
+    }
+
+    /// Episodes in the value file before this run.
+    pub fn previous_episodes(&self) -> usize {
+        self.previous_episodes
+    }
+
+    /// Save the values and visit counts with the metadata after `episodes` episodes of this run.
+    pub fn save(
+        &mut self,
+        values: &ValueFunction,
+        visits: &VisitCounts,
+        episodes: usize,
+    ) -> io::Result<()> {
+        self.metadata.total_episodes = self.previous_episodes + episodes;
+        self.metadata.runs.last_mut().unwrap().episodes = episodes;
+        persist::save(&self.path, &self.metadata, values, visits)
+    }
+
+    /// Build the metrics for an interval, evaluating against the baseline if there is one, and
+    /// append them to the metrics log if there is one.
+    pub fn record_interval(
+        &mut self,
+        stats: &IntervalStats,
+        values: &ValueFunction,
+    ) -> MetricsRecord {
+        let mut record = MetricsRecord::new(stats, self.previous_episodes);
+        if let Some(baseline) = &mut self.baseline
+            && baseline.pairs > 0
+        {
+            let table = Agent {
+                spec: AgentSpec::Table(None),
+                values,
+            };
+            let opponent = Agent {
+                spec: baseline.spec.clone(),
+                values: baseline.values.as_ref(),
+            };
+            let stats = eval::evaluate(
+                &table,
+                &opponent,
+                self.starting_state,
+                baseline.pairs,
+                2,
+                &mut baseline.rng,
+            );
+            record.baseline = Some(baseline.spec.to_string());
+            record.baseline_win_rate = Some(stats.win_rate());
+        }
+        if let Some(log) = &mut self.metrics
+            && let Err(err) = log.write(&record)
+        {
+            eprintln!("Error writing training metrics: {}", err);
+        }
+        record
+    }
+}
+
+impl TrainingObserver for TrainingSession {
+    fn previous_episodes(&self) -> usize {
+        self.previous_episodes
+    }
+
+    fn report_every(&self) -> usize {
+        self.report_every
+    }
+
+    fn report(&mut self, stats: &IntervalStats, values: &ValueFunction) {
+        if stats.episodes <= self.report_every {
+            println!("Game length histogram:");
+            learning::dump_counter_stats(&[], true);
+        }
+        learning::dump_counter_stats(&stats.game_lengths, false);
+        let record = self.record_interval(stats, values);
+        if let (Some(baseline), Some(win_rate)) = (&record.baseline, record.baseline_win_rate) {
+            println!("Win rate against {}: {:.3}", baseline, win_rate);
+        }
+    }
+
+    fn checkpoint_every(&self) -> Option<usize> {
+        self.checkpoint_every
+    }
+
+    fn checkpoint(&mut self, values: &ValueFunction, visits: &VisitCounts, episodes: usize) {
+        match self.save(values, visits, episodes) {
+            Ok(()) => println!("Saved checkpoint after {} episodes", episodes),
+            Err(err) => eprintln!("Error saving checkpoint: {}", err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::reward::RewardScheme;
+    use rand::SeedableRng;
+
+    /// Keeps the epsilon of every episode, carrying on from the session's earlier episodes.
+    struct Epsilons {
+        session: TrainingSession,
+        seen: Vec<f64>,
+    }
+
+    impl TrainingObserver for Epsilons {
+        fn previous_episodes(&self) -> usize {
+            self.session.previous_episodes()
+        }
+
+        fn report_every(&self) -> usize {
+            1
+        }
+
+        fn report(&mut self, stats: &IntervalStats, _values: &ValueFunction) {
+            self.seen.push(stats.epsilon);
+        }
+    }
+
+    #[test]
+    fn test_resume_schedules() {
+        let path = std::env::temp_dir().join(format!("mancala-resume-{}.dat", std::process::id()));
+        let rules = Rules::Kalah { seeds: 2 };
+        let config = TrainingConfig {
+            epsilon: "linear:1.0,0.0,20".parse().unwrap(),
+            learning_rate: "0.1".parse().unwrap(),
+            discount_factor: 1.0,
+            episodes: 10,
+            reward: RewardScheme::WinLoss,
+            replay: None,
+            pool: None,
+        };
+        let mut rng = StdRng::seed_from_u64(0);
+        let (mut session, mut values, mut visits) =
+            TrainingSession::start(&path, false, 0, &config, rules).unwrap();
+        session.report_every = 100;
+        let start = rules.starting_state();
+        let episodes = learning::sarsa_loop(
+            &mut values,
+            &mut visits,
+            start,
+            &config,
+            &mut rng,
+            &mut session,
+        );
+        session.save(&values, &visits, episodes).unwrap();
+
+        // The resumed run picks the decay up at episode 10, rather than starting it again
+        let (session, mut values, mut visits) =
+            TrainingSession::start(&path, true, 0, &config, rules).unwrap();
+        let mut observer = Epsilons {
+            session,
+            seen: Vec::new(),
+        };
+        learning::sarsa_loop(
+            &mut values,
+            &mut visits,
+            start,
+            &config,
+            &mut rng,
+            &mut observer,
+        );
+        let _ = std::fs::remove_file(&path);
+        let expected: Vec<f64> = (10..20)
+            .map(|episode| config.epsilon.value(episode, 0))
+            .collect();
+        assert_eq!(observer.seen, expected);
+        assert!(observer.seen[0] <= 0.5);
+    }
+}
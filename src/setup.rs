@@ -0,0 +1,374 @@
+use crate::animation::{ANIMATION_SPEEDS, AnimationSpeed};
+use crate::eval::AgentSpec;
+use crate::mancala::{Rules, ValueTable};
+use crate::player::Strategy;
+use ratatui::{
+    Frame,
+    layout::{Constraint, Rect},
+    style::{Color, Modifier, Style},
+    text::{Line, Span},
+    widgets::{Block, Borders, Paragraph, Row, Table, Wrap},
+};
+use std::collections::HashMap;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Loads the value file at a path for games under the given rules.
+pub type RulesLoader<'a> = dyn Fn(&Path, Rules) -> io::Result<Box<dyn ValueTable>> + 'a;
+
+/// Who the person at the keyboard plays in a new game.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OpponentKind {
+    /// The value file, as the AI.
+    Table,
+    AlphaBeta,
+    Greedy,
+    Random,
+    /// A second person at the same keyboard.
+    HotSeat,
+}
+
+const OPPONENT_KINDS: [OpponentKind; 5] = [
+    OpponentKind::Table,
+    OpponentKind::AlphaBeta,
+    OpponentKind::Greedy,
+    OpponentKind::Random,
+    OpponentKind::HotSeat,
+];
+
+/// Seeds per house on offer.
+const MIN_SEEDS: u8 = 1;
+const MAX_SEEDS: u8 = 6;
+/// Deepest alpha-beta search on offer; deeper ones take too long to wait for.
+const MAX_DEPTH: u32 = 10;
+/// How often the table opponent can be made to play a random move instead of its best one.
+const EPSILONS: [f64; 4] = [0.0, 0.1, 0.25, 0.5];
+
+/// Everything that decides how a game in the TUI is played.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GameSetup {
+    pub rules: Rules,
+    /// Whether the person at the keyboard (or player 1, in a hot-seat game) moves first.
+    pub human_first: bool,
+    pub opponent: OpponentKind,
+    /// How deep the alpha-beta opponent searches.
+    pub depth: u32,
+    /// How often the table opponent plays a random move.
+    pub epsilon: f64,
+    /// Value file to play with and analyse moves by.
+    pub table: PathBuf,
+    /// How fast moves are shown being sown.
+    pub animation: AnimationSpeed,
+}
+
+impl GameSetup {
+    pub fn new(table: PathBuf) -> GameSetup {
+        GameSetup {
+            rules: Rules::Kalah { seeds: 4 },
+            human_first: true,
+            opponent: OpponentKind::Table,
+            depth: 4,
+            epsilon: 0.0,
+            table,
+            animation: AnimationSpeed::Normal,
+        }
+    }
+
+    /// The opponent as an agent; `None` for a hot-seat game.
+    pub fn agent(&self) -> Option<AgentSpec> {
+        match self.opponent {
+            OpponentKind::Table => Some(AgentSpec::Table(Some(self.table.clone()))),
+            OpponentKind::AlphaBeta => Some(AgentSpec::Baseline(Strategy::AlphaBeta(self.depth))),
+            OpponentKind::Greedy => Some(AgentSpec::Baseline(Strategy::Greedy)),
+            OpponentKind::Random => Some(AgentSpec::Baseline(Strategy::Random)),
+            OpponentKind::HotSeat => None,
+        }
+    }
+
+    /// Load the value file for this game. Only a table opponent has to have it: anyone else is
+    /// played without move analysis if it will not load, and the reason comes back as a warning.
+    pub fn load(
+        &self,
+        loader: &RulesLoader,
+    ) -> Result<(Box<dyn ValueTable>, Option<String>), String> {
+        match loader(&self.table, self.rules) {
+            Ok(table) => Ok((table, None)),
+            Err(err) if self.opponent == OpponentKind::Table => {
+                Err(format!("Error loading {}: {}", self.table.display(), err))
+            }
+            Err(err) => Ok((
+                Box::new(HashMap::new()),
+                Some(format!(
+                    "No move analysis ({}: {})",
+                    self.table.display(),
+                    err
+                )),
+            )),
+        }
+    }
+}
+
+/// The settings on the setup screen, in order.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Field {
+    Seeds,
+    FirstMove,
+    Opponent,
+    Strength,
+    Table,
+    Animation,
+}
+
+const FIELDS: [Field; 6] = [
+    Field::Seeds,
+    Field::FirstMove,
+    Field::Opponent,
+    Field::Strength,
+    Field::Table,
+    Field::Animation,
+];
+
+/// Step through `choices` from `current` by `step`, wrapping round.
+fn cycle<T: PartialEq + Copy>(choices: &[T], current: T, step: i32) -> T {
+    let index = choices
+        .iter()
+        .position(|&choice| choice == current)
+        .unwrap_or(0) as i32;
+    choices[(index + step).rem_euclid(choices.len() as i32) as usize]
+}
+
+/// The new-game setup screen: a setup being edited, one field at a time.
+pub struct SetupForm {
+    pub setup: GameSetup,
+    selected: usize,
+    /// Why the last attempt to start a game failed.
+    pub error: Option<String>,
+    /// Whether there is a game to go back to instead of starting one.
+    pub cancellable: bool,
+}
+
+impl SetupForm {
+    pub fn new(setup: GameSetup, cancellable: bool) -> SetupForm {
+        SetupForm {
+            setup,
+            selected: 0,
+            error: None,
+            cancellable,
+        }
+    }
+
+    fn field(&self) -> Field {
+        FIELDS[self.selected]
+    }
+
+    pub fn up(&mut self) {
+        self.selected = (self.selected + FIELDS.len() - 1) % FIELDS.len();
+    }
+
+    pub fn down(&mut self) {
+        self.selected = (self.selected + 1) % FIELDS.len();
+    }
+
+    /// Whether typing goes into the selected field rather than being taken as a command.
+    pub fn is_editing_text(&self) -> bool {
+        self.field() == Field::Table
+    }
+
+    /// Change the selected setting to the next (`step` 1) or previous (-1) choice.
+    pub fn change(&mut self, step: i32) {
+        let field = self.field();
+        let setup = &mut self.setup;
+        match field {
+            Field::Seeds => {
+                let Rules::Kalah { seeds } = &mut setup.rules;
+                let choices: Vec<u8> = (MIN_SEEDS..=MAX_SEEDS).collect();
+                *seeds = cycle(&choices, *seeds, step);
+            }
+            Field::FirstMove => setup.human_first = !setup.human_first,
+            Field::Opponent => setup.opponent = cycle(&OPPONENT_KINDS, setup.opponent, step),
+            Field::Strength => match setup.opponent {
+                OpponentKind::Table => setup.epsilon = cycle(&EPSILONS, setup.epsilon, step),
+                OpponentKind::AlphaBeta => {
+                    let depths: Vec<u32> = (1..=MAX_DEPTH).collect();
+                    setup.depth = cycle(&depths, setup.depth, step);
+                }
+                _ => {}
+            },
+            Field::Table => {}
+            Field::Animation => setup.animation = cycle(&ANIMATION_SPEEDS, setup.animation, step),
+        }
+        self.error = None;
+    }
+
+    pub fn type_char(&mut self, c: char) {
+        if self.is_editing_text() {
+            let mut path = self.setup.table.as_os_str().to_os_string();
+            path.push(c.to_string());
+            self.setup.table = PathBuf::from(path);
+            self.error = None;
+        }
+    }
+
+    pub fn backspace(&mut self) {
+        if self.is_editing_text() {
+            let mut path = self.setup.table.to_string_lossy().into_owned();
+            path.pop();
+            self.setup.table = PathBuf::from(path);
+            self.error = None;
+        }
+    }
+
+    /// Each setting's label and current value, in field order.
+    fn rows(&self) -> Vec<(&'static str, String)> {
+        let setup = &self.setup;
+        let Rules::Kalah { seeds } = setup.rules;
+        let strength = match setup.opponent {
+            OpponentKind::Table => format!("{:.0}% random moves", setup.epsilon * 100.0),
+            OpponentKind::AlphaBeta => format!("searches {} turns ahead", setup.depth),
+            _ => String::from("-"),
+        };
+        let opponent = match setup.opponent {
+            OpponentKind::Table => "AI (value file)",
+            OpponentKind::AlphaBeta => "Alpha-beta search",
+            OpponentKind::Greedy => "Greedy",
+            OpponentKind::Random => "Random",
+            OpponentKind::HotSeat => "Human (hot seat)",
+        };
+        let first_move = match (setup.opponent, setup.human_first) {
+            (OpponentKind::HotSeat, true) => "Player 1",
+            (OpponentKind::HotSeat, false) => "Player 2",
+            (_, true) => "You",
+            (_, false) => "Opponent",
+        };
+        vec![
+            ("Seeds per house", seeds.to_string()),
+            ("Moves first", first_move.to_string()),
+            ("Opponent", opponent.to_string()),
+            ("Strength", strength),
+            ("Value file", setup.table.display().to_string()),
+            ("Animation", setup.animation.name().to_string()),
+        ]
+    }
+}
+
+pub fn draw_setup(f: &mut Frame, form: &SetupForm, area: Rect) {
+    let block = Block::default().borders(Borders::ALL).title("New Game");
+    let inner = block.inner(area);
+    f.render_widget(block, area);
+
+    let rows: Vec<Row> = form
+        .rows()
+        .into_iter()
+        .enumerate()
+        .map(|(i, (label, value))| {
+            let style = if i == form.selected {
+                Style::default()
+                    .fg(Color::Yellow)
+                    .add_modifier(Modifier::BOLD)
+            } else {
+                Style::default()
+            };
+            let value = if i == form.selected && !form.is_editing_text() {
+                format!("< {} >", value)
+            } else if i == form.selected {
+                format!("{}_", value)
+            } else {
+                value
+            };
+            Row::new(vec![Span::styled(label, style), Span::styled(value, style)])
+        })
+        .collect();
+    let table = Table::new(rows, [Constraint::Length(18), Constraint::Min(20)]);
+    let table_area = Rect::new(
+        inner.x + 1,
+        inner.y + 1,
+        inner.width.saturating_sub(2),
+        FIELDS.len() as u16,
+    );
+    f.render_widget(table, table_area);
+
+    let key = Style::default().fg(Color::Yellow);
+    let mut help = vec![
+        Span::styled("↑/↓", key),
+        Span::raw(" Setting | "),
+        Span::styled("←/→", key),
+        Span::raw(" Change | "),
+        Span::styled("Enter", key),
+        Span::raw(" Start | "),
+        Span::styled("Esc", key),
+    ];
+    help.push(Span::raw(if form.cancellable { " Back" } else { " Quit" }));
+    let mut lines = vec![Line::from(help)];
+    if let Some(error) = &form.error {
+        lines.push(Line::from(Span::styled(
+            error.as_str(),
+            Style::default().fg(Color::Red),
+        )));
+    }
+    let help_area = Rect::new(
+        inner.x + 1,
+        inner.y + FIELDS.len() as u16 + 2,
+        inner.width.saturating_sub(2),
+        inner.height.saturating_sub(FIELDS.len() as u16 + 2),
+    );
+    f.render_widget(Paragraph::new(lines).wrap(Wrap { trim: true }), help_area);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_setup_form() {
+        let mut form = SetupForm::new(GameSetup::new(PathBuf::from("train.dat")), false);
+        form.change(1);
+        form.change(1);
+        assert_eq!(form.setup.rules, Rules::Kalah { seeds: 6 });
+        form.change(1);
+        assert_eq!(form.setup.rules, Rules::Kalah { seeds: 1 });
+
+        form.down();
+        form.change(1);
+        assert!(!form.setup.human_first);
+        form.down();
+        form.change(1);
+        form.down();
+        form.change(-1);
+        assert_eq!(
+            form.setup.agent(),
+            Some(AgentSpec::Baseline(Strategy::AlphaBeta(3)))
+        );
+
+        // Typing only goes into the file name
+        form.type_char('x');
+        assert_eq!(form.setup.table, PathBuf::from("train.dat"));
+        form.down();
+        form.backspace();
+        form.type_char('x');
+        assert_eq!(form.setup.table, PathBuf::from("train.dax"));
+        form.down();
+        form.change(-1);
+        form.change(-1);
+        assert_eq!(form.setup.animation, AnimationSpeed::Off);
+        form.up();
+        form.up();
+        form.up();
+        form.change(1);
+        form.change(1);
+        form.change(1);
+        assert_eq!(form.setup.agent(), None);
+    }
+
+    #[test]
+    fn test_load() {
+        let missing = |_: &Path, _: Rules| -> io::Result<Box<dyn ValueTable>> {
+            Err(io::Error::new(io::ErrorKind::NotFound, "no such file"))
+        };
+        let mut setup = GameSetup::new(PathBuf::from("missing.dat"));
+        assert!(setup.load(&missing).is_err());
+        setup.opponent = OpponentKind::Greedy;
+        let (table, warning) = setup.load(&missing).unwrap();
+        assert_eq!(table.entries(), 0);
+        assert!(warning.unwrap().contains("missing.dat"));
+    }
+}
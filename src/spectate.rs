@@ -0,0 +1,462 @@
+use crate::canonical;
+use crate::eval::{Agent, AgentSpec};
+use crate::mancala::{GameState, Outcome};
+use crate::player::{Player, Strategy};
+use crate::search::{alpha_beta, store_margin};
+use crate::tui::{GameHistory, draw_board, restore_terminal, setup_terminal};
+use crossterm::event::{self, Event, KeyCode};
+use rand::rngs::StdRng;
+use ratatui::{
+    Frame, Terminal,
+    backend::Backend,
+    layout::{Constraint, Direction, Layout, Rect},
+    style::{Color, Modifier, Style},
+    text::{Line, Span},
+    widgets::{
+        Axis, Block, Borders, Chart, Dataset, GraphType, List, ListItem, ListState, Paragraph,
+    },
+};
+use std::error::Error;
+use std::io;
+use std::time::{Duration, Instant};
+
+/// Slowest and fastest autoplay, as the delay between moves.
+const MAX_DELAY: Duration = Duration::from_secs(5);
+const MIN_DELAY: Duration = Duration::from_millis(50);
+/// Colours of the two agents' lines on the chart, in seat order.
+const AGENT_COLORS: [Color; 2] = [Color::Cyan, Color::Magenta];
+
+/// How `agent` rates the game for the player to move at `position`, from 0 (lost) to 1 (won).
+/// Tables give their value; baselines, which have no values, give their view of the store margin
+/// scaled so that winning every seed is 1.
+fn rate_to_move(agent: &Agent, position: &GameState, seeds: f64) -> f64 {
+    let margin = match &agent.spec {
+        AgentSpec::Table(_) => return canonical::value_to_move(agent.values, position),
+        AgentSpec::Baseline(Strategy::AlphaBeta(depth)) if !position.is_ended() => {
+            alpha_beta(position, *depth, &store_margin).score
+        }
+        AgentSpec::Baseline(_) => store_margin(position),
+    };
+    (0.5 + margin / (2.0 * seeds)).clamp(0.0, 1.0)
+}
+
+/// How `agent` rates the game for the player who just moved into `afterstate`.
+fn rate(agent: &Agent, afterstate: &GameState, seeds: f64) -> f64 {
+    if afterstate.is_ended() {
+        return match &agent.spec {
+            AgentSpec::Table(_) => canonical::value(agent.values, afterstate),
+            AgentSpec::Baseline(_) => rate_to_move(agent, afterstate, seeds),
+        };
+    }
+    let mut position = *afterstate;
+    position.swap_board();
+    1.0 - rate_to_move(agent, &position, seeds)
+}
+
+/// Fresh players for both agents. Each sees the board from its own side.
+fn new_players(agents: &[Agent; 2], starting_state: GameState) -> [Box<dyn Player>; 2] {
+    let mut swapped = starting_state;
+    swapped.swap_board();
+    [
+        agents[0].spec.player(starting_state),
+        agents[1].spec.player(swapped),
+    ]
+}
+
+/// Watching two agents play, with the game shown from the first one's side. Moves are only worked
+/// out as playback reaches them, so stepping back and forth is free.
+pub struct Spectator<'a> {
+    agents: [Agent<'a>; 2],
+    players: [Box<dyn Player>; 2],
+    starting_state: GameState,
+    /// Which agent moved first.
+    first: usize,
+    /// Each agent's view of the game: the same boards, seen from the first agent's side, rated by
+    /// that agent as the first agent's chances.
+    histories: [GameHistory; 2],
+    /// The position on show, as an index into the history.
+    shown: usize,
+    playing: bool,
+    delay: Duration,
+    last_step: Instant,
+    rng: StdRng,
+}
+
+impl<'a> Spectator<'a> {
+    pub fn new(
+        agents: [Agent<'a>; 2],
+        starting_state: GameState,
+        delay: Duration,
+        rng: StdRng,
+    ) -> Self {
+        let players = new_players(&agents, starting_state);
+        let mut spectator = Spectator {
+            players,
+            agents,
+            starting_state,
+            first: 0,
+            histories: [
+                GameHistory::new(starting_state, 0.5),
+                GameHistory::new(starting_state, 0.5),
+            ],
+            shown: 0,
+            playing: true,
+            delay: delay.clamp(MIN_DELAY, MAX_DELAY),
+            last_step: Instant::now(),
+            rng,
+        };
+        spectator.start(0);
+        spectator
+    }
+
+    /// Start a new game with `first` moving first.
+    pub fn restart(&mut self, first: usize) {
+        self.players = new_players(&self.agents, self.starting_state);
+        self.start(first);
+    }
+
+    /// Rate the opening and rewind playback for a game with `first` moving first, between players
+    /// that have not moved yet.
+    fn start(&mut self, first: usize) {
+        let mut swapped = self.starting_state;
+        swapped.swap_board();
+        self.first = first;
+        let seeds = self.seeds();
+        let start = if first == 0 {
+            self.starting_state
+        } else {
+            swapped
+        };
+        for (history, agent) in self.histories.iter_mut().zip(&self.agents) {
+            let value = rate_to_move(agent, &start, seeds);
+            let first_agents_chance = if first == 0 { value } else { 1.0 - value };
+            *history = GameHistory::new(self.starting_state, first_agents_chance);
+        }
+        self.shown = 0;
+        self.last_step = Instant::now();
+    }
+
+    fn seeds(&self) -> f64 {
+        self.starting_state
+            .houses
+            .iter()
+            .map(|&seeds| seeds as f64)
+            .sum()
+    }
+
+    /// The board as it stands after the last move played, from the first agent's side.
+    fn latest(&self) -> GameState {
+        self.players[0].current_state()
+    }
+
+    pub fn is_game_over(&self) -> bool {
+        self.latest().is_ended()
+    }
+
+    /// Number of moves played so far.
+    fn plies(&self) -> usize {
+        self.histories[0].actions().len()
+    }
+
+    /// Which agent made move `ply`, counting from 0.
+    fn mover(&self, ply: usize) -> usize {
+        (self.first + ply) % 2
+    }
+
+    /// Have the agent to move play, and let both agents rate the result.
+    fn play_next(&mut self) {
+        let mover = self.mover(self.plies());
+        let action = self.players[mover].take_action(self.agents[mover].values, 0.0, &mut self.rng);
+        self.players[1 - mover].opponent_plays(action);
+        let afterstate = self.players[mover].current_state();
+        let board = self.latest();
+        let seeds = self.seeds();
+        for (history, agent) in self.histories.iter_mut().zip(&self.agents) {
+            let value = rate(agent, &afterstate, seeds);
+            let first_agents_chance = if mover == 0 { value } else { 1.0 - value };
+            history.add_move(board, first_agents_chance, action);
+        }
+    }
+
+    /// Show the next position, playing a move if it has not been played yet. Returns whether
+    /// there was one.
+    pub fn step_forward(&mut self) -> bool {
+        if self.shown == self.plies() {
+            if self.is_game_over() {
+                return false;
+            }
+            self.play_next();
+        }
+        self.shown += 1;
+        true
+    }
+
+    pub fn step_back(&mut self) {
+        self.shown = self.shown.saturating_sub(1);
+    }
+
+    pub fn toggle_playing(&mut self) {
+        self.playing = !self.playing;
+        self.last_step = Instant::now();
+    }
+
+    pub fn faster(&mut self) {
+        self.delay = (self.delay / 2).max(MIN_DELAY);
+    }
+
+    pub fn slower(&mut self) {
+        self.delay = (self.delay * 2).min(MAX_DELAY);
+    }
+
+    /// Move on if autoplay is due to, pausing at the end of the game.
+    pub fn tick(&mut self) {
+        if self.playing && self.last_step.elapsed() >= self.delay {
+            if !self.step_forward() {
+                self.playing = false;
+            }
+            self.last_step = Instant::now();
+        }
+    }
+
+    fn status(&self) -> String {
+        let names = self.names();
+        if self.shown == self.plies() && self.is_game_over() {
+            let mut final_state = self.latest();
+            final_state.finalize_game();
+            return match final_state.is_won() {
+                Some(Outcome::P1win(score)) => {
+                    format!("GAME OVER! {} won {}-{}", names[0], score.p1, score.p2)
+                }
+                Some(Outcome::P2win(score)) => {
+                    format!("GAME OVER! {} won {}-{}", names[1], score.p2, score.p1)
+                }
+                Some(Outcome::Tie(score)) => format!("GAME OVER! Tied {}-{}", score.p1, score.p2),
+                None => String::from("GAME OVER!"),
+            };
+        }
+        let to_move = &names[self.mover(self.shown)];
+        format!(
+            "Move {} of {}, {} to move. {} every {} ms",
+            self.shown,
+            self.plies(),
+            to_move,
+            if self.playing { "Playing" } else { "Paused" },
+            self.delay.as_millis()
+        )
+    }
+
+    fn names(&self) -> [String; 2] {
+        [
+            self.agents[0].spec.to_string(),
+            self.agents[1].spec.to_string(),
+        ]
+    }
+}
+
+pub fn draw(f: &mut Frame, spectator: &Spectator) {
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Length(8), // Game board
+            Constraint::Length(3), // Status message
+            Constraint::Min(6),    // Moves played
+            Constraint::Length(9), // Win probability chart
+            Constraint::Length(3), // Controls
+        ])
+        .split(f.size());
+
+    let names = spectator.names();
+    let board = spectator.histories[0].states()[spectator.shown];
+    let title = format!("{} vs {}", names[0], names[1]);
+    draw_board(f, &board, (&names[0], &names[1]), &title, chunks[0]);
+
+    let status = Paragraph::new(Line::from(Span::styled(
+        spectator.status(),
+        Style::default().fg(Color::Yellow),
+    )))
+    .block(Block::default().borders(Borders::ALL).title("Status"));
+    f.render_widget(status, chunks[1]);
+
+    draw_moves(f, spectator, chunks[2]);
+    draw_evaluations(f, spectator, chunks[3]);
+
+    let key = Style::default().fg(Color::Yellow);
+    let controls = Paragraph::new(Line::from(vec![
+        Span::styled("Space", key),
+        Span::raw(" Play/Pause | "),
+        Span::styled("←/→", key),
+        Span::raw(" Step | "),
+        Span::styled("+/-", key),
+        Span::raw(" Speed | "),
+        Span::styled("Home/End", key),
+        Span::raw(" Start/Latest | "),
+        Span::styled("r", key),
+        Span::raw(" Rematch | "),
+        Span::styled("q", key),
+        Span::raw(" Quit"),
+    ]))
+    .block(Block::default().borders(Borders::ALL).title("Controls"));
+    f.render_widget(controls, chunks[4]);
+}
+
+fn draw_moves(f: &mut Frame, spectator: &Spectator, area: Rect) {
+    let names = spectator.names();
+    let items: Vec<ListItem> = spectator.histories[0]
+        .actions()
+        .iter()
+        .enumerate()
+        .map(|(ply, action)| {
+            let mover = spectator.mover(ply);
+            ListItem::new(Line::from(vec![
+                Span::raw(format!("{:>3}. ", ply + 1)),
+                Span::styled(
+                    format!("{:<24}", names[mover]),
+                    Style::default().fg(AGENT_COLORS[mover]),
+                ),
+                Span::raw(action.to_string()),
+            ]))
+        })
+        .collect();
+    let list = List::new(items)
+        .block(Block::default().borders(Borders::ALL).title("Moves"))
+        .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+    let mut state = ListState::default();
+    // The move that led to the position on show
+    state.select(spectator.shown.checked_sub(1));
+    f.render_stateful_widget(list, area, &mut state);
+}
+
+fn draw_evaluations(f: &mut Frame, spectator: &Spectator, area: Rect) {
+    let names = spectator.names();
+    // Only as far as the position on show, so stepping back does not give away what comes next
+    let data: Vec<Vec<(f64, f64)>> = spectator
+        .histories
+        .iter()
+        .map(|history| history.get_data_points()[..=spectator.shown].to_vec())
+        .collect();
+    let datasets = data
+        .iter()
+        .zip(&names)
+        .zip(AGENT_COLORS)
+        .map(|((points, name), color)| {
+            Dataset::default()
+                .name(name.as_str())
+                .marker(ratatui::symbols::Marker::Braille)
+                .graph_type(GraphType::Line)
+                .style(Style::default().fg(color))
+                .data(points)
+        })
+        .collect();
+    let last = spectator.shown.max(1) as f64;
+    let chart = Chart::new(datasets)
+        .block(
+            Block::default()
+                .title(format!("Each agent's view of {}'s chances", names[0]))
+                .borders(Borders::ALL),
+        )
+        .x_axis(
+            Axis::default()
+                .title("Move")
+                .bounds([0.0, last])
+                .labels(vec![Span::raw("0"), Span::raw(format!("{}", last))]),
+        )
+        .y_axis(Axis::default().bounds([0.0, 1.0]).labels(vec![
+            Span::raw("0.0"),
+            Span::raw("0.5"),
+            Span::raw("1.0"),
+        ]));
+    f.render_widget(chart, area);
+}
+
+/// Watch `agents` play in the TUI, one move every `delay` to begin with.
+pub fn run_spectator(
+    agents: [Agent; 2],
+    starting_state: GameState,
+    delay: Duration,
+    rng: StdRng,
+) -> Result<(), Box<dyn Error>> {
+    let mut terminal = setup_terminal()?;
+    let mut spectator = Spectator::new(agents, starting_state, delay, rng);
+    let res = run(&mut terminal, &mut spectator);
+    restore_terminal(&mut terminal)?;
+    res?;
+    Ok(())
+}
+
+fn run<B: Backend>(terminal: &mut Terminal<B>, spectator: &mut Spectator) -> io::Result<()> {
+    loop {
+        terminal.draw(|f| draw(f, spectator))?;
+        if event::poll(Duration::from_millis(20))?
+            && let Event::Key(key) = event::read()?
+        {
+            match key.code {
+                KeyCode::Char('q') => return Ok(()),
+                KeyCode::Char(' ') => spectator.toggle_playing(),
+                KeyCode::Right => {
+                    spectator.step_forward();
+                }
+                KeyCode::Left => spectator.step_back(),
+                KeyCode::Char('+') | KeyCode::Char('=') => spectator.faster(),
+                KeyCode::Char('-') => spectator.slower(),
+                KeyCode::Home => spectator.shown = 0,
+                KeyCode::End => spectator.shown = spectator.plies(),
+                // The other agent starts the rematch
+                KeyCode::Char('r') => spectator.restart(1 - spectator.first),
+                _ => {}
+            }
+        }
+        spectator.tick();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mancala::ValueFunction;
+    use rand::SeedableRng;
+
+    #[test]
+    fn test_spectator() {
+        let values = ValueFunction::new();
+        let agent = |spec: &str| Agent {
+            spec: spec.parse().unwrap(),
+            values: &values,
+        };
+        let mut spectator = Spectator::new(
+            [agent("greedy"), agent("alphabeta:2")],
+            GameState::new(4),
+            Duration::ZERO,
+            StdRng::seed_from_u64(0),
+        );
+        while spectator.step_forward() {}
+        let plies = spectator.plies();
+        assert!(spectator.is_game_over() && plies > 4);
+        assert_eq!(spectator.histories[0].states()[plies], spectator.latest());
+
+        // Stepping back replays nothing
+        spectator.step_back();
+        spectator.step_back();
+        assert_eq!(spectator.shown, plies - 2);
+        assert!(spectator.step_forward());
+        assert_eq!(spectator.plies(), plies);
+
+        // The first agent's chances are rated on the same scale by both
+        for history in &spectator.histories {
+            assert!(
+                history
+                    .get_data_points()
+                    .iter()
+                    .all(|&(_, v)| (0.0..=1.0).contains(&v))
+            );
+        }
+
+        let mut terminal = Terminal::new(ratatui::backend::TestBackend::new(100, 40)).unwrap();
+        terminal.draw(|f| draw(f, &spectator)).unwrap();
+
+        spectator.restart(1);
+        assert_eq!((spectator.plies(), spectator.shown), (0, 0));
+        spectator.step_forward();
+        assert_eq!(spectator.mover(0), 1);
+        assert_ne!(spectator.latest(), GameState::new(4));
+    }
+}
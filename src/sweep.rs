@@ -0,0 +1,356 @@
+use crate::eval::{self, Agent, AgentSpec};
+use crate::learning::{self, TrainingConfig};
+use crate::mancala::{GameState, ValueFunction, VisitCounts};
+use crate::reward::RewardScheme;
+use crate::schedule::Schedule;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{RngCore, SeedableRng};
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fmt::{self, Display, Formatter};
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering as AtomicOrdering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How configurations are picked from a `SearchSpace`: every combination (`grid`), or N
+/// combinations drawn at random (`random:N`).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Search {
+    Grid,
+    Random(usize),
+}
+
+impl Display for Search {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Search::Grid => write!(f, "grid"),
+            Search::Random(trials) => write!(f, "random:{}", trials),
+        }
+    }
+}
+
+impl FromStr for Search {
+    type Err = String;
+
+    fn from_str(spec: &str) -> Result<Search, String> {
+        match spec.split_once(':') {
+            None if spec == "grid" => Ok(Search::Grid),
+            Some(("random", trials)) => match trials.parse::<usize>() {
+                Ok(trials) if trials > 0 => Ok(Search::Random(trials)),
+                _ => Err(format!("bad number of trials '{}'", trials)),
+            },
+            _ => Err(format!(
+                "unknown search '{}' (expected grid or random:TRIALS)",
+                spec
+            )),
+        }
+    }
+}
+
+/// Candidate values for each hyperparameter.
+#[derive(Debug, Clone)]
+pub struct SearchSpace {
+    pub epsilon: Vec<Schedule>,
+    pub learning_rate: Vec<Schedule>,
+    pub discount_factor: Vec<f64>,
+    pub episodes: Vec<usize>,
+    pub reward: Vec<RewardScheme>,
+}
+
+impl SearchSpace {
+    /// The configurations to train, in a fixed order.
+    pub fn configs(&self, search: Search, rng: &mut dyn RngCore) -> Vec<TrainingConfig> {
+        let config = |epsilon: &Schedule, learning_rate: &Schedule, discount, episodes, reward| {
+            TrainingConfig {
+                epsilon: epsilon.clone(),
+                learning_rate: learning_rate.clone(),
+                discount_factor: discount,
+                episodes,
+                reward,
+                replay: None,
+                pool: None,
+            }
+        };
+        match search {
+            Search::Grid => {
+                let mut configs = Vec::new();
+                for epsilon in &self.epsilon {
+                    for learning_rate in &self.learning_rate {
+                        for &discount in &self.discount_factor {
+                            for &episodes in &self.episodes {
+                                for &reward in &self.reward {
+                                    configs.push(config(
+                                        epsilon,
+                                        learning_rate,
+                                        discount,
+                                        episodes,
+                                        reward,
+                                    ));
+                                }
+                            }
+                        }
+                    }
+                }
+                configs
+            }
+            Search::Random(trials) => (0..trials)
+                .map(|_| {
+                    config(
+                        self.epsilon.choose(rng).unwrap(),
+                        self.learning_rate.choose(rng).unwrap(),
+                        *self.discount_factor.choose(rng).unwrap(),
+                        *self.episodes.choose(rng).unwrap(),
+                        *self.reward.choose(rng).unwrap(),
+                    )
+                })
+                .collect(),
+        }
+    }
+}
+
+/// One trained and evaluated configuration.
+#[derive(Debug, Clone)]
+pub struct Trial {
+    /// Position in the list of configurations.
+    pub index: usize,
+    pub config: TrainingConfig,
+    /// Seed the table was trained with; `train -s SEED` with the same settings reproduces it.
+    pub seed: u64,
+    /// Mean score against the baseline and its 95% confidence half-width.
+    pub score: (f64, f64),
+    /// Mean store margin against the baseline and its 95% confidence half-width.
+    pub margin: (f64, f64),
+    pub table_size: usize,
+    pub elapsed: Duration,
+}
+
+impl Trial {
+    /// Better trials sort first: by score, then margin, then earlier index.
+    fn rank(&self, other: &Trial) -> Ordering {
+        other
+            .score
+            .0
+            .total_cmp(&self.score.0)
+            .then(other.margin.0.total_cmp(&self.margin.0))
+            .then(self.index.cmp(&other.index))
+    }
+}
+
+/// Results of a sweep, best trial first.
+pub struct SweepResult {
+    pub baseline: AgentSpec,
+    pub eval_games: usize,
+    pub trials: Vec<Trial>,
+}
+
+impl Display for SweepResult {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "Sweep of {} configurations, each evaluated over {} games against {}",
+            self.trials.len(),
+            self.eval_games,
+            self.baseline
+        )?;
+        writeln!(f)?;
+        writeln!(
+            f,
+            "{:>4}  {:<13}  {:<13}  {:<22}  {:<22}  {:>8}  {:>8}  {:<7}  {:>8}  {:>20}  {:>8}",
+            "Rank",
+            "Score",
+            "Margin",
+            "Epsilon",
+            "Learning rate",
+            "Discount",
+            "Episodes",
+            "Reward",
+            "Entries",
+            "Seed",
+            "Time"
+        )?;
+        for (rank, trial) in self.trials.iter().enumerate() {
+            writeln!(
+                f,
+                "{:>4}  {:<13}  {:<13}  {:<22}  {:<22}  {:>8}  {:>8}  {:<7}  {:>8}  {:>20}  {:>7.1}s",
+                rank + 1,
+                format!("{:.3} ± {:.3}", trial.score.0, trial.score.1),
+                format!("{:+.2} ± {:.2}", trial.margin.0, trial.margin.1),
+                trial.config.epsilon.to_string(),
+                trial.config.learning_rate.to_string(),
+                trial.config.discount_factor,
+                trial.config.episodes,
+                trial.config.reward.to_string(),
+                trial.table_size,
+                trial.seed,
+                trial.elapsed.as_secs_f64()
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// The best trial's trained table, kept so it can be saved.
+pub struct BestModel {
+    pub trial: Trial,
+    pub values: ValueFunction,
+    pub visits: VisitCounts,
+}
+
+/// Train every configuration from scratch on up to `jobs` threads, then play `eval_pairs` pairs
+/// of games against `baseline` with each. Trial `i` trains with seed `seed + i + 1`.
+pub fn run(
+    configs: &[TrainingConfig],
+    baseline: &Agent,
+    starting_state: GameState,
+    eval_pairs: usize,
+    seed: u64,
+    jobs: usize,
+) -> (SweepResult, Option<BestModel>) {
+    let next = AtomicUsize::new(0);
+    let trials = Mutex::new(Vec::with_capacity(configs.len()));
+    let best: Mutex<Option<BestModel>> = Mutex::new(None);
+    thread::scope(|scope| {
+        for _ in 0..jobs.clamp(1, configs.len().max(1)) {
+            scope.spawn(|| {
+                loop {
+                    let index = next.fetch_add(1, AtomicOrdering::Relaxed);
+                    let Some(config) = configs.get(index) else {
+                        break;
+                    };
+                    let trial_seed = seed.wrapping_add(index as u64 + 1);
+                    let (trial, values, visits) = run_trial(
+                        index,
+                        config,
+                        baseline,
+                        starting_state,
+                        eval_pairs,
+                        trial_seed,
+                    );
+                    info!(
+                        "Trial {} scored {:.3} against {}",
+                        index, trial.score.0, baseline.spec
+                    );
+                    let mut best = best.lock().unwrap();
+                    if best
+                        .as_ref()
+                        .is_none_or(|best| trial.rank(&best.trial) == Ordering::Less)
+                    {
+                        *best = Some(BestModel {
+                            trial: trial.clone(),
+                            values,
+                            visits,
+                        });
+                    }
+                    drop(best);
+                    trials.lock().unwrap().push(trial);
+                }
+            });
+        }
+    });
+    let mut trials = trials.into_inner().unwrap();
+    trials.sort_by(Trial::rank);
+    let result = SweepResult {
+        baseline: baseline.spec.clone(),
+        eval_games: 2 * eval_pairs,
+        trials,
+    };
+    (result, best.into_inner().unwrap())
+}
+
+fn run_trial(
+    index: usize,
+    config: &TrainingConfig,
+    baseline: &Agent,
+    starting_state: GameState,
+    eval_pairs: usize,
+    seed: u64,
+) -> (Trial, ValueFunction, VisitCounts) {
+    let start = Instant::now();
+    // Training first, from the same seed, matches what `train` does
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut values = HashMap::with_capacity(1_000);
+    let mut visits = HashMap::with_capacity(1_000);
+    learning::sarsa_loop(
+        &mut values,
+        &mut visits,
+        starting_state,
+        config,
+        &mut rng,
+        &mut (),
+    );
+    let table = Agent {
+        spec: AgentSpec::Table(None),
+        values: &values,
+    };
+    let stats = eval::evaluate(&table, baseline, starting_state, eval_pairs, 2, &mut rng);
+    let trial = Trial {
+        index,
+        config: config.clone(),
+        seed,
+        score: stats.score(),
+        margin: stats.margin(),
+        table_size: values.len(),
+        elapsed: start.elapsed(),
+    };
+    (trial, values, visits)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn space() -> SearchSpace {
+        SearchSpace {
+            epsilon: vec!["0.1".parse().unwrap(), "linear:0.5,0.0,10".parse().unwrap()],
+            learning_rate: vec!["0.2".parse().unwrap(), "visits".parse().unwrap()],
+            discount_factor: vec![1.0],
+            episodes: vec![5, 10],
+            reward: vec![RewardScheme::WinLoss],
+        }
+    }
+
+    #[test]
+    fn test_parse_search() {
+        for spec in ["grid", "random:8"] {
+            assert_eq!(spec.parse::<Search>().unwrap().to_string(), spec);
+        }
+        assert!("random:0".parse::<Search>().is_err());
+        assert!("random".parse::<Search>().is_err());
+    }
+
+    #[test]
+    fn test_configs() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let grid = space().configs(Search::Grid, &mut rng);
+        assert_eq!(grid.len(), 8);
+        assert_eq!(grid[1].episodes, 10);
+        assert_eq!(space().configs(Search::Random(3), &mut rng).len(), 3);
+    }
+
+    #[test]
+    fn test_sweep() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let configs = space().configs(Search::Random(3), &mut rng);
+        let empty = HashMap::new();
+        let baseline = Agent {
+            spec: AgentSpec::Baseline(crate::player::Strategy::Random),
+            values: &empty,
+        };
+        let (serial, best) = run(&configs, &baseline, GameState::new(4), 3, 7, 1);
+        let (parallel, _) = run(&configs, &baseline, GameState::new(4), 3, 7, 3);
+        assert_eq!(serial.trials.len(), 3);
+        for (a, b) in serial.trials.iter().zip(&parallel.trials) {
+            assert_eq!((a.index, a.seed, a.score), (b.index, b.seed, b.score));
+        }
+        for pair in serial.trials.windows(2) {
+            assert_ne!(pair[0].rank(&pair[1]), Ordering::Greater);
+        }
+        let best = best.unwrap();
+        assert_eq!(best.trial.index, serial.trials[0].index);
+        assert_eq!(best.values.len(), serial.trials[0].table_size);
+        assert!(serial.to_string().contains("Rank"));
+    }
+}
@@ -0,0 +1,441 @@
+use crate::eval::{self, Agent, margin_score};
+use crate::mancala::GameState;
+use rand::RngCore;
+use std::f64::consts::{LN_10, PI};
+use std::fmt::{self, Display, Formatter};
+use std::str::FromStr;
+
+/// How agents are paired each round.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    /// Every agent plays every other agent once per cycle (`roundrobin[:CYCLES]`).
+    RoundRobin { cycles: usize },
+    /// Agents with similar scores are paired each round, avoiding rematches (`swiss:ROUNDS`).
+    Swiss { rounds: usize },
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(spec: &str) -> Result<Format, String> {
+        let (kind, count) = match spec.split_once(':') {
+            Some((kind, count)) => match count.parse::<usize>() {
+                Ok(count) if count > 0 => (kind, Some(count)),
+                _ => return Err(format!("bad round count '{}'", count)),
+            },
+            None => (spec, None),
+        };
+        match (kind, count) {
+            ("roundrobin", cycles) => Ok(Format::RoundRobin {
+                cycles: cycles.unwrap_or(1),
+            }),
+            ("swiss", Some(rounds)) => Ok(Format::Swiss { rounds }),
+            ("swiss", None) => Err("swiss format needs a round count, e.g. swiss:5".to_string()),
+            _ => Err(format!(
+                "unknown format '{}' (expected roundrobin[:CYCLES] or swiss:ROUNDS)",
+                spec
+            )),
+        }
+    }
+}
+
+/// How ratings are computed. Both update once per round from all of that round's games.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RatingSystem {
+    /// Elo with the given K-factor (`elo[:K]`, default 16).
+    Elo { k: f64 },
+    /// Glicko-1, treating each round as a rating period (`glicko`).
+    Glicko,
+}
+
+impl FromStr for RatingSystem {
+    type Err = String;
+
+    fn from_str(spec: &str) -> Result<RatingSystem, String> {
+        match spec.split_once(':') {
+            None if spec == "elo" => Ok(RatingSystem::Elo { k: 16.0 }),
+            None if spec == "glicko" => Ok(RatingSystem::Glicko),
+            Some(("elo", k)) => match k.parse::<f64>() {
+                Ok(k) if k.is_finite() && k > 0.0 => Ok(RatingSystem::Elo { k }),
+                Ok(_) => Err(format!("bad K-factor '{}': it has to be above 0", k)),
+                Err(err) => Err(format!("bad K-factor '{}': {}", k, err)),
+            },
+            _ => Err(format!(
+                "unknown rating system '{}' (expected elo[:K] or glicko)",
+                spec
+            )),
+        }
+    }
+}
+
+const INITIAL_RATING: f64 = 1500.0;
+const INITIAL_DEVIATION: f64 = 350.0;
+const GLICKO_Q: f64 = LN_10 / 400.0;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Rating {
+    pub rating: f64,
+    /// Glicko rating deviation; unused by Elo.
+    pub deviation: f64,
+}
+
+fn expected_score(rating: f64, opponent: f64, g: f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf(-g * (rating - opponent) / 400.0))
+}
+
+fn glicko_g(deviation: f64) -> f64 {
+    1.0 / (1.0 + 3.0 * GLICKO_Q.powi(2) * deviation.powi(2) / PI.powi(2)).sqrt()
+}
+
+impl RatingSystem {
+    /// Update every agent's rating from the games of one round, each `(a, b, a's score)`.
+    fn update(&self, ratings: &mut [Rating], games: &[(usize, usize, f64)]) {
+        let before = ratings.to_vec();
+        // Each game counts once from each side's point of view
+        let results = |agent: usize| {
+            games.iter().filter_map(move |&(a, b, score)| {
+                if a == agent {
+                    Some((b, score))
+                } else if b == agent {
+                    Some((a, 1.0 - score))
+                } else {
+                    None
+                }
+            })
+        };
+        for (agent, rating) in ratings.iter_mut().enumerate() {
+            let r = before[agent].rating;
+            match *self {
+                RatingSystem::Elo { k } => {
+                    let delta: f64 = results(agent)
+                        .map(|(opp, score)| score - expected_score(r, before[opp].rating, 1.0))
+                        .sum();
+                    rating.rating += k * delta;
+                }
+                RatingSystem::Glicko => {
+                    let mut inv_d2 = 0.0;
+                    let mut delta = 0.0;
+                    for (opp, score) in results(agent) {
+                        let g = glicko_g(before[opp].deviation);
+                        let e = expected_score(r, before[opp].rating, g);
+                        inv_d2 += GLICKO_Q.powi(2) * g.powi(2) * e * (1.0 - e);
+                        delta += g * (score - e);
+                    }
+                    if inv_d2 == 0.0 {
+                        continue;
+                    }
+                    let precision = 1.0 / before[agent].deviation.powi(2) + inv_d2;
+                    rating.rating += GLICKO_Q / precision * delta;
+                    rating.deviation = (1.0 / precision).sqrt();
+                }
+            }
+        }
+    }
+}
+
+/// Ratings, standings and crosstable of a finished tournament.
+pub struct TournamentResult {
+    pub names: Vec<String>,
+    pub ratings: Vec<Rating>,
+    pub rating_system: RatingSystem,
+    /// `crosstable[i][j]` is (points scored by i against j, games between them).
+    pub crosstable: Vec<Vec<(f64, usize)>>,
+}
+
+impl TournamentResult {
+    /// Total (points, games) for each agent.
+    pub fn totals(&self) -> Vec<(f64, usize)> {
+        self.crosstable
+            .iter()
+            .map(|row| {
+                row.iter()
+                    .fold((0.0, 0), |acc, cell| (acc.0 + cell.0, acc.1 + cell.1))
+            })
+            .collect()
+    }
+
+    /// Agent indices, highest rated first.
+    pub fn ranking(&self) -> Vec<usize> {
+        let mut order: Vec<usize> = (0..self.names.len()).collect();
+        order.sort_by(|&a, &b| self.ratings[b].rating.total_cmp(&self.ratings[a].rating));
+        order
+    }
+}
+
+impl Display for TournamentResult {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let order = self.ranking();
+        let totals = self.totals();
+        let width = self.names.iter().map(String::len).max().unwrap_or(0).max(5);
+        writeln!(f, "Standings:")?;
+        writeln!(
+            f,
+            "{:>4}  {:<width$}  {:>7}  {:>5}  {:>9}",
+            "Rank", "Agent", "Rating", "RD", "Points",
+        )?;
+        for (rank, &i) in order.iter().enumerate() {
+            let deviation = match self.rating_system {
+                RatingSystem::Glicko => format!("{:.0}", self.ratings[i].deviation),
+                RatingSystem::Elo { .. } => "-".to_string(),
+            };
+            writeln!(
+                f,
+                "{:>4}  {:<width$}  {:>7.1}  {:>5}  {:>9}",
+                rank + 1,
+                self.names[i],
+                self.ratings[i].rating,
+                deviation,
+                format!("{}/{}", totals[i].0, totals[i].1),
+            )?;
+        }
+        writeln!(f)?;
+        writeln!(f, "Crosstable (points scored by row against column):")?;
+        write!(f, "{:>4}  {:<width$}", "", "")?;
+        for rank in 1..=order.len() {
+            write!(f, "  {:>9}", rank)?;
+        }
+        writeln!(f)?;
+        for (rank, &i) in order.iter().enumerate() {
+            write!(f, "{:>4}  {:<width$}", rank + 1, self.names[i])?;
+            for &j in &order {
+                let (points, games) = self.crosstable[i][j];
+                if i == j || games == 0 {
+                    write!(f, "  {:>9}", "-")?;
+                } else {
+                    write!(f, "  {:>9}", format!("{}/{}", points, games))?;
+                }
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+/// Pair agents for a Swiss round: sort by points (then rating) and pair each agent with the
+/// next one it has not met yet. With an odd number of agents one sits the round out: the lowest
+/// ranked of those who have not yet, going by `had_bye`. Returns the pairings and who sits out.
+fn swiss_pairings(
+    result: &TournamentResult,
+    had_bye: &[bool],
+) -> (Vec<(usize, usize)>, Option<usize>) {
+    let totals = result.totals();
+    let mut order: Vec<usize> = (0..result.names.len()).collect();
+    order.sort_by(|&a, &b| {
+        totals[b].0.total_cmp(&totals[a].0).then(
+            result.ratings[b]
+                .rating
+                .total_cmp(&result.ratings[a].rating),
+        )
+    });
+    let bye = (order.len() % 2 == 1).then(|| {
+        // Once everyone has sat out, the lowest ranked does again
+        let slot = order
+            .iter()
+            .rposition(|&a| !had_bye[a])
+            .unwrap_or(order.len() - 1);
+        order.remove(slot)
+    });
+    let mut pairings = Vec::new();
+    while let Some(a) = order.first().copied() {
+        order.remove(0);
+        if order.is_empty() {
+            break;
+        }
+        let opponent = order
+            .iter()
+            .position(|&b| result.crosstable[a][b].1 == 0)
+            .unwrap_or(0);
+        pairings.push((a, order.remove(opponent)));
+    }
+    (pairings, bye)
+}
+
+/// Run a tournament between `agents`. Every pairing in a round plays `pairs` pairs of games
+/// (see `eval::evaluate`), and ratings are updated at the end of each round.
+pub fn run(
+    agents: &[Agent],
+    format: Format,
+    rating_system: RatingSystem,
+    starting_state: GameState,
+    pairs: usize,
+    opening_plies: usize,
+    rng: &mut dyn RngCore,
+) -> TournamentResult {
+    let n = agents.len();
+    let mut result = TournamentResult {
+        names: agents.iter().map(|agent| agent.spec.to_string()).collect(),
+        ratings: vec![
+            Rating {
+                rating: INITIAL_RATING,
+                deviation: INITIAL_DEVIATION,
+            };
+            n
+        ],
+        rating_system,
+        crosstable: vec![vec![(0.0, 0); n]; n],
+    };
+    let rounds = match format {
+        Format::RoundRobin { cycles } => cycles,
+        Format::Swiss { rounds } => rounds,
+    };
+    let mut had_bye = vec![false; n];
+    for round in 0..rounds {
+        let pairings = match format {
+            Format::RoundRobin { .. } => (0..n)
+                .flat_map(|a| (a + 1..n).map(move |b| (a, b)))
+                .collect(),
+            Format::Swiss { .. } => {
+                let (pairings, bye) = swiss_pairings(&result, &had_bye);
+                if let Some(bye) = bye {
+                    info!("Round {}: {} sits out", round + 1, result.names[bye]);
+                    had_bye[bye] = true;
+                }
+                pairings
+            }
+        };
+        let mut games = Vec::new();
+        for (a, b) in pairings {
+            let stats = eval::evaluate(
+                &agents[a],
+                &agents[b],
+                starting_state,
+                pairs,
+                opening_plies,
+                rng,
+            );
+            for &(margin, _) in &stats.games {
+                let score = margin_score(margin);
+                games.push((a, b, score));
+                result.crosstable[a][b].0 += score;
+                result.crosstable[a][b].1 += 1;
+                result.crosstable[b][a].0 += 1.0 - score;
+                result.crosstable[b][a].1 += 1;
+            }
+            info!(
+                "Round {}: {} vs {}: {}",
+                round + 1,
+                result.names[a],
+                result.names[b],
+                stats
+            );
+        }
+        rating_system.update(&mut result.ratings, &games);
+        println!("Finished round {} of {}", round + 1, rounds);
+    }
+    result
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_rating_system() {
+        assert_eq!("elo:24".parse(), Ok(RatingSystem::Elo { k: 24.0 }));
+        for k in ["0", "-16", "NaN", "inf"] {
+            assert!(format!("elo:{}", k).parse::<RatingSystem>().is_err());
+        }
+    }
+
+    #[test]
+    fn test_swiss_byes() {
+        let mut result = TournamentResult {
+            names: vec![String::new(); 3],
+            ratings: vec![
+                Rating {
+                    rating: INITIAL_RATING,
+                    deviation: INITIAL_DEVIATION,
+                };
+                3
+            ],
+            rating_system: RatingSystem::Glicko,
+            crosstable: vec![vec![(0.0, 0); 3]; 3],
+        };
+        result.ratings[0].rating = 1600.0;
+        result.ratings[2].rating = 1400.0;
+        // However the ratings stand, each agent sits out once before anyone does twice
+        let mut had_bye = vec![false; 3];
+        for _ in 0..3 {
+            let (pairings, bye) = swiss_pairings(&result, &had_bye);
+            let bye = bye.unwrap();
+            assert!(!had_bye[bye]);
+            assert_eq!(pairings.len(), 1);
+            had_bye[bye] = true;
+        }
+        assert_eq!(swiss_pairings(&result, &had_bye).1, Some(2));
+        result.names.push(String::new());
+        result.ratings.push(result.ratings[1]);
+        result.crosstable = vec![vec![(0.0, 0); 4]; 4];
+        assert_eq!(swiss_pairings(&result, &[false; 4]).1, None);
+    }
+
+    #[test]
+    fn test_rating_updates() {
+        let mut ratings = vec![
+            Rating {
+                rating: INITIAL_RATING,
+                deviation: INITIAL_DEVIATION,
+            };
+            2
+        ];
+        let games = [(0, 1, 1.0), (0, 1, 1.0), (0, 1, 0.5)];
+        RatingSystem::Elo { k: 16.0 }.update(&mut ratings, &games);
+        assert_eq!(ratings[0].rating, 1516.0);
+        assert_eq!(ratings[1].rating, 1484.0);
+
+        let mut ratings = vec![
+            Rating {
+                rating: INITIAL_RATING,
+                deviation: INITIAL_DEVIATION,
+            };
+            3
+        ];
+        RatingSystem::Glicko.update(&mut ratings, &games);
+        assert!(ratings[0].rating > INITIAL_RATING && ratings[1].rating < INITIAL_RATING);
+        assert!(ratings[0].deviation < INITIAL_DEVIATION);
+        // An agent that did not play keeps its rating and deviation
+        assert_eq!(ratings[2].rating, INITIAL_RATING);
+        assert_eq!(ratings[2].deviation, INITIAL_DEVIATION);
+    }
+
+    #[test]
+    fn test_tournament() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let empty = HashMap::new();
+        let specs = ["random", "alphabeta:2", "greedy"];
+        let agents: Vec<Agent> = specs
+            .iter()
+            .map(|spec| Agent {
+                spec: spec.parse().unwrap(),
+                values: &empty,
+            })
+            .collect();
+        let result = run(
+            &agents,
+            "swiss:3".parse().unwrap(),
+            "glicko".parse().unwrap(),
+            GameState::new(4),
+            5,
+            2,
+            &mut rng,
+        );
+        let ranking = result.ranking();
+        assert_eq!(result.names[ranking[2]], "random");
+        let totals = result.totals();
+        // Three agents: one sits out each round, so 3 rounds of one 10-game pairing
+        assert_eq!(
+            totals.iter().map(|total| total.1).sum::<usize>(),
+            2 * 3 * 10
+        );
+        for i in 0..3 {
+            for j in 0..3 {
+                let (points, games) = result.crosstable[i][j];
+                assert_eq!(points + result.crosstable[j][i].0, games as f64);
+            }
+        }
+        assert!(result.to_string().contains("Crosstable"));
+    }
+}
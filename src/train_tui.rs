@@ -0,0 +1,463 @@
+use crate::learning::{self, GAME_LENGTH_BUCKETS, IntervalStats, TrainingConfig, TrainingObserver};
+use crate::mancala::{GameState, ValueFunction, VisitCounts};
+use crate::metrics::MetricsRecord;
+use crate::session::TrainingSession;
+use crate::tui;
+use crossterm::event::{self, Event, KeyCode};
+use rand::rngs::StdRng;
+use ratatui::{
+    Frame, Terminal,
+    backend::Backend,
+    layout::{Constraint, Direction, Layout, Rect},
+    style::{Color, Modifier, Style},
+    text::{Line, Span},
+    widgets::{Axis, BarChart, Block, Borders, Chart, Dataset, Gauge, GraphType, Paragraph},
+};
+use std::io;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+/// What the training thread tells the dashboard.
+enum TrainingUpdate {
+    Report(MetricsRecord),
+    Saved(usize),
+    SaveFailed(String),
+}
+
+/// Flags the dashboard sets to steer the training thread.
+#[derive(Default)]
+struct Controls {
+    paused: AtomicBool,
+    save_requested: AtomicBool,
+    stop: AtomicBool,
+}
+
+/// Runs inside the training thread, forwarding progress to the dashboard and pausing, saving or
+/// stopping when asked.
+struct DashboardObserver {
+    session: TrainingSession,
+    controls: Arc<Controls>,
+    updates: Sender<TrainingUpdate>,
+}
+
+impl DashboardObserver {
+    fn save(&mut self, values: &ValueFunction, visits: &VisitCounts, episodes: usize) {
+        let update = match self.session.save(values, visits, episodes) {
+            Ok(()) => TrainingUpdate::Saved(episodes),
+            Err(err) => TrainingUpdate::SaveFailed(err.to_string()),
+        };
+        // The dashboard may already have gone away, in which case nobody needs telling
+        let _ = self.updates.send(update);
+    }
+}
+
+impl TrainingObserver for DashboardObserver {
+    fn previous_episodes(&self) -> usize {
+        self.session.previous_episodes()
+    }
+
+    fn report_every(&self) -> usize {
+        self.session.report_every
+    }
+
+    fn report(&mut self, stats: &IntervalStats, values: &ValueFunction) {
+        let record = self.session.record_interval(stats, values);
+        let _ = self.updates.send(TrainingUpdate::Report(record));
+    }
+
+    fn checkpoint_every(&self) -> Option<usize> {
+        self.session.checkpoint_every
+    }
+
+    fn checkpoint(&mut self, values: &ValueFunction, visits: &VisitCounts, episodes: usize) {
+        self.save(values, visits, episodes);
+    }
+
+    fn after_episode(
+        &mut self,
+        values: &ValueFunction,
+        visits: &VisitCounts,
+        episodes: usize,
+    ) -> bool {
+        loop {
+            if self.controls.save_requested.swap(false, Ordering::Relaxed) {
+                self.save(values, visits, episodes);
+            }
+            if self.controls.stop.load(Ordering::Relaxed) {
+                return false;
+            }
+            if !self.controls.paused.load(Ordering::Relaxed) {
+                return true;
+            }
+            thread::sleep(Duration::from_millis(50));
+        }
+    }
+}
+
+/// What the training thread hands back: the session, the trained values and visit counts, and
+/// the number of episodes played.
+type TrainingResult = (TrainingSession, ValueFunction, VisitCounts, usize);
+
+/// Dashboard state
+struct Dashboard {
+    /// Episodes already in the value file when this run started.
+    start_episodes: usize,
+    /// Episodes this run was asked to play.
+    run_episodes: usize,
+    records: Vec<MetricsRecord>,
+    paused: bool,
+    finished: bool,
+    status_message: String,
+}
+
+impl Dashboard {
+    fn episodes_done(&self) -> usize {
+        self.records
+            .last()
+            .map_or(0, |record| record.episodes - self.start_episodes)
+    }
+
+    fn handle_update(&mut self, update: TrainingUpdate) {
+        match update {
+            TrainingUpdate::Report(record) => self.records.push(record),
+            TrainingUpdate::Saved(episodes) => {
+                self.status_message = format!("Saved checkpoint after {} episodes", episodes)
+            }
+            TrainingUpdate::SaveFailed(err) => {
+                self.status_message = format!("Error saving checkpoint: {}", err)
+            }
+        }
+    }
+
+    /// Chart bounds along the episode axis.
+    fn x_bounds(&self) -> [f64; 2] {
+        [
+            self.start_episodes as f64,
+            (self.start_episodes + self.run_episodes.max(1)) as f64,
+        ]
+    }
+
+    fn series(&self, metric: impl Fn(&MetricsRecord) -> Option<f64>) -> Vec<(f64, f64)> {
+        self.records
+            .iter()
+            .filter_map(|record| metric(record).map(|value| (record.episodes as f64, value)))
+            .collect()
+    }
+}
+
+/// Train in a background thread while showing live charts of its progress. Returns once the user
+/// quits, after training finishes or is stopped early.
+pub fn run_training_tui(
+    session: TrainingSession,
+    values: ValueFunction,
+    visits: VisitCounts,
+    starting_state: GameState,
+    config: TrainingConfig,
+    rng: StdRng,
+) -> io::Result<TrainingResult> {
+    let mut dashboard = Dashboard {
+        start_episodes: session.previous_episodes(),
+        run_episodes: config.episodes,
+        records: Vec::new(),
+        paused: false,
+        finished: false,
+        status_message: String::from("Training..."),
+    };
+    let controls = Arc::new(Controls::default());
+    let (updates, updates_rx) = mpsc::channel();
+    let mut observer = DashboardObserver {
+        session,
+        controls: controls.clone(),
+        updates,
+    };
+    let handle = thread::spawn(move || {
+        let (mut values, mut visits, mut rng) = (values, visits, rng);
+        let episodes = learning::sarsa_loop(
+            &mut values,
+            &mut visits,
+            starting_state,
+            &config,
+            &mut rng,
+            &mut observer,
+        );
+        (observer.session, values, visits, episodes)
+    });
+
+    let res = tui::setup_terminal().and_then(|mut terminal| {
+        let res = run_dashboard(
+            &mut terminal,
+            &mut dashboard,
+            &controls,
+            &updates_rx,
+            &handle,
+        );
+        tui::restore_terminal(&mut terminal)?;
+        res
+    });
+
+    // Make sure training winds down however the dashboard was left, even if it never came up
+    controls.stop.store(true, Ordering::Relaxed);
+    controls.paused.store(false, Ordering::Relaxed);
+    let result = handle
+        .join()
+        .map_err(|_| io::Error::other("training thread panicked"))?;
+    res?;
+    Ok(result)
+}
+
+fn run_dashboard<B: Backend>(
+    terminal: &mut Terminal<B>,
+    dashboard: &mut Dashboard,
+    controls: &Controls,
+    updates: &Receiver<TrainingUpdate>,
+    handle: &JoinHandle<TrainingResult>,
+) -> io::Result<()> {
+    loop {
+        // Check before draining, so every update sent before the thread ended gets shown
+        let finished = handle.is_finished();
+        while let Ok(update) = updates.try_recv() {
+            dashboard.handle_update(update);
+        }
+        if finished && !dashboard.finished {
+            dashboard.finished = true;
+            dashboard.status_message = format!(
+                "Training finished after {} episodes. Press 'q' to save and exit.",
+                dashboard.episodes_done()
+            );
+        }
+
+        terminal.draw(|f| draw(f, dashboard))?;
+
+        if event::poll(Duration::from_millis(100))?
+            && let Event::Key(key) = event::read()?
+        {
+            match key.code {
+                KeyCode::Char('q') => return Ok(()),
+                KeyCode::Char('p') | KeyCode::Char(' ') if !dashboard.finished => {
+                    dashboard.paused = !dashboard.paused;
+                    controls.paused.store(dashboard.paused, Ordering::Relaxed);
+                    dashboard.status_message = String::from(if dashboard.paused {
+                        "Paused. Press 'p' to resume."
+                    } else {
+                        "Training..."
+                    });
+                }
+                KeyCode::Char('s') if !dashboard.finished => {
+                    controls.save_requested.store(true, Ordering::Relaxed);
+                    dashboard.status_message = String::from("Saving checkpoint...");
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+/// UI rendering
+fn draw(f: &mut Frame, dashboard: &Dashboard) {
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Length(3), // Progress
+            Constraint::Length(3), // Latest numbers and status
+            Constraint::Min(16),   // Charts
+            Constraint::Length(3), // Controls
+        ])
+        .split(f.size());
+    let rows = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Ratio(1, 2), Constraint::Ratio(1, 2)])
+        .split(chunks[2]);
+    let top = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Ratio(1, 2), Constraint::Ratio(1, 2)])
+        .split(rows[0]);
+    let bottom = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Ratio(1, 2), Constraint::Ratio(1, 2)])
+        .split(rows[1]);
+
+    draw_progress(f, dashboard, chunks[0]);
+    draw_summary(f, dashboard, chunks[1]);
+
+    let td_errors = dashboard.series(|record| Some(record.mean_td_error));
+    let max_td_error = td_errors.iter().map(|point| point.1).fold(0.0, f64::max);
+    draw_line_chart(
+        f,
+        dashboard,
+        top[0],
+        "Mean |TD error|",
+        &td_errors,
+        [0.0, max_td_error * 1.1],
+        Color::Red,
+    );
+
+    let table_sizes = dashboard.series(|record| Some(record.table_size as f64));
+    let max_table_size = table_sizes.iter().map(|point| point.1).fold(0.0, f64::max);
+    draw_line_chart(
+        f,
+        dashboard,
+        top[1],
+        "Value table size",
+        &table_sizes,
+        [0.0, max_table_size * 1.1],
+        Color::Cyan,
+    );
+
+    let baseline = dashboard
+        .records
+        .iter()
+        .find_map(|record| record.baseline.clone())
+        .unwrap_or_else(|| String::from("baseline"));
+    let win_rates = dashboard.series(|record| record.baseline_win_rate);
+    draw_line_chart(
+        f,
+        dashboard,
+        bottom[0],
+        &format!("Win rate against {}", baseline),
+        &win_rates,
+        [0.0, 1.0],
+        Color::Green,
+    );
+
+    draw_game_lengths(f, dashboard, bottom[1]);
+    draw_controls(f, dashboard, chunks[3]);
+}
+
+fn draw_progress(f: &mut Frame, dashboard: &Dashboard, area: Rect) {
+    let done = dashboard.episodes_done();
+    let ratio = (done as f64 / dashboard.run_episodes.max(1) as f64).clamp(0.0, 1.0);
+    let gauge = Gauge::default()
+        .block(Block::default().borders(Borders::ALL).title("Progress"))
+        .gauge_style(Style::default().fg(Color::Green))
+        .ratio(ratio)
+        .label(format!("{}/{} episodes", done, dashboard.run_episodes));
+    f.render_widget(gauge, area);
+}
+
+fn draw_summary(f: &mut Frame, dashboard: &Dashboard, area: Rect) {
+    let mut spans = Vec::new();
+    if let Some(record) = dashboard.records.last() {
+        spans.push(Span::raw(format!(
+            "Total episodes: {} | Epsilon: {:.4} | Elapsed: {:.0}s | ",
+            record.episodes, record.epsilon, record.wall_time_secs
+        )));
+    }
+    let status_style = if dashboard.finished {
+        Style::default()
+            .fg(Color::Magenta)
+            .add_modifier(Modifier::BOLD)
+    } else if dashboard.paused {
+        Style::default().fg(Color::Yellow)
+    } else {
+        Style::default().fg(Color::Green)
+    };
+    spans.push(Span::styled(&dashboard.status_message, status_style));
+    let paragraph = Paragraph::new(Line::from(spans))
+        .block(Block::default().borders(Borders::ALL).title("Status"));
+    f.render_widget(paragraph, area);
+}
+
+fn draw_line_chart(
+    f: &mut Frame,
+    dashboard: &Dashboard,
+    area: Rect,
+    title: &str,
+    data: &[(f64, f64)],
+    y_bounds: [f64; 2],
+    color: Color,
+) {
+    let x_bounds = dashboard.x_bounds();
+    // Keep the axis usable before the first report arrives
+    let y_bounds = if y_bounds[1] > y_bounds[0] {
+        y_bounds
+    } else {
+        [0.0, 1.0]
+    };
+    let dataset = Dataset::default()
+        .marker(ratatui::symbols::Marker::Braille)
+        .graph_type(GraphType::Line)
+        .style(Style::default().fg(color))
+        .data(data);
+    let label = |value: f64| {
+        let text = if value >= 1000.0 || value == 0.0 {
+            format!("{:.0}", value)
+        } else {
+            format!("{:.3}", value)
+        };
+        Span::styled(text, Style::default().fg(Color::White))
+    };
+    let chart = Chart::new(vec![dataset])
+        .block(
+            Block::default()
+                .title(title.to_string())
+                .borders(Borders::ALL),
+        )
+        .x_axis(
+            Axis::default()
+                .bounds(x_bounds)
+                .labels(vec![label(x_bounds[0]), label(x_bounds[1])]),
+        )
+        .y_axis(
+            Axis::default()
+                .bounds(y_bounds)
+                .labels(vec![label(y_bounds[0]), label(y_bounds[1])]),
+        );
+    f.render_widget(chart, area);
+}
+
+fn draw_game_lengths(f: &mut Frame, dashboard: &Dashboard, area: Rect) {
+    let labels: Vec<String> = GAME_LENGTH_BUCKETS[..GAME_LENGTH_BUCKETS.len() - 1]
+        .iter()
+        .map(|bucket| bucket.to_string())
+        .collect();
+    let counts = dashboard
+        .records
+        .last()
+        .map(|record| record.game_length_histogram.clone())
+        .unwrap_or_default();
+    let data: Vec<(&str, u64)> = labels
+        .iter()
+        .zip(counts.iter().chain(std::iter::repeat(&0)))
+        .map(|(label, &count)| (label.as_str(), count as u64))
+        .collect();
+    let bar_width = (area.width.saturating_sub(2) / data.len() as u16)
+        .saturating_sub(1)
+        .max(1);
+    let chart = BarChart::default()
+        .block(
+            Block::default()
+                .title("Game lengths (turns), last interval")
+                .borders(Borders::ALL),
+        )
+        .data(&data)
+        .bar_width(bar_width)
+        .bar_style(Style::default().fg(Color::Yellow))
+        .value_style(Style::default().fg(Color::Black).bg(Color::Yellow));
+    f.render_widget(chart, area);
+}
+
+fn draw_controls(f: &mut Frame, dashboard: &Dashboard, area: Rect) {
+    let mut controls = Vec::new();
+    if !dashboard.finished {
+        controls.push(Span::styled("p", Style::default().fg(Color::Yellow)));
+        controls.push(Span::raw(if dashboard.paused {
+            " Resume | "
+        } else {
+            " Pause | "
+        }));
+        controls.push(Span::styled("s", Style::default().fg(Color::Yellow)));
+        controls.push(Span::raw(" Save Checkpoint | "));
+    }
+    controls.push(Span::styled("q", Style::default().fg(Color::Yellow)));
+    controls.push(Span::raw(if dashboard.finished {
+        " Save and Quit"
+    } else {
+        " Stop, Save and Quit"
+    }));
+    let paragraph = Paragraph::new(Line::from(controls))
+        .block(Block::default().borders(Borders::ALL).title("Controls"));
+    f.render_widget(paragraph, area);
+}
@@ -1,8 +1,17 @@
-use crate::mancala::{GameState, Outcome};
-use crate::packed_actions::Action;
-use crate::player::{AIPlayer, Player};
+use crate::analysis::Analysis;
+use crate::animation::{self, Animation, Mark};
+use crate::canonical;
+use crate::engine::{format_move, format_score, Evaluation};
+use crate::mancala::{GameState, Outcome, ValueTable};
+use crate::net::{self, Peer};
+use crate::packed_actions::{Action, ActionQueue, SubAction};
+use crate::player::Player;
+use crate::setup::{draw_setup, GameSetup, RulesLoader, SetupForm};
+use rand::rngs::StdRng;
 use crossterm::{
-    event::{self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode},
+    event::{
+        self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode, MouseButton, MouseEventKind,
+    },
     execute,
     terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
 };
@@ -11,39 +20,73 @@ use ratatui::{
     style::{Color, Modifier, Style},
     text::{Line, Span},
     widgets::{
-        Axis, Block, Borders, Chart, Dataset, GraphType, Paragraph, Row, Table, TableState,
+        Axis, Block, Borders, Chart, Dataset, GraphType, List, ListItem, ListState, Paragraph, Row,
+        Table, TableState,
     },
+    backend::CrosstermBackend,
     Frame, Terminal,
 };
 use std::collections::HashMap;
 use std::error::Error;
-use std::io;
+use std::io::{self, Stdout};
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::sync::Arc;
 use std::time::Duration;
 
-/// Struct to track game history for visualization
+/// Struct to track game history for visualization. Moves taken back stay on the end, past the
+/// current position, until a different move is played.
 pub struct GameHistory {
     states: Vec<GameState>,
     values: Vec<f64>,
     actions: Vec<Action>,
+    /// Index of the position the game is at.
+    current: usize,
 }
 
 impl GameHistory {
-    fn new(initial_state: GameState, initial_value: f64) -> Self {
+    pub fn new(initial_state: GameState, initial_value: f64) -> Self {
         GameHistory {
             states: vec![initial_state],
             values: vec![initial_value],
             actions: vec![],
+            current: 0,
         }
     }
 
-    fn add_move(&mut self, state: GameState, value: f64, action: Action) {
+    /// Play on from the current position, forgetting any moves taken back from it.
+    pub fn add_move(&mut self, state: GameState, value: f64, action: Action) {
+        self.states.truncate(self.current + 1);
+        self.values.truncate(self.current + 1);
+        self.actions.truncate(self.current);
         self.states.push(state);
         self.values.push(value);
         self.actions.push(action);
+        self.current += 1;
+    }
+
+    /// Every position in the line, starting with the first.
+    pub fn states(&self) -> &[GameState] {
+        &self.states
+    }
+
+    /// The moves leading from each position to the next.
+    pub fn actions(&self) -> &[Action] {
+        &self.actions
+    }
+
+    pub fn current(&self) -> usize {
+        self.current
     }
 
-    fn get_data_points(&self) -> Vec<(f64, f64)> {
-        self.values
+    /// Go back to, or forward again to, position `index` in the line.
+    pub fn set_current(&mut self, index: usize) {
+        self.current = index.min(self.states.len() - 1);
+    }
+
+    /// The chart up to the current position.
+    pub fn get_data_points(&self) -> Vec<(f64, f64)> {
+        self.values[..=self.current]
             .iter()
             .enumerate()
             .map(|(i, &v)| (i as f64, v))
@@ -51,61 +94,381 @@ impl GameHistory {
     }
 }
 
+/// Who the person at the keyboard is playing.
+pub enum Opponent {
+    /// An agent from the setup screen, moving on its own.
+    Ai(Box<dyn Player>),
+    /// Someone on the other end of a network game, who sees the board from their own side.
+    Remote(Peer),
+    /// A second person at the same keyboard, taking turns with the first.
+    HotSeat,
+}
+
+/// What the two players are called in a hot-seat game.
+const PLAYER_NAMES: [&str; 2] = ["Player 1", "Player 2"];
+
+impl Opponent {
+    fn name(&self) -> &'static str {
+        match self {
+            Opponent::Ai(_) => "AI",
+            Opponent::Remote(_) => "Opponent",
+            Opponent::HotSeat => PLAYER_NAMES[1],
+        }
+    }
+}
+
+/// A move being made a house at a time, with the keys or the mouse, until the turn is over.
+#[derive(Clone, Copy)]
+struct PartialMove {
+    /// The houses sown so far, in order.
+    sown: Action,
+    /// The board after them.
+    board: GameState,
+}
+
+/// Whether `action` starts by sowing the houses of `sown`, in order.
+fn starts_with(mut action: Action, mut sown: Action) -> bool {
+    while !sown.is_empty() {
+        if action.is_empty() || action.pop_front() != sown.pop_front() {
+            return false;
+        }
+    }
+    true
+}
+
 /// App state
-pub struct App<'a> {
+pub struct App {
     game_state: GameState,
-    value_fn: &'a HashMap<GameState, f64>,
+    value_fn: Arc<dyn ValueTable>,
+    /// How games are started, and the screen for changing it when open.
+    setup: GameSetup,
+    setup_form: Option<SetupForm>,
     move_table_state: TableState,
     possible_moves: Vec<(Action, GameState, f64)>,
     history: GameHistory,
     should_quit: bool,
-    ai_player: AIPlayer,
+    opponent: Opponent,
     is_human_turn: bool,
+    /// Which player the board is shown for: always 0 except in a hot-seat game, where the board
+    /// turns round to face whoever is to move.
+    seat: usize,
+    /// Whether each player sees the move analysis, in seat order.
+    show_analysis: [bool; 2],
+    /// Who made the first move: 0 for the human (or player 1), 1 for their opponent.
+    first_mover: usize,
+    /// A past position being looked at instead of the game, as an index into the history.
+    viewing: Option<usize>,
+    /// The last move, being shown sown a step at a time.
+    animation: Option<Animation>,
+    /// The human's move so far, when sowing it a house at a time.
+    partial_move: Option<PartialMove>,
+    /// Whether the moves are also searched several turns ahead, and that search when running.
+    deep_analysis: bool,
+    search: Option<Analysis>,
     status_message: String,
+    rng: StdRng,
 }
 
-impl<'a> App<'a> {
-    pub fn new(initial_state: GameState, value_fn: &'a HashMap<GameState, f64>) -> Self {
-        let initial_value = *value_fn.get(&initial_state).unwrap_or(&0.5);
-        
-        // Create AI player with opponent's perspective
-        let mut ai_starting_state = initial_state.clone();
-        ai_starting_state.swap_board();
-        let ai_player = AIPlayer::new(ai_starting_state);
-        
+impl App {
+    /// An app playing a game as `setup` describes, analysing moves with `value_fn`.
+    pub fn new(setup: GameSetup, value_fn: Box<dyn ValueTable>, rng: StdRng) -> Self {
+        let initial_state = setup.rules.starting_state();
         let mut app = App {
-            game_state: initial_state.clone(),
-            value_fn,
+            game_state: initial_state,
+            value_fn: Arc::from(value_fn),
+            setup,
+            setup_form: None,
             move_table_state: TableState::default(),
             possible_moves: Vec::new(),
-            history: GameHistory::new(initial_state, initial_value),
+            history: GameHistory::new(initial_state, 0.5),
             should_quit: false,
-            ai_player,
+            opponent: Opponent::HotSeat,
             is_human_turn: true,
-            status_message: String::from("Your turn. Select a move."),
+            seat: 0,
+            show_analysis: [true; 2],
+            first_mover: 0,
+            viewing: None,
+            animation: None,
+            partial_move: None,
+            deep_analysis: false,
+            search: None,
+            status_message: String::new(),
+            rng,
         };
+        app.reset_game();
+        app
+    }
+
+    /// An app that opens on the setup screen, starting with `setup` filled in.
+    pub fn with_setup_screen(setup: GameSetup, rng: StdRng) -> Self {
+        let mut app = App::new(setup.clone(), Box::new(HashMap::new()), rng);
+        app.setup_form = Some(SetupForm::new(setup, false));
+        app
+    }
+
+    /// An app for a game over the network, showing the board from this side.
+    pub fn networked(peer: Peer, value_fn: Box<dyn ValueTable>, rng: StdRng) -> Self {
+        let mut app = App::new(GameSetup::new(PathBuf::new()), value_fn, rng);
+        app.game_state = peer.board();
+        let value = canonical::value_to_move(app.value_fn.as_ref(), &app.game_state);
+        app.history = GameHistory::new(app.game_state, value);
         app.update_possible_moves();
-        app.move_table_state.select(Some(0));
+        app.is_human_turn = peer.my_turn();
+        app.status_message = if app.is_human_turn {
+            String::from("Connected. Your turn. Select a move.")
+        } else {
+            String::from("Connected. Waiting for the opponent...")
+        };
+        app.opponent = Opponent::Remote(peer);
         app
     }
+
+    /// Who the board is shown for, and who sits across from them.
+    fn side_names(&self) -> (&'static str, &'static str) {
+        match self.opponent {
+            Opponent::HotSeat => (PLAYER_NAMES[self.seat], PLAYER_NAMES[1 - self.seat]),
+            _ => ("You", self.opponent.name()),
+        }
+    }
+
+    /// Whether the move analysis is on for the player choosing a move.
+    fn analysis_shown(&self) -> bool {
+        self.show_analysis[self.seat]
+    }
+
+    /// Turn the move analysis on or off for the player to move, as a coaching aid.
+    pub fn toggle_analysis(&mut self) {
+        self.show_analysis[self.seat] = !self.show_analysis[self.seat];
+        self.update_possible_moves();
+        self.move_table_state.select(Some(0));
+    }
+
+    /// Add a move to the history, which is kept from the first player's side of the board.
+    fn record_move(&mut self, mut state: GameState, mut value: f64, action: Action) {
+        if self.seat == 1 {
+            state.swap_board();
+            value = 1.0 - value;
+        }
+        self.history.add_move(state, value, action);
+    }
+
+    /// Who is to move at position `index` of the history: 0 for the human (or player 1), 1 for
+    /// their opponent.
+    fn mover_at(&self, index: usize) -> usize {
+        (self.first_mover + index) % 2
+    }
+
+    /// Position `index` of the history, seen by the player to move there.
+    fn position_at(&self, index: usize) -> GameState {
+        let mut position = self.history.states()[index];
+        if self.mover_at(index) == 1 {
+            position.swap_board();
+        }
+        position
+    }
+
+    /// The position the move analysis is for: the game, or the past position being looked at.
+    fn shown_position(&self) -> GameState {
+        match self.viewing {
+            Some(index) => self.position_at(index),
+            None => self.game_state,
+        }
+    }
+
+    /// The board as drawn, and who sits at the bottom and top of it.
+    fn shown_board(&self) -> (GameState, (&'static str, &'static str)) {
+        let Some(index) = self.viewing else {
+            let board = self.partial_move.map_or(self.game_state, |partial| partial.board);
+            return (board, self.side_names());
+        };
+        match self.opponent {
+            // Turned to face whoever was to move, as it was during the game
+            Opponent::HotSeat => {
+                let mover = self.mover_at(index);
+                (self.position_at(index), (PLAYER_NAMES[mover], PLAYER_NAMES[1 - mover]))
+            }
+            _ => (self.history.states()[index], self.side_names()),
+        }
+    }
+
+    /// Whether moves can be taken back and past positions played on from, which a network game
+    /// cannot do.
+    fn can_rewind(&self) -> bool {
+        self.can_restart()
+    }
+
+    /// Put the game back to position `index` of the history, keeping the moves after it for redo.
+    fn go_to(&mut self, index: usize) {
+        self.history.set_current(index);
+        self.viewing = None;
+        self.animation = None;
+        self.partial_move = None;
+        let board = self.history.states()[index];
+        let mover = self.mover_at(index);
+        match self.opponent {
+            Opponent::HotSeat => {
+                self.seat = mover;
+                self.game_state = self.position_at(index);
+                self.is_human_turn = true;
+            }
+            _ => {
+                self.game_state = board;
+                self.is_human_turn = mover == 0;
+                // A fresh opponent, sat at the position
+                if let Some(spec) = self.setup.agent() {
+                    let mut opponent_state = board;
+                    opponent_state.swap_board();
+                    self.opponent = Opponent::Ai(spec.player(opponent_state));
+                }
+            }
+        }
+        self.update_possible_moves();
+        self.move_table_state.select(Some(0));
+        if self.is_game_over() {
+            self.handle_game_end();
+        } else {
+            self.status_message = match (&self.opponent, self.is_human_turn) {
+                (Opponent::HotSeat, _) => {
+                    format!("Back at move {}. {}'s turn.", index, self.side_names().0)
+                }
+                (_, true) => format!("Back at move {}. Your turn.", index),
+                (_, false) => format!("Back at move {}. {}'s turn...", index, self.opponent.name()),
+            };
+        }
+    }
+
+    /// Take back the last move, and the opponent's reply to it when playing the AI.
+    pub fn undo(&mut self) {
+        let current = self.history.current();
+        if !self.can_rewind() || current == 0 {
+            return;
+        }
+        let mut target = current - 1;
+        if matches!(self.opponent, Opponent::Ai(_)) {
+            // Back to the human's turn, or the AI would just play again
+            while target > 0 && self.mover_at(target) != 0 {
+                target -= 1;
+            }
+            if self.mover_at(target) != 0 {
+                return;
+            }
+        }
+        self.go_to(target);
+    }
+
+    /// Play again a move that was taken back (both moves when playing the AI).
+    pub fn redo(&mut self) {
+        let current = self.history.current();
+        let last = self.history.states().len() - 1;
+        if !self.can_rewind() || current == last {
+            return;
+        }
+        let mut target = current + 1;
+        if matches!(self.opponent, Opponent::Ai(_)) {
+            while target < last && self.mover_at(target) != 0 {
+                target += 1;
+            }
+        }
+        self.go_to(target);
+    }
+
+    /// Look at the position `step` moves before (negative) or after the one shown, without
+    /// changing the game.
+    pub fn browse(&mut self, step: i32) {
+        let current = self.history.current();
+        let shown = self.viewing.unwrap_or(current) as i32;
+        let last = self.history.states().len() as i32 - 1;
+        let index = (shown + step).clamp(0, last) as usize;
+        self.viewing = (index != current).then_some(index);
+        self.update_possible_moves();
+        self.move_table_state.select(Some(0));
+    }
+
+    /// Go back to looking at the game.
+    pub fn stop_browsing(&mut self) {
+        if self.viewing.take().is_some() {
+            self.update_possible_moves();
+            self.move_table_state.select(Some(0));
+        }
+    }
+
+    /// Carry on the game from the past position being looked at.
+    pub fn branch(&mut self) {
+        if let Some(index) = self.viewing
+            && self.can_rewind()
+        {
+            self.go_to(index);
+        }
+    }
+
+    /// Open the setup screen for the next game.
+    pub fn open_setup(&mut self) {
+        if self.can_restart() {
+            self.setup_form = Some(SetupForm::new(self.setup.clone(), true));
+        }
+    }
+
+    /// Start a game as the setup screen has it, loading its value file with `loader`. The
+    /// screen stays open with the reason if the file will not do.
+    pub fn start_from_setup(&mut self, loader: &RulesLoader) {
+        let Some(form) = &mut self.setup_form else {
+            return;
+        };
+        match form.setup.load(loader) {
+            Ok((value_fn, warning)) => {
+                self.setup = form.setup.clone();
+                self.setup_form = None;
+                self.value_fn = Arc::from(value_fn);
+                self.reset_game();
+                if let Some(warning) = warning {
+                    self.status_message = format!("{}. {}", warning, self.status_message);
+                }
+            }
+            Err(err) => form.error = Some(err),
+        }
+    }
     
     pub fn reset_game(&mut self) {
+        // A network game is the host's to start, and only one is played per connection
+        if !self.can_restart() {
+            return;
+        }
+
         // Create a new game state
-        let initial_state = GameState::new(4);
-        let initial_value = *self.value_fn.get(&initial_state).unwrap_or(&0.5);
+        let initial_state = self.setup.rules.starting_state();
+        let first_value = canonical::value_to_move(self.value_fn.as_ref(), &initial_state);
         
-        // Create AI player with opponent's perspective
-        let mut ai_starting_state = initial_state.clone();
-        ai_starting_state.swap_board();
+        // Create the opponent with its own perspective
+        let mut opponent_starting_state = initial_state;
+        opponent_starting_state.swap_board();
+        self.opponent = match self.setup.agent() {
+            Some(spec) => Opponent::Ai(spec.player(opponent_starting_state)),
+            None => Opponent::HotSeat,
+        };
         
-        // Reset app state
-        self.game_state = initial_state.clone();
+        // Reset app state, with the board facing whoever moves first in a hot-seat game
+        let hot_seat = matches!(self.opponent, Opponent::HotSeat);
+        self.game_state = initial_state;
+        self.first_mover = if self.setup.human_first { 0 } else { 1 };
+        self.seat = if hot_seat { self.first_mover } else { 0 };
+        self.viewing = None;
+        self.animation = None;
+        self.partial_move = None;
+        self.is_human_turn = self.setup.human_first || hot_seat;
         self.move_table_state = TableState::default();
         self.possible_moves = Vec::new();
+        // The chart follows the human, or player 1
+        let initial_value = if self.setup.human_first {
+            first_value
+        } else {
+            1.0 - first_value
+        };
         self.history = GameHistory::new(initial_state, initial_value);
-        self.ai_player = AIPlayer::new(ai_starting_state);
-        self.is_human_turn = true;
-        self.status_message = String::from("New game started. Your turn!");
+        self.status_message = match (hot_seat, self.is_human_turn) {
+            (true, _) => format!("New game started. {}'s turn!", self.side_names().0),
+            (false, true) => String::from("New game started. Your turn!"),
+            (false, false) => String::from("New game started. AI's turn..."),
+        };
         
         // Update moves
         self.update_possible_moves();
@@ -114,101 +477,331 @@ impl<'a> App<'a> {
     
     pub fn ai_turn(&mut self) {
         if !self.is_human_turn && !self.is_game_over() {
+            let Opponent::Ai(ai_player) = &mut self.opponent else {
+                return;
+            };
             self.status_message = String::from("AI is thinking...");
             
             // Let AI make a move
-            let action = self.ai_player.take_action(self.value_fn, 0.0);
+            let action = ai_player.take_action(self.value_fn.as_ref(), self.setup.epsilon, &mut self.rng);
             
             // Update our game state with the AI's move
+            let mut before = self.game_state;
+            before.swap_board();
+            self.animate(before, action, true);
             self.game_state.swap_board();
             self.game_state.evaluate_action(action);
             self.game_state.swap_board();
-            
-            // Update history
-            let value = *self.value_fn.get(&self.game_state).unwrap_or(&0.5);
-            self.history.add_move(self.game_state.clone(), value, action);
-            
-            // Check if game is over after AI move
-            if self.is_game_over() {
-                self.handle_game_end();
+            self.opponent_moved(action);
+        }
+    }
+
+    /// Record the opponent's `action`, already played on the board, and hand the turn back.
+    fn opponent_moved(&mut self, action: Action) {
+        // Update history
+        let value = canonical::value_to_move(self.value_fn.as_ref(), &self.game_state);
+        self.history.add_move(self.game_state, value, action);
+        
+        // Check if game is over after the opponent's move
+        if self.is_game_over() {
+            self.handle_game_end();
+            return;
+        }
+        
+        // Update possible moves for human
+        self.update_possible_moves();
+        self.move_table_state.select(Some(0));
+        
+        self.is_human_turn = true;
+        self.status_message = format!(
+            "{} played {}. Your turn now.",
+            self.opponent.name(),
+            action
+        );
+    }
+
+    /// Take in whatever the other side of a network game has done since last time.
+    pub fn poll_remote(&mut self) {
+        let Opponent::Remote(peer) = &mut self.opponent else {
+            return;
+        };
+        let event = match peer.poll(Duration::ZERO) {
+            Ok(Some(event)) => event,
+            Ok(None) => return,
+            Err(err) => {
+                self.status_message = format!("Connection lost: {} (Press 'q' to quit)", err);
                 return;
             }
-            
-            // Update possible moves for human
-            self.update_possible_moves();
-            self.move_table_state.select(Some(0));
-            
-            self.is_human_turn = true;
-            self.status_message = format!("AI played {}. Your turn now.", action);
+        };
+        let (board, my_turn) = (peer.board(), peer.my_turn());
+        match event {
+            net::Event::Moved(action) => {
+                let mut before = self.game_state;
+                before.swap_board();
+                self.animate(before, action, true);
+                self.game_state = board;
+                self.opponent_moved(action);
+            }
+            net::Event::Rejected(reason) => {
+                // Go with the host's board
+                self.game_state = board;
+                self.is_human_turn = my_turn;
+                self.update_possible_moves();
+                self.move_table_state.select(Some(0));
+                self.status_message = format!("Move not played: {}", reason);
+            }
+            net::Event::Disconnected => {
+                self.status_message =
+                    String::from("Connection lost. Waiting for the opponent to come back...");
+            }
+            net::Event::Reconnected => {
+                // Anything played meanwhile arrives with the new connection
+                if board != self.game_state {
+                    self.game_state = board;
+                    let value = canonical::value_to_move(self.value_fn.as_ref(), &self.game_state);
+                    self.history = GameHistory::new(self.game_state, value);
+                    self.update_possible_moves();
+                    self.move_table_state.select(Some(0));
+                }
+                self.is_human_turn = my_turn;
+                if self.is_game_over() {
+                    self.handle_game_end();
+                } else {
+                    self.status_message = String::from(if my_turn {
+                        "Reconnected. Your turn."
+                    } else {
+                        "Reconnected. Waiting for the opponent..."
+                    });
+                }
+            }
+        }
+    }
+
+    /// Show `action` being sown from `before`, which is seen from the mover's side: the player at
+    /// the bottom of the board, or with `opponent` the one across from them.
+    fn animate(&mut self, before: GameState, action: Action, opponent: bool) {
+        let Some(step) = self.setup.animation.step() else {
+            return;
+        };
+        let names = self.side_names();
+        let mover = if opponent { names.1 } else { names.0 };
+        let frames = animation::frames(before, action, mover, opponent);
+        self.animation = Some(Animation::new(frames, names, step));
+    }
+
+    /// Move the animation on, dropping it once it has finished.
+    pub fn step_animation(&mut self) {
+        if let Some(animation) = &mut self.animation
+            && !animation.tick()
+        {
+            self.animation = None;
+        }
+    }
+
+    /// Go straight to the board after the move being shown.
+    pub fn skip_animation(&mut self) {
+        self.animation = None;
+    }
+
+    /// Sow `house` as the next part of the human's move, which is played once the turn is over.
+    pub fn sow_house(&mut self, house: usize) {
+        if !self.is_human_turn || self.viewing.is_some() || self.is_game_over() {
+            return;
+        }
+        let (sown, board) = match self.partial_move {
+            Some(partial) => (partial.sown, partial.board),
+            None => (Action::new(), self.game_state),
+        };
+        let mut next = sown;
+        next.push_front(house as SubAction);
+        let Some(index) = self
+            .possible_moves
+            .iter()
+            .position(|(action, _, _)| starts_with(*action, next))
+        else {
+            self.status_message = format!("Cell {} cannot be sown now.", house + 1);
+            return;
+        };
+        self.move_table_state.select(Some(index));
+        if self.possible_moves[index].0 == next {
+            // That ends the turn
+            self.make_selected_move();
+            return;
+        }
+        let mut after = board;
+        after.sow(house as SubAction);
+        self.animate(board, Action::singleton(house as SubAction), false);
+        self.partial_move = Some(PartialMove { sown: next, board: after });
+        self.status_message = format!(
+            "Sown {}. The last seed landed in the store: sow again.",
+            format_move(next)
+        );
+    }
+
+    /// Take back the last house sown of a move being made a house at a time.
+    pub fn take_back_sow(&mut self) {
+        let Some(partial) = self.partial_move.take() else {
+            return;
+        };
+        self.animation = None;
+        let mut sown = partial.sown;
+        sown.pop_back();
+        if sown.is_empty() {
+            self.status_message = String::from("Taken back. Select a move.");
+            return;
+        }
+        let mut board = self.game_state;
+        let mut replay = sown;
+        while !replay.is_empty() {
+            board.sow(replay.pop_front());
+        }
+        self.partial_move = Some(PartialMove { sown, board });
+        self.status_message = format!("Sown {}. Sow again.", format_move(sown));
+    }
+
+    /// Put back everything sown of a move being made a house at a time.
+    pub fn cancel_sowing(&mut self) {
+        if self.partial_move.take().is_some() {
+            self.animation = None;
+            self.status_message = String::from("Taken back. Select a move.");
         }
     }
 
     pub fn update_possible_moves(&mut self) {
-        self.possible_moves = self
-            .game_state
+        let position = self.shown_position();
+        self.possible_moves = position
             .gen_actions()
             .map(|action| {
-                let mut state = self.game_state;
+                let mut state = position;
                 state.evaluate_action(action);
-                let value = *self.value_fn.get(&state).unwrap_or(&0.5);
+                let value = canonical::value(self.value_fn.as_ref(), &state);
                 (action, state, value)
             })
             .collect();
 
-        // Sort by value, best moves first
-        self.possible_moves.sort_by(|a, b| b.2.partial_cmp(&a.2).unwrap());
+        // Sort by value, best moves first, unless that would give the game away
+        if self.analysis_shown() {
+            self.possible_moves.sort_by(|a, b| b.2.total_cmp(&a.2));
+        }
+
+        // Search the new position in the background, dropping the search of the last one
+        self.search = None;
+        if self.deep_analysis && self.analysis_shown() && !position.is_ended() {
+            // Without a table to go by, count seeds
+            let evaluation = if self.value_fn.entries() == 0 {
+                Evaluation::Margin
+            } else {
+                Evaluation::Table
+            };
+            self.search = Some(Analysis::start(position, self.value_fn.clone(), evaluation));
+        }
     }
 
-    pub fn next(&mut self) {
-        let i = match self.move_table_state.selected() {
-            Some(i) => {
-                if i >= self.possible_moves.len() - 1 {
-                    0
-                } else {
-                    i + 1
-                }
+    /// Turn searching the moves several turns ahead on or off.
+    pub fn toggle_deep_analysis(&mut self) {
+        self.deep_analysis = !self.deep_analysis;
+        self.update_possible_moves();
+    }
+
+    /// Take in what the background search has found since last time.
+    pub fn poll_search(&mut self) {
+        if let Some(search) = &mut self.search {
+            search.update();
+        }
+    }
+
+    /// Whether the move at `index` can be picked: once a move is being made a house at a time,
+    /// only those going on from the houses sown so far.
+    fn is_selectable(&self, index: usize) -> bool {
+        self.partial_move
+            .is_none_or(|partial| starts_with(self.possible_moves[index].0, partial.sown))
+    }
+
+    /// Move the selection `step` places through the moves that can be picked, wrapping round.
+    fn step_selection(&mut self, step: isize) {
+        let len = self.possible_moves.len() as isize;
+        if len == 0 {
+            return;
+        }
+        let mut i = self.move_table_state.selected().map_or(-step.signum(), |i| i as isize);
+        for _ in 0..len {
+            i = (i + step).rem_euclid(len);
+            if self.is_selectable(i as usize) {
+                self.move_table_state.select(Some(i as usize));
+                return;
             }
-            None => 0,
-        };
-        self.move_table_state.select(Some(i));
+        }
+    }
+
+    pub fn next(&mut self) {
+        self.step_selection(1);
     }
 
     pub fn previous(&mut self) {
-        let i = match self.move_table_state.selected() {
-            Some(i) => {
-                if i == 0 {
-                    self.possible_moves.len() - 1
-                } else {
-                    i - 1
-                }
-            }
-            None => 0,
-        };
-        self.move_table_state.select(Some(i));
+        self.step_selection(-1);
     }
 
     pub fn make_selected_move(&mut self) {
-        if self.is_human_turn && !self.is_game_over() {
-            if let Some(selected) = self.move_table_state.selected() {
-                if selected < self.possible_moves.len() {
-                    let (action, new_state, value) = self.possible_moves[selected].clone();
-                    self.game_state = new_state;
-                    self.history.add_move(self.game_state.clone(), value, action);
-                    
-                    // Update AI's state with human's move
-                    self.ai_player.opponent_plays(action);
-                    
-                    // Check if game is over after human move
-                    if self.is_game_over() {
-                        self.handle_game_end();
+        if self.is_human_turn
+            && self.viewing.is_none()
+            && !self.is_game_over()
+            && let Some(selected) = self.move_table_state.selected()
+            && selected < self.possible_moves.len()
+        {
+            let (action, new_state, value) = self.possible_moves[selected];
+            if !self.is_selectable(selected) {
+                self.status_message = format!(
+                    "{} does not go on from the cells sown. Backspace takes them back.",
+                    action
+                );
+                return;
+            }
+            
+            // Tell the opponent about the human's move
+            match &mut self.opponent {
+                Opponent::Ai(ai_player) => ai_player.opponent_plays(action),
+                Opponent::Remote(peer) => {
+                    if let Err(err) = peer.play(action) {
+                        self.status_message = format!("Could not play {}: {}", action, err);
                         return;
                     }
-                    
-                    // Switch to AI's turn
-                    self.is_human_turn = false;
-                    self.status_message = String::from("AI's turn...");
                 }
+                Opponent::HotSeat => {}
+            }
+            // Only what has not been sown already, when the move was made a house at a time
+            match self.partial_move.take() {
+                Some(partial) => {
+                    let mut rest = action;
+                    for _ in 0..partial.sown.length() {
+                        rest.pop_front();
+                    }
+                    self.animate(partial.board, rest, false);
+                }
+                _ => self.animate(self.game_state, action, false),
+            }
+            self.game_state = new_state;
+            self.record_move(new_state, value, action);
+            
+            // Check if game is over after human move
+            if self.is_game_over() {
+                self.handle_game_end();
+                return;
+            }
+            
+            // Turn the board round for the other player
+            if let Opponent::HotSeat = self.opponent {
+                let (mover, next) = self.side_names();
+                self.status_message = format!("{} played {}. {}'s turn.", mover, action, next);
+                self.game_state.swap_board();
+                self.seat = 1 - self.seat;
+                self.update_possible_moves();
+                self.move_table_state.select(Some(0));
+                return;
             }
+
+            // Switch to the opponent's turn
+            self.is_human_turn = false;
+            self.status_message = format!("{}'s turn...", self.opponent.name());
         }
     }
     
@@ -219,27 +812,35 @@ impl<'a> App<'a> {
         // This needs to happen before we calculate scores
         self.game_state.finalize_game();
         
-        // Calculate final scores for display
-        let player_score = self.game_state.houses[6];
-        let ai_score = self.game_state.houses[13];
-        
+        let again = if self.can_restart() {
+            "(Press 'r' to play again)"
+        } else {
+            "(Press 'q' to quit)"
+        };
+        // The board faces whoever made the last move
+        let (near, far) = self.side_names();
         match self.get_game_outcome() {
-            Some(P1win) => self.status_message = format!(
-                "GAME OVER! You WON! Score: {}-{} (Press 'r' to play again)",
-                player_score, ai_score
+            Some(P1win(score)) => self.status_message = format!(
+                "GAME OVER! {} WON! Score: {}-{} {}",
+                near, score.p1, score.p2, again
             ),
-            Some(P2win) => self.status_message = format!(
-                "GAME OVER! AI WON! Score: {}-{} (Press 'r' to play again)",
-                player_score, ai_score
+            Some(P2win(score)) => self.status_message = format!(
+                "GAME OVER! {} WON! Score: {}-{} {}",
+                far, score.p1, score.p2, again
             ),
-            Some(Tie) => self.status_message = format!(
-                "GAME OVER! It's a tie! Score: {}-{} (Press 'r' to play again)",
-                player_score, ai_score
+            Some(Tie(score)) => self.status_message = format!(
+                "GAME OVER! It's a tie! Score: {}-{} {}",
+                score.p1, score.p2, again
             ),
-            _ => self.status_message = String::from("Game somehow ended without a result... (Press 'r' to play again)"),
+            _ => self.status_message = format!("Game somehow ended without a result... {}", again),
         }
     }
     
+    /// Whether 'r' starts a new game, which a network game cannot do.
+    pub fn can_restart(&self) -> bool {
+        !matches!(self.opponent, Opponent::Remote(_))
+    }
+
     pub fn quit(&mut self) {
         self.should_quit = true;
     }
@@ -253,10 +854,10 @@ impl<'a> App<'a> {
     }
 }
 
-/// UI rendering
-pub fn draw(f: &mut Frame, app: &App) {
-    // Create the layout
-    let chunks = Layout::default()
+/// Where each part of the game screen goes, from the top: the board, status message, move
+/// analysis, win probability chart and controls.
+fn screen_layout(area: Rect) -> Rc<[Rect]> {
+    Layout::default()
         .direction(Direction::Vertical)
         .constraints([
             Constraint::Length(8),  // Game board
@@ -265,7 +866,17 @@ pub fn draw(f: &mut Frame, app: &App) {
             Constraint::Length(9),  // Win probability chart
             Constraint::Length(3),  // Controls
         ])
-        .split(f.size());
+        .split(area)
+}
+
+/// UI rendering
+pub fn draw(f: &mut Frame, app: &App) {
+    if let Some(form) = &app.setup_form {
+        draw_setup(f, form, f.size());
+        return;
+    }
+
+    let chunks = screen_layout(f.size());
 
     // Draw the game board
     draw_game_board(f, app, chunks[0]);
@@ -273,8 +884,13 @@ pub fn draw(f: &mut Frame, app: &App) {
     // Draw status message
     draw_status_message(f, app, chunks[1]);
     
-    // Draw the move analysis table
-    draw_move_analysis(f, app, chunks[2]);
+    // Draw the move analysis table, with the moves played beside it
+    let middle = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Min(40), Constraint::Length(34)])
+        .split(chunks[2]);
+    draw_move_analysis(f, app, middle[0]);
+    draw_move_list(f, app, middle[1]);
     
     // Draw the win probability chart
     draw_win_probability(f, app, chunks[3]);
@@ -284,10 +900,83 @@ pub fn draw(f: &mut Frame, app: &App) {
 }
 
 fn draw_game_board(f: &mut Frame, app: &App, area: Rect) {
+    if let Some(animation) = &app.animation {
+        let frame = animation.frame();
+        let title = "Game Board: sowing (s to skip)";
+        draw_marked_board(f, &frame.board, animation.names, title, &frame.marks, area);
+        return;
+    }
+    let (board, names) = app.shown_board();
+    let title = match (app.viewing, &app.opponent) {
+        (Some(index), _) => format!(
+            "Game Board: after move {} of {} (Enter to play on from here, Esc to return)",
+            index,
+            app.history.current()
+        ),
+        // Whose turn it is, as the board turns round between them
+        (None, _) if app.partial_move.is_some() => String::from(
+            "Game Board: sow again (1-6 or click, Backspace to take back)",
+        ),
+        (None, Opponent::HotSeat) if !app.is_game_over() => {
+            format!("Game Board: {} to move", names.0)
+        }
+        _ => String::from("Game Board"),
+    };
+    draw_board(f, &board, names, &title, area);
+}
+
+/// The moves of the game so far, and any taken back, with the position on show picked out.
+fn draw_move_list(f: &mut Frame, app: &App, area: Rect) {
+    let current = app.history.current();
+    let shown = app.viewing.unwrap_or(current);
+    let items: Vec<ListItem> = app
+        .history
+        .actions()
+        .iter()
+        .enumerate()
+        .map(|(ply, action)| {
+            let mover = match (&app.opponent, app.mover_at(ply)) {
+                (Opponent::HotSeat, seat) => PLAYER_NAMES[seat],
+                (_, 0) => "You",
+                (opponent, _) => opponent.name(),
+            };
+            // Moves taken back are dimmed
+            let style = if ply < current {
+                Style::default()
+            } else {
+                Style::default().fg(Color::DarkGray)
+            };
+            ListItem::new(Span::styled(format!("{:>3}. {:<9}{}", ply + 1, mover, action), style))
+        })
+        .collect();
+    let list = List::new(items)
+        .block(Block::default().borders(Borders::ALL).title("Moves"))
+        .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+    let mut state = ListState::default();
+    // The move that led to the position on show
+    state.select(shown.checked_sub(1));
+    f.render_stateful_widget(list, area, &mut state);
+}
+
+/// Draw `state` with the side whose houses come first along the bottom. `names` label the stores,
+/// bottom player first.
+pub fn draw_board(f: &mut Frame, state: &GameState, names: (&str, &str), title: &str, area: Rect) {
+    draw_marked_board(f, state, names, title, &[], area);
+}
+
+/// Draw the board as `draw_board` does, picking out the houses in `marks`.
+fn draw_marked_board(
+    f: &mut Frame,
+    state: &GameState,
+    names: (&str, &str),
+    title: &str,
+    marks: &[(usize, Mark)],
+    area: Rect,
+) {
     // Create a container block for the entire board
     let board_block = Block::default()
         .borders(Borders::ALL)
-        .title("Game Board");
+        .title(title);
     
     // Get the inner area to work with (inside borders)
     let inner_area = board_block.inner(area);
@@ -301,65 +990,38 @@ fn draw_game_board(f: &mut Frame, app: &App, area: Rect) {
     let p1_cells_style = Style::default().fg(Color::Green);
     let p2_cells_style = Style::default().fg(Color::Yellow);
     let mancala_style = Style::default().fg(Color::Cyan).add_modifier(Modifier::BOLD);
+    let mark_style = |house: usize| {
+        marks.iter().find(|(marked, _)| *marked == house).map(|(_, mark)| {
+            let color = match mark {
+                Mark::Lifted => Color::Magenta,
+                Mark::Dropped => Color::White,
+                Mark::Captured => Color::Red,
+            };
+            Style::default().fg(color).add_modifier(Modifier::BOLD)
+        })
+    };
     
-    // First, create main horizontal layout with left mancala, center board, right mancala
-    let main_horizontal = Layout::default()
-        .direction(Direction::Horizontal)
-        .constraints([
-            Constraint::Ratio(1, 8),  // P2 Mancala (left)
-            Constraint::Ratio(6, 8),  // Center board
-            Constraint::Ratio(1, 8),  // P1 Mancala (right)
-        ])
-        .split(inner_area);
-    
-    // Define vertical layout for the center board (2 rows)
-    let center_vertical = Layout::default()
-        .direction(Direction::Vertical)
-        .constraints([
-            Constraint::Ratio(1, 2),  // Top row (P2)
-            Constraint::Ratio(1, 2),  // Bottom row (P1)
-        ])
-        .split(main_horizontal[1]);
-    
-    // Define columns for top row (P2) - cells 12 to 7
-    let p2_columns = Layout::default()
-        .direction(Direction::Horizontal)
-        .constraints([
-            Constraint::Ratio(1, 6),
-            Constraint::Ratio(1, 6),
-            Constraint::Ratio(1, 6),
-            Constraint::Ratio(1, 6),
-            Constraint::Ratio(1, 6),
-            Constraint::Ratio(1, 6),
-        ])
-        .split(center_vertical[0]);
-    
-    // Define columns for bottom row (P1) - cells 1 to 6
-    let p1_columns = Layout::default()
-        .direction(Direction::Horizontal)
-        .constraints([
-            Constraint::Ratio(1, 6),
-            Constraint::Ratio(1, 6),
-            Constraint::Ratio(1, 6),
-            Constraint::Ratio(1, 6),
-            Constraint::Ratio(1, 6),
-            Constraint::Ratio(1, 6),
-        ])
-        .split(center_vertical[1]);
+    let BoardLayout {
+        columns: main_horizontal,
+        top_row: p2_columns,
+        bottom_row: p1_columns,
+    } = board_layout(inner_area);
     
     // Render P2's cells (cells 12 to 7, reversed)
     for (i, rect) in p2_columns.iter().enumerate() {
         // Cell index (12 to 7, reversed)
         let cell_idx = 12 - i;
-        let house_idx = cell_idx as usize;
+        let house_idx = cell_idx;
         
         // Stone count
-        let stone_count = app.game_state.houses[house_idx];
+        let stone_count = state.houses[house_idx];
         
         // Create cell with block and cell number as title
+        let marked = mark_style(house_idx);
         let cell_block = Block::default()
             .borders(Borders::ALL)
             .style(cell_style)
+            .border_style(marked.unwrap_or_default())
             .title(format!("{}", cell_idx));
         
         f.render_widget(cell_block.clone(), *rect);
@@ -369,7 +1031,7 @@ fn draw_game_board(f: &mut Frame, app: &App, area: Rect) {
         
         // Add stone count centered in the cell
         let stone_text = Paragraph::new(stone_count.to_string())
-            .style(p2_cells_style)
+            .style(marked.unwrap_or(p2_cells_style))
             .alignment(ratatui::layout::Alignment::Center);
         
         // Center the stone count in the inner cell
@@ -390,12 +1052,14 @@ fn draw_game_board(f: &mut Frame, app: &App, area: Rect) {
         let house_idx = i;
         
         // Stone count
-        let stone_count = app.game_state.houses[house_idx];
+        let stone_count = state.houses[house_idx];
         
         // Create cell with cell number as title
+        let marked = mark_style(house_idx);
         let cell_block = Block::default()
             .borders(Borders::ALL)
             .style(cell_style)
+            .border_style(marked.unwrap_or_default())
             .title(format!("{}", cell_idx));
         
         f.render_widget(cell_block.clone(), *rect);
@@ -405,7 +1069,7 @@ fn draw_game_board(f: &mut Frame, app: &App, area: Rect) {
         
         // Add stone count centered in the cell
         let stone_text = Paragraph::new(stone_count.to_string())
-            .style(p1_cells_style)
+            .style(marked.unwrap_or(p1_cells_style))
             .alignment(ratatui::layout::Alignment::Center);
         
         // Center the stone count in the inner cell
@@ -423,6 +1087,7 @@ fn draw_game_board(f: &mut Frame, app: &App, area: Rect) {
     let p2_mancala_block = Block::default()
         .borders(Borders::ALL)
         .style(cell_style)
+        .border_style(mark_style(13).unwrap_or_default())
         .title("13");
     
     f.render_widget(p2_mancala_block.clone(), main_horizontal[0]);
@@ -430,8 +1095,8 @@ fn draw_game_board(f: &mut Frame, app: &App, area: Rect) {
     // Get inner area of the mancala
     let inner_p2_mancala = p2_mancala_block.inner(main_horizontal[0]);
     
-    // Add "P2" label at the top of the mancala
-    let p2_label = Paragraph::new("P2")
+    // Label the opponent's store at the top of the mancala
+    let p2_label = Paragraph::new(names.1)
         .style(cell_number_style)
         .alignment(ratatui::layout::Alignment::Center);
     
@@ -445,8 +1110,8 @@ fn draw_game_board(f: &mut Frame, app: &App, area: Rect) {
     f.render_widget(p2_label, p2_label_rect);
     
     // Add stone count to P2's Mancala
-    let p2_mancala_text = Paragraph::new(app.game_state.houses[13].to_string())
-        .style(mancala_style)
+    let p2_mancala_text = Paragraph::new(state.houses[13].to_string())
+        .style(mark_style(13).unwrap_or(mancala_style))
         .alignment(ratatui::layout::Alignment::Center);
     
     // Center the stone count vertically in the mancala
@@ -463,6 +1128,7 @@ fn draw_game_board(f: &mut Frame, app: &App, area: Rect) {
     let p1_mancala_block = Block::default()
         .borders(Borders::ALL)
         .style(cell_style)
+        .border_style(mark_style(6).unwrap_or_default())
         .title("6");
     
     f.render_widget(p1_mancala_block.clone(), main_horizontal[2]);
@@ -470,8 +1136,8 @@ fn draw_game_board(f: &mut Frame, app: &App, area: Rect) {
     // Get inner area of the mancala
     let inner_p1_mancala = p1_mancala_block.inner(main_horizontal[2]);
     
-    // Add "P1" label at the top of the mancala
-    let p1_label = Paragraph::new("P1")
+    // The board is always drawn from the side of the player to move, so their store is on the right
+    let p1_label = Paragraph::new(names.0)
         .style(cell_number_style)
         .alignment(ratatui::layout::Alignment::Center);
     
@@ -485,8 +1151,8 @@ fn draw_game_board(f: &mut Frame, app: &App, area: Rect) {
     f.render_widget(p1_label, p1_label_rect);
     
     // Add stone count to P1's Mancala
-    let p1_mancala_text = Paragraph::new(app.game_state.houses[6].to_string())
-        .style(mancala_style)
+    let p1_mancala_text = Paragraph::new(state.houses[6].to_string())
+        .style(mark_style(6).unwrap_or(mancala_style))
         .alignment(ratatui::layout::Alignment::Center);
     
     // Center the stone count vertically in the mancala
@@ -500,6 +1166,77 @@ fn draw_game_board(f: &mut Frame, app: &App, area: Rect) {
     f.render_widget(p1_mancala_text, p1_mancala_rect);
 }
 
+/// Where the parts of a board go inside its border.
+struct BoardLayout {
+    /// The stores either side of the houses: left, middle, right.
+    columns: Rc<[Rect]>,
+    /// The rows of houses, left to right.
+    top_row: Rc<[Rect]>,
+    bottom_row: Rc<[Rect]>,
+}
+
+fn board_layout(inner_area: Rect) -> BoardLayout {
+    // First, create main horizontal layout with left mancala, center board, right mancala
+    let main_horizontal = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([
+            Constraint::Ratio(1, 8),  // P2 Mancala (left)
+            Constraint::Ratio(6, 8),  // Center board
+            Constraint::Ratio(1, 8),  // P1 Mancala (right)
+        ])
+        .split(inner_area);
+    
+    // Define vertical layout for the center board (2 rows)
+    let center_vertical = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Ratio(1, 2),  // Top row (P2)
+            Constraint::Ratio(1, 2),  // Bottom row (P1)
+        ])
+        .split(main_horizontal[1]);
+    
+    // Define columns for top row (P2) - cells 12 to 7
+    let p2_columns = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([
+            Constraint::Ratio(1, 6),
+            Constraint::Ratio(1, 6),
+            Constraint::Ratio(1, 6),
+            Constraint::Ratio(1, 6),
+            Constraint::Ratio(1, 6),
+            Constraint::Ratio(1, 6),
+        ])
+        .split(center_vertical[0]);
+    
+    // Define columns for bottom row (P1) - cells 1 to 6
+    let p1_columns = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([
+            Constraint::Ratio(1, 6),
+            Constraint::Ratio(1, 6),
+            Constraint::Ratio(1, 6),
+            Constraint::Ratio(1, 6),
+            Constraint::Ratio(1, 6),
+            Constraint::Ratio(1, 6),
+        ])
+        .split(center_vertical[1]);
+    
+    BoardLayout {
+        columns: main_horizontal,
+        top_row: p2_columns,
+        bottom_row: p1_columns,
+    }
+}
+
+/// The house of the player at the bottom of a board drawn in `area` that is under a click at
+/// (`column`, `row`).
+fn house_at(area: Rect, column: u16, row: u16) -> Option<usize> {
+    let inner_area = Block::default().borders(Borders::ALL).inner(area);
+    board_layout(inner_area).bottom_row.iter().position(|rect| {
+        (rect.x..rect.x + rect.width).contains(&column) && (rect.y..rect.y + rect.height).contains(&row)
+    })
+}
+
 fn draw_move_analysis(f: &mut Frame, app: &App, area: Rect) {
     let selected_style = Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD);
     
@@ -512,7 +1249,10 @@ fn draw_move_analysis(f: &mut Frame, app: &App, area: Rect) {
     
     for (i, (action, _, value)) in app.possible_moves.iter().enumerate() {
         let diff = value - best_value;
-        let move_style = if i == 0 {
+        let move_style = if !app.is_selectable(i) {
+            // Does not go on from the cells sown so far
+            Style::default().fg(Color::DarkGray)
+        } else if i == 0 {
             Style::default().fg(Color::Green)
         } else if i == app.possible_moves.len() - 1 {
             Style::default().fg(Color::Red)
@@ -523,47 +1263,95 @@ fn draw_move_analysis(f: &mut Frame, app: &App, area: Rect) {
         };
         
         // Create a selection indicator in its own column
-        let selection_indicator = if app.move_table_state.selected().map_or(false, |selected| selected == i) {
+        let selection_indicator = if app.move_table_state.selected() == Some(i) {
             "▶"
         } else {
             " "
         };
         
-        // Create separate cells for each column
-        let cells = vec![
-            Span::styled(selection_indicator, Style::default().add_modifier(Modifier::BOLD)),
-            Span::styled(format!("{}", action), move_style),
-            Span::styled(format!("{:.6}", value), move_style),
-            Span::styled(format!("{:+.6}", diff), move_style),
-            Span::styled(format!("{}", i + 1), Style::default()),
-        ];
+        // Create separate cells for each column, leaving the numbers out while analysis is off
+        let cells = if let Some(search) = &app.search {
+            // What searching on from the move found, once it has
+            let (score, reply, then) = match search.line(*action) {
+                Some(found) => {
+                    let mut line = found.line.iter().map(|&action| format_move(action));
+                    (
+                        format_score(search.evaluation, found.score),
+                        line.next().unwrap_or_else(|| String::from("-")),
+                        line.collect::<Vec<_>>().join(" "),
+                    )
+                }
+                None => (String::from("..."), String::new(), String::new()),
+            };
+            vec![
+                Span::styled(selection_indicator, Style::default().add_modifier(Modifier::BOLD)),
+                Span::styled(format!("{}", action), move_style),
+                Span::styled(format!("{:.4}", value), move_style),
+                Span::styled(score, move_style),
+                Span::raw(reply),
+                Span::styled(then, Style::default().fg(Color::DarkGray)),
+            ]
+        } else if app.analysis_shown() {
+            vec![
+                Span::styled(selection_indicator, Style::default().add_modifier(Modifier::BOLD)),
+                Span::styled(format!("{}", action), move_style),
+                Span::styled(format!("{:.6}", value), move_style),
+                Span::styled(format!("{:+.6}", diff), move_style),
+                Span::styled(format!("{}", i + 1), Style::default()),
+            ]
+        } else {
+            vec![
+                Span::styled(selection_indicator, Style::default().add_modifier(Modifier::BOLD)),
+                Span::raw(format!("{}", action)),
+                Span::raw(""),
+                Span::raw(""),
+                Span::styled(format!("{}", i + 1), Style::default()),
+            ]
+        };
         
         // Create a row with individual cells for proper column separation
         rows.push(Row::new(cells));
     }
     
     // Create header cells with matching spans
-    let header_cells = vec![
-        Span::styled(" ", Style::default().fg(Color::White).add_modifier(Modifier::BOLD)),
-        Span::styled("Move", Style::default().fg(Color::White).add_modifier(Modifier::BOLD)),
-        Span::styled("Value", Style::default().fg(Color::White).add_modifier(Modifier::BOLD)),
-        Span::styled("Diff", Style::default().fg(Color::White).add_modifier(Modifier::BOLD)),
-        Span::styled("#", Style::default().fg(Color::White).add_modifier(Modifier::BOLD)),
-    ];
-    
-    let header = Row::new(header_cells);
-    
-    let widths = [
-        Constraint::Length(2),         // Selection indicator
-        Constraint::Length(20),        // Move - expanded to show multi-turn actions better
-        Constraint::Length(10),        // Expected Value (fixed width)
-        Constraint::Length(10),        // Diff from Best (fixed width)
-        Constraint::Length(3),         // Position
-    ];
+    let header_style = Style::default().fg(Color::White).add_modifier(Modifier::BOLD);
+    let (labels, widths) = if app.search.is_some() {
+        (
+            vec![" ", "Move", "Value", "Search", "Reply", "Then"],
+            vec![
+                Constraint::Length(2),   // Selection indicator
+                Constraint::Length(18),  // Move
+                Constraint::Length(8),   // Expected Value
+                Constraint::Length(13),  // Score from searching on
+                Constraint::Length(7),   // Expected reply
+                Constraint::Min(10),     // The rest of the line
+            ],
+        )
+    } else {
+        (
+            vec![" ", "Move", "Value", "Diff", "#"],
+            vec![
+                Constraint::Length(2),   // Selection indicator
+                Constraint::Length(20),  // Move - expanded to show multi-turn actions better
+                Constraint::Length(10),  // Expected Value (fixed width)
+                Constraint::Length(10),  // Diff from Best (fixed width)
+                Constraint::Length(3),   // Position
+            ],
+        )
+    };
+    let header = Row::new(labels.into_iter().map(|label| Span::styled(label, header_style)));
     
+    let title = match &app.search {
+        Some(search) if search.is_finished() => {
+            format!("Possible Moves (searched {} turns on)", search.depth())
+        }
+        Some(search) => format!("Possible Moves (searching, {} turns on so far)", search.depth()),
+        None if app.analysis_shown() => String::from("Possible Moves"),
+        None => String::from("Possible Moves (analysis off)"),
+    };
     let table = Table::new(rows, widths)
         .header(header)
-        .block(Block::default().borders(Borders::ALL).title("Possible Moves"))
+        .block(Block::default().borders(Borders::ALL).title(title))
         .highlight_style(selected_style);
     
     f.render_stateful_widget(table, area, &mut app.move_table_state.clone());
@@ -598,7 +1386,11 @@ fn draw_win_probability(f: &mut Frame, app: &App, area: Rect) {
     ];
     
     let chart = Chart::new(vec![dataset])
-        .block(Block::default().title("Win Probability").borders(Borders::ALL))
+        .block(Block::default().title(match app.opponent {
+            // The chart follows the first player throughout
+            Opponent::HotSeat => format!("Win Probability ({})", PLAYER_NAMES[0]),
+            _ => String::from("Win Probability"),
+        }).borders(Borders::ALL))
         .x_axis(
             Axis::default()
                 .title("Turn")
@@ -624,7 +1416,12 @@ fn draw_status_message(f: &mut Frame, app: &App, area: Rect) {
         Style::default().fg(Color::Yellow)
     };
     
-    let status = Span::styled(&app.status_message, status_style);
+    // What the move being sown is doing, until it is done
+    let message = match &app.animation {
+        Some(animation) => &animation.frame().caption,
+        None => &app.status_message,
+    };
+    let status = Span::styled(message, status_style);
     let paragraph = Paragraph::new(Line::from(vec![status]))
         .block(Block::default().borders(Borders::ALL).title("Status"));
     
@@ -632,18 +1429,46 @@ fn draw_status_message(f: &mut Frame, app: &App, area: Rect) {
 }
 
 fn draw_controls(f: &mut Frame, app: &App, area: Rect) {
+    if app.animation.is_some() {
+        let skip = vec![
+            Span::styled("s/Space", Style::default().fg(Color::Yellow)),
+            Span::raw(" Skip | "),
+            Span::styled("q", Style::default().fg(Color::Yellow)),
+            Span::raw(" Quit"),
+        ];
+        let paragraph = Paragraph::new(Line::from(skip))
+            .block(Block::default().borders(Borders::ALL).title("Controls"));
+        f.render_widget(paragraph, area);
+        return;
+    }
     let mut controls = vec![
         Span::styled("↑/↓", Style::default().fg(Color::Yellow)),
         Span::raw(" Select Move | "),
         Span::styled("Enter", Style::default().fg(Color::Yellow)),
         Span::raw(" Play Move | "),
+        Span::styled("1-6/Click", Style::default().fg(Color::Yellow)),
+        Span::raw(" Sow | "),
+        Span::styled("a", Style::default().fg(Color::Yellow)),
+        Span::raw(" Analysis On/Off | "),
+        Span::styled("d", Style::default().fg(Color::Yellow)),
+        Span::raw(" Search Ahead | "),
     ];
+    if app.can_rewind() {
+        controls.push(Span::styled("u/y", Style::default().fg(Color::Yellow)));
+        controls.push(Span::raw(" Undo/Redo | "));
+        controls.push(Span::styled("PgUp/PgDn", Style::default().fg(Color::Yellow)));
+        controls.push(Span::raw(" Browse | "));
+    }
     
     // Add restart control if game is over
-    if app.is_game_over() {
+    if app.is_game_over() && app.can_restart() {
         controls.push(Span::styled("r", Style::default().fg(Color::Green)));
         controls.push(Span::raw(" Restart | "));
     }
+    if app.can_restart() {
+        controls.push(Span::styled("n", Style::default().fg(Color::Yellow)));
+        controls.push(Span::raw(" New Game | "));
+    }
     
     controls.push(Span::styled("q", Style::default().fg(Color::Yellow)));
     controls.push(Span::raw(" Quit"));
@@ -654,30 +1479,58 @@ fn draw_controls(f: &mut Frame, app: &App, area: Rect) {
     f.render_widget(paragraph, area);
 }
 
-/// Terminal setup and handling
-pub fn run_tui(
-    starting_state: GameState,
-    value_fun: &HashMap<GameState, f64>,
-) -> Result<(), Box<dyn Error>> {
-    // Setup terminal
+/// Switch the terminal to raw mode on the alternate screen.
+pub fn setup_terminal() -> io::Result<Terminal<CrosstermBackend<Stdout>>> {
     enable_raw_mode()?;
     let mut stdout = io::stdout();
     execute!(stdout, EnterAlternateScreen, EnableMouseCapture)?;
-    let backend = ratatui::backend::CrosstermBackend::new(stdout);
-    let mut terminal = Terminal::new(backend)?;
-
-    // Create app and run it
-    let mut app = App::new(starting_state, value_fun);
-    let res = run_app(&mut terminal, &mut app);
+    Terminal::new(CrosstermBackend::new(stdout))
+}
 
-    // Restore terminal
+/// Put the terminal back the way `setup_terminal` found it.
+pub fn restore_terminal(terminal: &mut Terminal<CrosstermBackend<Stdout>>) -> io::Result<()> {
     disable_raw_mode()?;
     execute!(
         terminal.backend_mut(),
         LeaveAlternateScreen,
         DisableMouseCapture
     )?;
-    terminal.show_cursor()?;
+    terminal.show_cursor()
+}
+
+/// Terminal setup and handling. `loader` loads the value files picked on the setup screen.
+pub fn run_tui(
+    setup: GameSetup,
+    loader: &RulesLoader,
+    rng: StdRng,
+) -> Result<(), Box<dyn Error>> {
+    let mut terminal = setup_terminal()?;
+
+    // Create app and run it, starting on the setup screen
+    let mut app = App::with_setup_screen(setup, rng);
+    let res = run_app(&mut terminal, &mut app, Some(loader));
+
+    restore_terminal(&mut terminal)?;
+
+    if let Err(err) = res {
+        println!("{:?}", err);
+    }
+
+    Ok(())
+}
+
+/// Play a network game over `peer` in the TUI, with `value_fun` for the move analysis.
+pub fn run_network_tui(
+    peer: Peer,
+    value_fun: Box<dyn ValueTable>,
+    rng: StdRng,
+) -> Result<(), Box<dyn Error>> {
+    let mut terminal = setup_terminal()?;
+
+    let mut app = App::networked(peer, value_fun, rng);
+    let res = run_app(&mut terminal, &mut app, None);
+
+    restore_terminal(&mut terminal)?;
 
     if let Err(err) = res {
         println!("{:?}", err);
@@ -689,48 +1542,118 @@ pub fn run_tui(
 fn run_app<B: ratatui::backend::Backend>(
     terminal: &mut Terminal<B>,
     app: &mut App,
+    loader: Option<&RulesLoader>,
 ) -> io::Result<()> {
     loop {
+        app.poll_remote();
+        app.poll_search();
         terminal.draw(|f| draw(f, app))?;
 
+        if let Some(form) = &mut app.setup_form {
+            if event::poll(Duration::from_millis(200))?
+                && let Event::Key(key) = event::read()?
+            {
+                match key.code {
+                    KeyCode::Esc if form.cancellable => app.setup_form = None,
+                    KeyCode::Esc => return Ok(()),
+                    KeyCode::Up => form.up(),
+                    KeyCode::Down => form.down(),
+                    KeyCode::Left => form.change(-1),
+                    KeyCode::Right => form.change(1),
+                    KeyCode::Backspace => form.backspace(),
+                    KeyCode::Char(c) => form.type_char(c),
+                    KeyCode::Enter => {
+                        if let Some(loader) = loader {
+                            app.start_from_setup(loader);
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            continue;
+        }
+
+        // Let the move being sown play out before anything else happens
+        if app.animation.is_some() {
+            app.step_animation();
+            if event::poll(Duration::from_millis(20))?
+                && let Event::Key(key) = event::read()?
+            {
+                match key.code {
+                    KeyCode::Char('q') => return Ok(()),
+                    KeyCode::Char('s') | KeyCode::Char(' ') | KeyCode::Esc => app.skip_animation(),
+                    _ => {}
+                }
+            }
+            continue;
+        }
+
         if app.is_game_over() {
             // When game is over, display the result but keep the UI available
             // to review the final state until user quits or resets
-            if event::poll(Duration::from_millis(200))? {
-                if let Event::Key(key) = event::read()? {
-                    match key.code {
-                        KeyCode::Char('q') => return Ok(()),
-                        KeyCode::Char('r') => {
-                            app.reset_game();
-                            continue;
-                        },
-                        _ => {}
-                    }
+            if event::poll(Duration::from_millis(200))?
+                && let Event::Key(key) = event::read()?
+            {
+                match key.code {
+                    KeyCode::Char('q') => return Ok(()),
+                    KeyCode::Char('r') if app.can_restart() => {
+                        app.reset_game();
+                        continue;
+                    },
+                    KeyCode::Char('n') => app.open_setup(),
+                    // Looking back over the game, or taking back the last move
+                    KeyCode::Char('u') => app.undo(),
+                    KeyCode::PageUp | KeyCode::Char('[') => app.browse(-1),
+                    KeyCode::PageDown | KeyCode::Char(']') => app.browse(1),
+                    KeyCode::Esc => app.stop_browsing(),
+                    KeyCode::Enter => app.branch(),
+                    _ => {}
                 }
             }
             continue;
         }
 
-        // Check if it's AI's turn and trigger AI move
-        if !app.is_human_turn && !app.is_game_over() {
+        // Check if it's AI's turn and trigger AI move; a remote opponent's arrives in `poll_remote`
+        if !app.is_human_turn && matches!(app.opponent, Opponent::Ai(_)) {
             app.ai_turn();
             terminal.draw(|f| draw(f, app))?;
             continue;
         }
 
-        if event::poll(Duration::from_millis(200))? {
-            if let Event::Key(key) = event::read()? {
-                match key.code {
-                    KeyCode::Char('q') => {
-                        app.quit();
-                        return Ok(());
-                    }
-                    KeyCode::Char('r') => app.reset_game(),
-                    KeyCode::Up => app.previous(),
-                    KeyCode::Down => app.next(),
-                    KeyCode::Enter => app.make_selected_move(),
-                    _ => {}
+        if !event::poll(Duration::from_millis(200))? {
+            continue;
+        }
+        let event = event::read()?;
+        // A click on one of the player's own houses sows it
+        if let Event::Mouse(mouse) = event
+            && mouse.kind == MouseEventKind::Down(MouseButton::Left)
+            && let Some(house) = house_at(screen_layout(terminal.size()?)[0], mouse.column, mouse.row)
+        {
+            app.sow_house(house);
+        }
+        if let Event::Key(key) = event {
+            match key.code {
+                KeyCode::Char('q') => {
+                    app.quit();
+                    return Ok(());
                 }
+                KeyCode::Char('r') => app.reset_game(),
+                KeyCode::Char('n') => app.open_setup(),
+                KeyCode::Up => app.previous(),
+                KeyCode::Down => app.next(),
+                KeyCode::Enter if app.viewing.is_some() => app.branch(),
+                KeyCode::Enter => app.make_selected_move(),
+                KeyCode::Char('a') => app.toggle_analysis(),
+                KeyCode::Char('d') => app.toggle_deep_analysis(),
+                KeyCode::Char('u') => app.undo(),
+                KeyCode::Char('y') => app.redo(),
+                KeyCode::PageUp | KeyCode::Char('[') => app.browse(-1),
+                KeyCode::PageDown | KeyCode::Char(']') => app.browse(1),
+                KeyCode::Char(c @ '1'..='6') => app.sow_house(c as usize - '1' as usize),
+                KeyCode::Backspace => app.take_back_sow(),
+                KeyCode::Esc if app.partial_move.is_some() => app.cancel_sowing(),
+                KeyCode::Esc => app.stop_browsing(),
+                _ => {}
             }
         }
         
@@ -739,3 +1662,261 @@ fn run_app<B: ratatui::backend::Backend>(
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mancala::{Rules, ValueFunction};
+    use crate::setup::OpponentKind;
+    use rand::SeedableRng;
+
+    fn app(opponent: OpponentKind, human_first: bool) -> App {
+        let mut setup = GameSetup::new(PathBuf::new());
+        setup.opponent = opponent;
+        setup.human_first = human_first;
+        App::new(setup, Box::new(ValueFunction::new()), StdRng::seed_from_u64(0))
+    }
+
+    #[test]
+    fn test_hot_seat() {
+        let mut app = app(OpponentKind::HotSeat, true);
+        assert_eq!(app.side_names(), ("Player 1", "Player 2"));
+
+        // Player 1 plays a single sow; the board turns round for player 2
+        app.toggle_analysis();
+        let index = app
+            .possible_moves
+            .iter()
+            .position(|(action, _, _)| action.to_string() == "Cell 1")
+            .unwrap();
+        app.move_table_state.select(Some(index));
+        let played = app.possible_moves[index].1;
+        app.make_selected_move();
+        assert_eq!(app.seat, 1);
+        assert!(app.is_human_turn);
+        assert_eq!(app.side_names(), ("Player 2", "Player 1"));
+        let mut seen_by_player_2 = played;
+        seen_by_player_2.swap_board();
+        assert_eq!(app.game_state, seen_by_player_2);
+        // The history stays on player 1's side
+        assert_eq!(app.history.states[1], played);
+
+        // Player 2 still has the analysis on, with the moves in value order
+        assert!(app.analysis_shown() && !app.show_analysis[0]);
+        app.make_selected_move();
+        assert_eq!(app.seat, 0);
+        assert_eq!(app.history.states.len(), 3);
+    }
+
+    #[test]
+    fn test_animation() {
+        let mut app = app(OpponentKind::Greedy, false);
+        app.ai_turn();
+        // The game has moved on, while the board starts with the AI picking its seeds up
+        let animation = app.animation.as_ref().unwrap();
+        assert_eq!(animation.names, ("You", "AI"));
+        let frame = animation.frame();
+        assert_eq!(frame.marks.len(), 1);
+        assert!(frame.marks[0].0 > 6);
+        assert_ne!(frame.board, app.game_state);
+        let mut terminal = Terminal::new(ratatui::backend::TestBackend::new(100, 40)).unwrap();
+        terminal.draw(|f| draw(f, &app)).unwrap();
+        app.skip_animation();
+        assert!(app.animation.is_none());
+
+        // Off on the setup screen, moves go straight on the board
+        app.setup.animation = crate::animation::AnimationSpeed::Off;
+        app.make_selected_move();
+        assert!(app.animation.is_none());
+        assert_eq!(app.history.states.len(), 3);
+    }
+
+    #[test]
+    fn test_sow_house() {
+        let mut app = app(OpponentKind::Greedy, true);
+        app.setup.animation = crate::animation::AnimationSpeed::Off;
+        // Cell 3 ends in the store, so the turn goes on
+        app.sow_house(2);
+        let partial = app.partial_move.unwrap();
+        assert_eq!(format_move(partial.sown), "3");
+        assert_eq!(app.shown_board().0, partial.board);
+        assert!(app.is_human_turn);
+        assert_eq!(app.history.states.len(), 1);
+        // Only moves going on from cell 3 can be picked from the list now
+        for _ in 0..app.possible_moves.len() {
+            app.next();
+            let selected = app.move_table_state.selected().unwrap();
+            assert!(starts_with(app.possible_moves[selected].0, partial.sown));
+        }
+        let other = app
+            .possible_moves
+            .iter()
+            .position(|(action, _, _)| !starts_with(*action, partial.sown))
+            .unwrap();
+        app.move_table_state.select(Some(other));
+        app.make_selected_move();
+        assert!(app.is_human_turn);
+        assert!(app.partial_move.is_some());
+        assert_eq!(app.history.states.len(), 1);
+        // It is empty now
+        app.sow_house(2);
+        assert!(app.status_message.contains("cannot"));
+        app.take_back_sow();
+        assert!(app.partial_move.is_none());
+
+        app.sow_house(2);
+        app.sow_house(0);
+        assert!(app.partial_move.is_none());
+        assert!(!app.is_human_turn);
+        assert_eq!(format_move(app.history.actions()[0]), "3-1");
+        let mut played = GameState::new(4);
+        played.evaluate_action(app.history.actions()[0]);
+        assert_eq!(app.game_state, played);
+    }
+
+    #[test]
+    fn test_deep_analysis() {
+        let mut app = app(OpponentKind::Greedy, true);
+        app.toggle_deep_analysis();
+        // With an empty table the search counts seeds
+        assert_eq!(app.search.as_ref().unwrap().evaluation, Evaluation::Margin);
+        let action = app.possible_moves[0].0;
+        let start = std::time::Instant::now();
+        while app.search.as_ref().unwrap().line(action).is_none()
+            && start.elapsed() < Duration::from_secs(5)
+        {
+            app.poll_search();
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        assert!(!app.search.as_ref().unwrap().line(action).unwrap().line.is_empty());
+        let mut terminal = Terminal::new(ratatui::backend::TestBackend::new(100, 40)).unwrap();
+        terminal.draw(|f| draw(f, &app)).unwrap();
+
+        // A new position gets a new search, and none while the analysis is hidden
+        app.make_selected_move();
+        app.ai_turn();
+        assert!(app.search.as_ref().unwrap().line(action).is_none());
+        app.toggle_analysis();
+        assert!(app.search.is_none());
+    }
+
+    #[test]
+    fn test_house_at() {
+        let board = screen_layout(Rect::new(0, 0, 100, 40))[0];
+        // The bottom row of houses, a sixth of the middle of the board each
+        let bottom = board.y + board.height - 3;
+        assert_eq!(house_at(board, board.x + board.width / 2 - 2, bottom), Some(2));
+        assert_eq!(house_at(board, board.x + board.width / 2 + 2, bottom), Some(3));
+        // The top row and the stores are not the player's to sow
+        assert_eq!(house_at(board, board.x + board.width / 2, board.y + 2), None);
+        assert_eq!(house_at(board, board.x + 2, bottom), None);
+    }
+
+    #[test]
+    fn test_new_game_setup() {
+        let mut app = app(OpponentKind::Greedy, false);
+        // The opponent moves first
+        assert!(!app.is_human_turn);
+        app.ai_turn();
+        assert!(app.is_human_turn);
+        assert_eq!(app.history.states.len(), 2);
+
+        app.open_setup();
+        let form = app.setup_form.as_mut().unwrap();
+        form.setup.rules = Rules::Kalah { seeds: 3 };
+        form.setup.human_first = true;
+        let missing = |_: &std::path::Path, _| -> io::Result<Box<dyn ValueTable>> {
+            Err(io::Error::new(io::ErrorKind::NotFound, "no such file"))
+        };
+        // Only the table opponent cannot do without its file
+        form.setup.opponent = OpponentKind::Table;
+        app.start_from_setup(&missing);
+        assert!(app.setup_form.as_ref().unwrap().error.is_some());
+        app.setup_form.as_mut().unwrap().setup.opponent = OpponentKind::AlphaBeta;
+        app.start_from_setup(&missing);
+        assert!(app.setup_form.is_none());
+        assert_eq!(app.game_state, GameState::new(3));
+        assert!(app.is_human_turn);
+        assert!(app.status_message.starts_with("No move analysis"));
+
+        let mut terminal = Terminal::new(ratatui::backend::TestBackend::new(100, 40)).unwrap();
+        terminal.draw(|f| draw(f, &app)).unwrap();
+        app.open_setup();
+        terminal.draw(|f| draw(f, &app)).unwrap();
+    }
+
+    /// Play the first of the moves on offer.
+    fn play_first(app: &mut App) {
+        app.move_table_state.select(Some(0));
+        app.make_selected_move();
+        app.ai_turn();
+    }
+
+    #[test]
+    fn test_undo_redo() {
+        let mut app = app(OpponentKind::Greedy, true);
+        play_first(&mut app);
+        play_first(&mut app);
+        let after_two = app.game_state;
+        assert_eq!(app.history.current(), 4);
+
+        // Taking back goes to the human's last turn, with the AI's reply
+        app.undo();
+        assert_eq!(app.history.current(), 2);
+        assert!(app.is_human_turn);
+        assert_eq!(app.game_state, app.history.states()[2]);
+        app.undo();
+        app.undo();
+        assert_eq!(app.history.current(), 0);
+        assert_eq!(app.game_state, GameState::new(4));
+
+        app.redo();
+        app.redo();
+        assert_eq!(app.game_state, after_two);
+        assert_eq!(app.history.get_data_points().len(), 5);
+
+        // A new move from an earlier position drops the old line
+        app.undo();
+        app.move_table_state.select(Some(app.possible_moves.len() - 1));
+        app.make_selected_move();
+        app.ai_turn();
+        assert_eq!(app.history.states().len(), 5);
+        app.redo();
+        assert_eq!(app.history.current(), 4);
+    }
+
+    #[test]
+    fn test_browse_and_branch() {
+        let mut app = app(OpponentKind::HotSeat, true);
+        play_first(&mut app);
+        play_first(&mut app);
+        assert_eq!(app.seat, 0);
+
+        // Looking back shows the board as player 2 saw it, without touching the game
+        app.browse(-1);
+        assert_eq!(app.viewing, Some(1));
+        let (board, names) = app.shown_board();
+        assert_eq!(names, ("Player 2", "Player 1"));
+        assert_eq!(board, app.position_at(1));
+        assert_eq!(app.possible_moves[0].1, {
+            let mut after = board;
+            after.evaluate_action(app.possible_moves[0].0);
+            after
+        });
+        app.make_selected_move();
+        assert_eq!(app.history.current(), 2);
+        app.browse(1);
+        assert_eq!(app.viewing, None);
+
+        // Playing on from an earlier position hands the move to whoever had it
+        app.browse(-1);
+        app.branch();
+        assert_eq!((app.seat, app.viewing, app.history.current()), (1, None, 1));
+        app.undo();
+        assert_eq!((app.seat, app.history.current()), (0, 0));
+
+        let mut terminal = Terminal::new(ratatui::backend::TestBackend::new(100, 40)).unwrap();
+        app.browse(2);
+        terminal.draw(|f| draw(f, &app)).unwrap();
+    }
+}